@@ -0,0 +1,343 @@
+// Threat assessment for hostile creeps.
+// Each tick every room's hostiles are classified once, counting their active parts and what their
+// boosts multiply them by, so towers, spawning and safe mode all work from the same picture of
+// how dangerous a room is instead of each guessing separately.
+pub use safe_mode::SafeMode;
+
+mod safe_mode;
+
+use crate::tower_manager::net_damage;
+use crate::world::{BodyPartInfo, HostileInfo, TowerView, World};
+use log::{debug, warn};
+use screeps::constants::{
+    Boost, ATTACK_POWER, DISMANTLE_POWER, HEAL_POWER, INVADER_USERNAME, RANGED_ATTACK_POWER,
+    RANGED_HEAL_POWER, SOURCE_KEEPER_USERNAME,
+};
+use screeps::{Part, Position, RoomName};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Who a hostile belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostileOwner {
+    Invader,
+    SourceKeeper,
+    Player,
+}
+
+impl HostileOwner {
+    fn from_username(username: &str) -> Self {
+        match username {
+            INVADER_USERNAME => HostileOwner::Invader,
+            SOURCE_KEEPER_USERNAME => HostileOwner::SourceKeeper,
+            _ => HostileOwner::Player,
+        }
+    }
+
+    pub fn is_npc(&self) -> bool {
+        !matches!(self, HostileOwner::Player)
+    }
+}
+
+/// How dangerous a room currently is, higher is worse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThreatLevel {
+    // No hostiles
+    #[default]
+    None,
+    // Hostiles that can't damage anything, e.g. scouts
+    Low,
+    // NPCs that can do damage
+    Medium,
+    // Another player's creeps that can do damage
+    High,
+}
+
+/// Number of active (not destroyed) parts of each type that matter for combat
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartCounts {
+    pub attack: u32,
+    pub ranged_attack: u32,
+    pub heal: u32,
+    pub work: u32,
+    pub tough: u32,
+}
+
+/// A single hostile creep and what it can do, with boosts taken into account
+#[derive(Debug, Clone)]
+pub struct HostileThreat {
+    pub info: HostileInfo,
+    pub owner: HostileOwner,
+    pub parts: PartCounts,
+    // Damage per tick to creeps, from ATTACK and RANGED_ATTACK parts
+    pub dps: u32,
+    // Damage per tick to structures from dismantling with WORK parts
+    pub dismantle: u32,
+    // Healing per tick at range 1, and at range 2 to 3
+    pub hps: u32,
+    pub ranged_hps: u32,
+}
+
+impl HostileThreat {
+    pub fn new(info: HostileInfo) -> Self {
+        let mut parts = PartCounts::default();
+        let (mut dps, mut dismantle, mut hps, mut ranged_hps) = (0.0, 0.0, 0.0, 0.0);
+        for part in info.body.iter().filter(|part| part.hits > 0) {
+            let multiplier = boost_multiplier(part);
+            match part.part {
+                Part::Attack => {
+                    parts.attack += 1;
+                    dps += ATTACK_POWER as f32 * multiplier;
+                }
+                Part::RangedAttack => {
+                    parts.ranged_attack += 1;
+                    dps += RANGED_ATTACK_POWER as f32 * multiplier;
+                }
+                Part::Heal => {
+                    parts.heal += 1;
+                    hps += HEAL_POWER as f32 * multiplier;
+                    ranged_hps += RANGED_HEAL_POWER as f32 * multiplier;
+                }
+                Part::Work => {
+                    parts.work += 1;
+                    dismantle += DISMANTLE_POWER as f32 * multiplier;
+                }
+                Part::Tough => parts.tough += 1,
+                _ => {}
+            }
+        }
+        HostileThreat {
+            owner: HostileOwner::from_username(&info.owner),
+            info,
+            parts,
+            dps: dps as u32,
+            dismantle: dismantle as u32,
+            hps: hps as u32,
+            ranged_hps: ranged_hps as u32,
+        }
+    }
+
+    /// Whether the creep can damage our creeps or structures
+    pub fn is_dangerous(&self) -> bool {
+        self.dps > 0 || self.dismantle > 0
+    }
+
+    pub fn pos(&self) -> Position {
+        self.info.pos
+    }
+
+    /// Hits the creep would actually lose from the given damage. Boosted TOUGH parts take a
+    /// fraction of the damage dealt to them, and parts take damage front to back
+    pub fn damage_taken(&self, damage: u32) -> u32 {
+        let mut remaining = damage as f32;
+        let mut taken = 0.0;
+        for part in self.info.body.iter().filter(|part| part.hits > 0) {
+            let multiplier = match part.part {
+                Part::Tough => boost_multiplier(part),
+                _ => 1.0,
+            };
+            let absorbed = (part.hits as f32 / multiplier).min(remaining);
+            taken += absorbed * multiplier;
+            remaining -= absorbed;
+            if remaining <= 0.0 {
+                break;
+            }
+        }
+        (taken + remaining) as u32
+    }
+}
+
+/// Everything hostile in one of our rooms
+#[derive(Debug, Clone, Default)]
+pub struct RoomThreat {
+    pub hostiles: Vec<HostileThreat>,
+    // Totals across all hostiles in the room
+    pub dps: u32,
+    pub dismantle: u32,
+    pub hps: u32,
+    pub level: ThreatLevel,
+}
+
+impl RoomThreat {
+    pub fn new(hostiles: Vec<HostileInfo>) -> Self {
+        let hostiles: Vec<HostileThreat> = hostiles.into_iter().map(HostileThreat::new).collect();
+        let level = hostiles
+            .iter()
+            .map(
+                |hostile| match (hostile.is_dangerous(), hostile.owner.is_npc()) {
+                    (false, _) => ThreatLevel::Low,
+                    (true, true) => ThreatLevel::Medium,
+                    (true, false) => ThreatLevel::High,
+                },
+            )
+            .max()
+            .unwrap_or_default();
+        RoomThreat {
+            dps: hostiles.iter().map(|hostile| hostile.dps).sum(),
+            dismantle: hostiles.iter().map(|hostile| hostile.dismantle).sum(),
+            hps: hostiles.iter().map(|hostile| hostile.hps).sum(),
+            level,
+            hostiles,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hostiles.is_empty()
+    }
+
+    /// Whether there are hostiles that can do damage which the towers can't kill, because
+    /// they heal faster than the towers can hurt them
+    pub fn exceeds_towers(&self, towers: &[Rc<dyn TowerView + '_>]) -> bool {
+        self.hostiles
+            .iter()
+            .filter(|hostile| hostile.is_dangerous())
+            .any(|hostile| net_damage(towers, hostile, self) <= 0)
+    }
+
+    /// Most the hostiles could heal a creep at the position in a tick
+    pub fn healing_at(&self, pos: Position) -> u32 {
+        self.hostiles
+            .iter()
+            .map(|healer| match healer.pos().get_range_to(pos) {
+                0..=1 => healer.hps,
+                2..=3 => healer.ranged_hps,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+
+/// Assess the threat in every room we can see
+pub fn assess(world: &dyn World) -> HashMap<RoomName, RoomThreat> {
+    world
+        .rooms()
+        .iter()
+        .map(|room| {
+            let threat = RoomThreat::new(room.hostile_creeps());
+            log_threat(room.name(), &threat);
+            (room.name(), threat)
+        })
+        .collect()
+}
+
+fn log_threat(room: RoomName, threat: &RoomThreat) {
+    if threat.level < ThreatLevel::Medium {
+        return;
+    }
+    warn!(
+        "{} hostiles in {}, {:?} threat: {} dps, {} hps, {} dismantle",
+        threat.hostiles.len(),
+        room,
+        threat.level,
+        threat.dps,
+        threat.hps,
+        threat.dismantle
+    );
+    for hostile in threat.hostiles.iter() {
+        let parts = hostile.parts;
+        debug!(
+            "-> {:?} creep of {}: {} attack, {} ranged attack, {} heal, {} work, {} tough",
+            hostile.owner,
+            hostile.info.owner,
+            parts.attack,
+            parts.ranged_attack,
+            parts.heal,
+            parts.work,
+            parts.tough
+        );
+    }
+}
+
+/// How much the part's boost multiplies what it does. For TOUGH this is the fraction of damage
+/// the part takes
+fn boost_multiplier(part: &BodyPartInfo) -> f32 {
+    match part.boost.and_then(|boost| boost.boost()) {
+        Some(Boost::Attack(multiplier))
+        | Some(Boost::RangedAttack(multiplier))
+        | Some(Boost::Heal(multiplier))
+        | Some(Boost::Dismantle(multiplier)) => multiplier as f32,
+        Some(Boost::Tough(multiplier)) => multiplier,
+        _ => 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+    use screeps::{ResourceType, INVADER_USERNAME};
+
+    fn hostile(owner: &str, body: &[(Part, Option<ResourceType>)]) -> HostileInfo {
+        HostileInfo {
+            id: MockWorld::new().next_id().into(),
+            pos: position(25, 25, test_room()),
+            owner: owner.to_string(),
+            hits: body.len() as u32 * 100,
+            hits_max: body.len() as u32 * 100,
+            body: body
+                .iter()
+                .map(|&(part, boost)| BodyPartInfo {
+                    part,
+                    hits: 100,
+                    boost,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn boosts_multiply_what_parts_do() {
+        let mut info = hostile(
+            "player",
+            &[
+                (Part::Attack, Some(ResourceType::UtriumHydride)),
+                (Part::Attack, None),
+                (Part::Heal, Some(ResourceType::LemergiumOxide)),
+                (Part::Work, None),
+            ],
+        );
+        // Destroyed parts don't count
+        info.body[3].hits = 0;
+
+        let threat = HostileThreat::new(info);
+        assert_eq!(threat.dps, ATTACK_POWER * 3);
+        assert_eq!(threat.hps, HEAL_POWER * 2);
+        assert_eq!(threat.ranged_hps, RANGED_HEAL_POWER * 2);
+        assert_eq!(threat.dismantle, 0);
+        assert_eq!(threat.parts.attack, 2);
+        assert_eq!(threat.parts.work, 0);
+    }
+
+    #[test]
+    fn boosted_tough_parts_soak_up_damage() {
+        let boosted = HostileThreat::new(hostile(
+            "player",
+            &[
+                (Part::Tough, Some(ResourceType::CatalyzedGhodiumAlkalide)),
+                (Part::Move, None),
+            ],
+        ));
+        assert_eq!(boosted.damage_taken(60), 18);
+        // Once the TOUGH part is gone the rest of the damage is taken in full
+        assert_eq!(boosted.damage_taken(400), 166);
+
+        let unboosted = HostileThreat::new(hostile("player", &[(Part::Tough, None)]));
+        assert_eq!(unboosted.damage_taken(60), 60);
+    }
+
+    #[test]
+    fn players_that_can_do_damage_are_the_highest_threat() {
+        let scout = || hostile("player", &[(Part::Move, None)]);
+        let invader = || hostile(INVADER_USERNAME, &[(Part::RangedAttack, None)]);
+        let attacker = || hostile("player", &[(Part::Attack, None), (Part::Heal, None)]);
+
+        assert_eq!(RoomThreat::new(vec![]).level, ThreatLevel::None);
+        assert_eq!(RoomThreat::new(vec![scout()]).level, ThreatLevel::Low);
+        assert_eq!(RoomThreat::new(vec![scout(), invader()]).level, ThreatLevel::Medium);
+
+        let threat = RoomThreat::new(vec![invader(), attacker(), scout()]);
+        assert_eq!(threat.level, ThreatLevel::High);
+        assert_eq!(threat.dps, RANGED_ATTACK_POWER + ATTACK_POWER);
+        assert_eq!(threat.hps, HEAL_POWER);
+    }
+}
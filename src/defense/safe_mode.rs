@@ -0,0 +1,247 @@
+// Activates safe mode when a room is about to be lost and nobody is around to do it by hand.
+// Safe mode fires when another player's creeps that can damage structures are inside our walls and
+// neither the towers nor our defenders can out-damage their healing, or when the spawn or anything
+// next to the controller is hit. NPC invaders on their own are left to the towers and defenders.
+use super::RoomThreat;
+use crate::terrain::{self, UNREACHABLE};
+use crate::tower_manager::{loaded_towers, net_damage};
+use crate::world::{ControllerInfo, RoomView, StructureInfo, World};
+use log::warn;
+use screeps::constants::{ATTACK_POWER, RANGED_ATTACK_POWER};
+use screeps::{Part, RawObjectId, RoomName, RoomXY, StructureType};
+use std::collections::{HashMap, HashSet};
+
+// Without a sealed wall around the base, hostiles this close to a spawn count as inside it
+const CORE_RANGE: u8 = 5;
+
+/// Remembers what the structures we guard looked like last tick, so we can tell when they are
+/// being damaged
+#[derive(Default)]
+pub struct SafeMode {
+    last_hits: HashMap<RawObjectId, u32>,
+}
+
+impl SafeMode {
+    pub fn new() -> Self {
+        SafeMode::default()
+    }
+
+    /// Check every room we own and activate safe mode in the first one that needs it
+    pub fn run(&mut self, world: &dyn World, threats: &HashMap<RoomName, RoomThreat>) {
+        // Only one room can be in safe mode at a time
+        let mut active = world
+            .rooms()
+            .iter()
+            .filter_map(|room| room.controller())
+            .any(|controller| controller.my && controller.safe_mode.is_some());
+
+        let mut last_hits = HashMap::new();
+        for room in world.rooms() {
+            let Some(controller) = room.controller().filter(|controller| controller.my) else {
+                continue;
+            };
+            let guarded = guarded_structures(room.as_ref(), &controller);
+            let reason = threats
+                .get(&room.name())
+                .filter(|threat| !threat.is_empty())
+                .and_then(|threat| self.find_reason(world, room.as_ref(), &guarded, threat));
+            last_hits.extend(guarded.iter().map(|s| (s.id, s.hits)));
+
+            let Some(reason) = reason else {
+                continue;
+            };
+            if active || !can_activate(&controller) {
+                continue;
+            }
+            match room.activate_safe_mode() {
+                Ok(()) => {
+                    let message = format!("Activated safe mode in {}: {}", room.name(), reason);
+                    warn!("{}", message);
+                    world.notify(&message);
+                    active = true;
+                }
+                Err(err) => warn!("Couldn't activate safe mode in {}: {:?}", room.name(), err),
+            }
+        }
+        self.last_hits = last_hits;
+    }
+
+    /// Why the room needs safe mode, if it does
+    fn find_reason(
+        &self,
+        world: &dyn World,
+        room: &dyn RoomView,
+        guarded: &[StructureInfo],
+        threat: &RoomThreat,
+    ) -> Option<String> {
+        let damaged = guarded.iter().find(|s| {
+            self.last_hits
+                .get(&s.id)
+                .is_some_and(|last_hits| s.hits < *last_hits)
+        });
+        if let Some(damaged) = damaged {
+            return Some(format!(
+                "{:?} at {} is being damaged",
+                damaged.structure_type, damaged.pos
+            ));
+        }
+
+        let towers = loaded_towers(room);
+        let mut dangerous = threat
+            .hostiles
+            .iter()
+            .filter(|hostile| hostile.is_dangerous() && !hostile.owner.is_npc())
+            .peekable();
+        dangerous.peek()?;
+        let interior = interior(room);
+        let defense = defender_damage(world, room.name());
+        let breach = dangerous.find(|hostile| {
+            let damage =
+                net_damage(&towers, hostile, threat) + hostile.damage_taken(defense) as i32;
+            interior.contains(&hostile.pos().xy()) && damage <= 0
+        })?;
+        Some(format!(
+            "creeps of {} are inside the base and we can't out-damage their healing",
+            breach.info.owner
+        ))
+    }
+}
+
+/// Whether the controller has safe mode ready to use
+fn can_activate(controller: &ControllerInfo) -> bool {
+    controller.safe_mode.is_none()
+        && controller.safe_mode_cooldown.is_none()
+        && controller.safe_mode_available > 0
+}
+
+/// Damage our creeps in the room could deal in a tick if they all fought
+fn defender_damage(world: &dyn World, room: RoomName) -> u32 {
+    world
+        .creeps_in_room(room)
+        .iter()
+        .flat_map(|creep| creep.body())
+        .map(|part| match part {
+            Part::Attack => ATTACK_POWER,
+            Part::RangedAttack => RANGED_ATTACK_POWER,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Spawns and anything next to the controller, losing any of these loses the room.
+/// Structures that decay are left out, since losing hits doesn't mean they're under attack
+fn guarded_structures(room: &dyn RoomView, controller: &ControllerInfo) -> Vec<StructureInfo> {
+    room.structures()
+        .into_iter()
+        .filter(|s| match s.structure_type {
+            StructureType::Spawn => true,
+            StructureType::Controller
+            | StructureType::Road
+            | StructureType::Container
+            | StructureType::Rampart => false,
+            _ => s.pos.get_range_to(controller.pos) <= 1,
+        })
+        .collect()
+}
+
+/// Tiles inside our walls: everything reachable from a spawn without crossing a wall or rampart.
+/// If that reaches an exit the walls aren't sealed, so only the tiles around the spawns count
+fn interior(room: &dyn RoomView) -> HashSet<RoomXY> {
+    let spawns: Vec<RoomXY> = room
+        .structures()
+        .iter()
+        .filter(|s| s.structure_type == StructureType::Spawn)
+        .map(|s| s.pos.xy())
+        .collect();
+    let barriers: HashSet<RoomXY> = room
+        .structures()
+        .iter()
+        .filter(|s| {
+            matches!(
+                s.structure_type,
+                StructureType::Wall | StructureType::Rampart
+            )
+        })
+        .map(|s| s.pos.xy())
+        .collect();
+    let analysis = terrain::analyse(room);
+
+    let reached = terrain::flood_fill(&analysis.terrain, &spawns, |xy| !barriers.contains(&xy));
+    let inside = |xy: RoomXY| reached[xy] != UNREACHABLE;
+    if terrain::exits(&analysis.terrain).any(inside) {
+        return terrain::all_tiles()
+            .filter(|xy| {
+                spawns
+                    .iter()
+                    .any(|spawn| spawn.get_range_to(*xy) <= CORE_RANGE)
+            })
+            .collect();
+    }
+    terrain::all_tiles().filter(|xy| inside(*xy)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, terrain_from_rows, test_room, MockWorld};
+    use screeps::INVADER_USERNAME;
+
+    /// A room with no walls, and exits all the way round
+    fn world_with_spawn() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room()).terrain = terrain_from_rows(&[]);
+        world.add_controller(position(25, 10, test_room()), 3);
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world
+    }
+
+    fn find_reason(safe_mode: &SafeMode, world: &MockWorld) -> Option<String> {
+        let room = world.room(test_room()).unwrap();
+        let guarded = guarded_structures(room.as_ref(), &room.controller().unwrap());
+        let threat = RoomThreat::new(room.hostile_creeps());
+        safe_mode.find_reason(world, room.as_ref(), &guarded, &threat)
+    }
+
+    #[test]
+    fn players_inside_the_base_trigger_safe_mode() {
+        let mut world = world_with_spawn();
+        world.add_hostile("player", position(27, 27, test_room()), vec![Part::Work, Part::Move]);
+
+        assert!(find_reason(&SafeMode::new(), &world).is_some());
+    }
+
+    #[test]
+    fn hostiles_we_can_handle_do_not_trigger_safe_mode() {
+        let mut world = world_with_spawn();
+        // Outside the core of an unwalled base
+        world.add_hostile("player", position(40, 40, test_room()), vec![Part::Attack]);
+        // Invaders are left to the towers and defenders
+        world.add_hostile(INVADER_USERNAME, position(26, 26, test_room()), vec![Part::Attack]);
+        assert!(find_reason(&SafeMode::new(), &world).is_none());
+
+        world.add_hostile("player", position(27, 27, test_room()), vec![Part::Attack]);
+        assert!(find_reason(&SafeMode::new(), &world).is_some());
+        world.add_creep(
+            "defender",
+            position(28, 28, test_room()),
+            vec![Part::Attack, Part::Move],
+            CreepMemory::new(Specialisation::Defender),
+        );
+        assert!(find_reason(&SafeMode::new(), &world).is_none());
+    }
+
+    #[test]
+    fn damage_to_the_spawn_triggers_safe_mode() {
+        let mut world = world_with_spawn();
+        world.add_hostile(INVADER_USERNAME, position(26, 26, test_room()), vec![Part::Attack]);
+        let spawn = world.room(test_room()).unwrap().structures()[1].clone();
+        let mut safe_mode = SafeMode::new();
+        safe_mode.last_hits.insert(spawn.id, spawn.hits);
+        assert!(find_reason(&safe_mode, &world).is_none());
+
+        safe_mode.last_hits.insert(spawn.id, spawn.hits + 30);
+        assert!(find_reason(&safe_mode, &world).is_some());
+    }
+}
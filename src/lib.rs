@@ -6,19 +6,41 @@ use screeps::game;
 use std::{cell::RefCell, collections::HashSet};
 use wasm_bindgen::prelude::*;
 
+mod defense;
+mod link_manager;
 mod logging;
+mod movement;
+mod planner;
+mod reservations;
 mod screep_states;
+#[cfg(test)]
+mod sim;
+mod spawn_queue;
 mod state_controllers;
+mod terrain;
 mod tower_manager;
 mod utils;
+mod world;
 
+use crate::defense::SafeMode;
+use crate::link_manager::LinkManager;
+use crate::planner::Planner;
 use crate::state_controllers::SCManager;
-use tower_manager::TowerManager;
+use tower_manager::{RepairPolicy, TowerManager};
+use world::LiveWorld;
 
 // this is one way to persist data between ticks within Rust's memory, as opposed to
 // keeping state in memory on game objects - but will be lost on global resets!
+// Each creep's current state is also written to its memory, so SCManager rebuilds it after a reset
 thread_local! {
-    static STATE_MANAGER: RefCell<SCManager> = RefCell::new(SCManager::new());
+    static STATE_MANAGER: RefCell<SCManager> = RefCell::new(SCManager::new(repair_policy()));
+    static SAFE_MODE: RefCell<SafeMode> = RefCell::new(SafeMode::new());
+    static PLANNER: RefCell<Planner> = RefCell::new(Planner::new());
+}
+
+/// What towers and creeps repair, and how far. Both are given the same policy so they agree
+fn repair_policy() -> RepairPolicy {
+    RepairPolicy::default()
 }
 
 static INIT_LOGGING: std::sync::Once = std::sync::Once::new();
@@ -35,18 +57,30 @@ pub fn game_loop() {
     });
 
     // debug!("loop starting! CPU: {}", game::cpu::get_used());
+    let world = LiveWorld;
+    let threats = defense::assess(&world);
     STATE_MANAGER.with(|state_manager_refcell| {
         let mut state_manager = state_manager_refcell.borrow_mut();
         // run the tick for all state controllers
-        state_manager.run();
+        state_manager.run(&world, &threats);
     });
+    // Move creeps once every state has said where it wants to go
+    movement::resolve_traffic(&world);
+
+    SAFE_MODE.with(|safe_mode| safe_mode.borrow_mut().run(&world, &threats));
 
     // Run all towers to repair some shit
-    TowerManager::new().run_all_towers();
+    TowerManager::new(repair_policy()).run_all_towers(&world, &threats);
+
+    // Send mined energy through the links to where it is used
+    LinkManager::new().run_all_links(&world);
+
+    // Place construction sites for whatever the controller level has unlocked
+    PLANNER.with(|planner| planner.borrow_mut().run(&world));
 
     // memory cleanup; memory gets created for all creeps upon spawning, and any time move_to
     // is used; this should be removed if you're using RawMemory/serde for persistence
-    if game::time() % 1000 == 0 {
+    if game::time().is_multiple_of(1000) {
         info!("running memory cleanup");
         let mut alive_creeps = HashSet::new();
         // add all living creep names to a hashset
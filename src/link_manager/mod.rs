@@ -0,0 +1,211 @@
+// Runs each room's links as one network.
+// A link's role is worked out from what it sits next to: a source, the controller, the storage or
+// a spawn. Miners fill the source links, and once a source link is off cooldown it sends its
+// energy on, to the controller link first while that is running low so upgraders never wait,
+// otherwise to the storage link for haulers to put away.
+use crate::world::{LinkView, RoomView, StructureInfo, World};
+use log::warn;
+use screeps::constants::LINK_CAPACITY;
+use screeps::{ObjectId, Position, ResourceType, StructureLink, StructureType};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// How close a link has to be to what it serves. The planner puts source and controller links
+// next to their container, which is itself within a tile or two of the source or controller
+const SOURCE_RANGE: u32 = 2;
+const CONTROLLER_RANGE: u32 = 3;
+const STORAGE_RANGE: u32 = 2;
+const SPAWN_RANGE: u32 = 2;
+// Every send puts the link on cooldown, so source links wait until they have this much
+const SEND_THRESHOLD: u32 = LINK_CAPACITY / 2;
+// The controller link is topped up before anything goes to storage while it has less than this
+const CONTROLLER_LOW: u32 = LINK_CAPACITY / 2;
+
+/// What a link is for, worked out from where it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkRole {
+    Source,
+    Controller,
+    Storage,
+    Spawn,
+}
+
+/// Where everything links serve is in a room, read from one snapshot of its structures so a
+/// whole room's links can be classified without looking them up again for each one
+pub struct LinkAnchors {
+    controller: Option<Position>,
+    storage: Vec<Position>,
+    sources: Vec<Position>,
+    spawns: Vec<Position>,
+}
+
+impl LinkAnchors {
+    pub fn new(room: &dyn RoomView, structures: &[StructureInfo]) -> Self {
+        let positions = |structure_type: StructureType| {
+            structures
+                .iter()
+                .filter(|s| s.structure_type == structure_type)
+                .map(|s| s.pos)
+                .collect()
+        };
+        LinkAnchors {
+            controller: room.controller().map(|controller| controller.pos),
+            storage: positions(StructureType::Storage),
+            sources: room.sources().iter().map(|source| source.pos).collect(),
+            spawns: positions(StructureType::Spawn),
+        }
+    }
+
+    /// The role of a link at the given position, or None if it isn't next to anything we know
+    /// of. A link in range of more than one thing serves the closest, and ties go to the
+    /// controller and storage, so a receiving link near a source isn't mistaken for a source link
+    pub fn classify(&self, pos: Position) -> Option<LinkRole> {
+        let closest = |positions: &[Position]| {
+            positions
+                .iter()
+                .map(|anchor| anchor.get_range_to(pos))
+                .min()
+        };
+        let anchors = [
+            (
+                LinkRole::Controller,
+                self.controller
+                    .map(|controller| controller.get_range_to(pos)),
+                CONTROLLER_RANGE,
+            ),
+            (LinkRole::Storage, closest(&self.storage), STORAGE_RANGE),
+            (LinkRole::Source, closest(&self.sources), SOURCE_RANGE),
+            (LinkRole::Spawn, closest(&self.spawns), SPAWN_RANGE),
+        ];
+        anchors
+            .into_iter()
+            .filter_map(|(role, range, max_range)| Some((range.filter(|r| *r <= max_range)?, role)))
+            .min_by_key(|(range, _)| *range)
+            .map(|(_, role)| role)
+    }
+}
+
+/// The room's links with the given role
+pub fn links_with_role(room: &dyn RoomView, role: LinkRole) -> Vec<StructureInfo> {
+    let structures = room.structures();
+    let anchors = LinkAnchors::new(room, &structures);
+    structures
+        .into_iter()
+        .filter(|structure| structure.structure_type == StructureType::Link)
+        .filter(|link| anchors.classify(link.pos) == Some(role))
+        .collect()
+}
+
+pub struct LinkManager;
+
+impl LinkManager {
+    pub fn new() -> Self {
+        LinkManager
+    }
+
+    pub fn run_all_links(&self, world: &dyn World) {
+        for room in world.rooms() {
+            self.run(room.as_ref());
+        }
+    }
+
+    /// Send energy on from every source link that is ready
+    fn run(&self, room: &dyn RoomView) {
+        let links = room.links();
+        if links.len() < 2 {
+            return;
+        }
+        let anchors = LinkAnchors::new(room, &room.structures());
+        let mut sources = vec![];
+        let mut receivers = vec![];
+        for link in links.iter() {
+            match anchors.classify(link.pos()) {
+                Some(LinkRole::Source) => sources.push(link),
+                Some(role @ (LinkRole::Controller | LinkRole::Storage)) => {
+                    receivers.push((role, link))
+                }
+                _ => {}
+            }
+        }
+
+        // What each receiver is being sent this tick, so two sources don't overfill it
+        let mut incoming: HashMap<ObjectId<StructureLink>, u32> = HashMap::new();
+        for link in sources {
+            let energy = link.store().get_used_capacity(Some(ResourceType::Energy));
+            if link.cooldown() > 0 || energy < SEND_THRESHOLD {
+                continue;
+            }
+            let Some((target, free)) = self.choose_receiver(&receivers, &incoming) else {
+                continue;
+            };
+            let amount = energy.min(free);
+            match link.transfer_energy(target, Some(amount)) {
+                Ok(()) => *incoming.entry(target).or_default() += amount,
+                Err(err) => warn!("Link {} failed to send energy: {:?}", link.id(), err),
+            }
+        }
+    }
+
+    /// The link to send to, along with how much room it has left. The controller link comes
+    /// first while it is low, then the storage link, then the controller link again
+    fn choose_receiver(
+        &self,
+        receivers: &[(LinkRole, &Rc<dyn LinkView + '_>)],
+        incoming: &HashMap<ObjectId<StructureLink>, u32>,
+    ) -> Option<(ObjectId<StructureLink>, u32)> {
+        let state = |link: &Rc<dyn LinkView + '_>| {
+            let store = link.store();
+            let incoming = incoming.get(&link.id()).copied().unwrap_or(0);
+            let energy = store.get_used_capacity(Some(ResourceType::Energy)) + incoming;
+            let free = (store.get_free_capacity(Some(ResourceType::Energy)).max(0) as u32)
+                .saturating_sub(incoming);
+            (energy, free)
+        };
+        let with_role = |role: LinkRole| {
+            receivers
+                .iter()
+                .filter(move |(link_role, _)| *link_role == role)
+                .map(|(_, link)| (link.id(), state(link)))
+        };
+        let low_controller = with_role(LinkRole::Controller)
+            .find(|(_, (energy, free))| *energy < CONTROLLER_LOW && *free > 0);
+        let storage = with_role(LinkRole::Storage).find(|(_, (_, free))| *free > 0);
+        let controller = with_role(LinkRole::Controller).find(|(_, (_, free))| *free > 0);
+        low_controller
+            .or(storage)
+            .or(controller)
+            .map(|(id, (_, free))| (id, free))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+
+    #[test]
+    fn links_serve_the_closest_thing_in_range() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 10, test_room()), 5);
+        world.add_source(position(25, 14, test_room()));
+        let view = world.room(test_room()).unwrap();
+        let anchors = LinkAnchors::new(view.as_ref(), &view.structures());
+
+        // In range of both, but closer to the controller
+        assert_eq!(
+            anchors.classify(position(25, 11, test_room())),
+            Some(LinkRole::Controller)
+        );
+        assert_eq!(
+            anchors.classify(position(25, 13, test_room())),
+            Some(LinkRole::Source)
+        );
+        // Ties go to the controller
+        assert_eq!(
+            anchors.classify(position(24, 12, test_room())),
+            Some(LinkRole::Controller)
+        );
+        assert_eq!(anchors.classify(position(40, 40, test_room())), None);
+    }
+}
@@ -0,0 +1,123 @@
+// Per-room cost matrices for the PathFinder. Building one means looking at every structure and
+// construction site in the room, so they are cached and only rebuilt when the number of either
+// changes, or the matrix gets old. Like the reservations the cache lives in wasm memory, so it
+// is rebuilt after a global reset
+use crate::defense::HostileThreat;
+use crate::terrain;
+use crate::world::{RoomView, World};
+use screeps::{LocalCostMatrix, RoomName, StructureType};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static COST_MATRICES: RefCell<HashMap<RoomName, CachedMatrix>> = RefCell::new(HashMap::new());
+}
+
+/// Cost of walking on a road, plain and swamp tile. Plains cost more than roads so paths prefer
+/// roads where they can
+pub const ROAD_COST: u8 = 1;
+pub const PLAIN_COST: u8 = 2;
+pub const SWAMP_COST: u8 = 10;
+/// Cost of a tile that can't be walked on
+pub const BLOCKED: u8 = u8::MAX;
+
+// Ticks before a cached matrix is rebuilt, to catch changes that don't change the counts
+const MATRIX_TTL: u32 = 500;
+// Cost of tiles within reach of dangerous hostiles, when avoiding them
+const HOSTILE_COST: u8 = 50;
+// Ranged attacks reach this far
+const HOSTILE_RANGE: u8 = 3;
+
+struct CachedMatrix {
+    matrix: LocalCostMatrix,
+    built: u32,
+    // Number of structures and construction sites when the matrix was built
+    counts: (usize, usize),
+}
+
+/// The room's cost matrix, from the cache if it is still current
+pub fn room_matrix(world: &dyn World, room: &dyn RoomView) -> LocalCostMatrix {
+    let counts = (room.structures().len(), room.construction_sites().len());
+    COST_MATRICES.with(|matrices| {
+        let mut matrices = matrices.borrow_mut();
+        let cached = matrices
+            .get(&room.name())
+            .filter(|cached| cached.counts == counts && world.time() < cached.built + MATRIX_TTL);
+        if let Some(cached) = cached {
+            return cached.matrix.clone();
+        }
+        let matrix = build_matrix(room);
+        matrices.insert(
+            room.name(),
+            CachedMatrix {
+                matrix: matrix.clone(),
+                built: world.time(),
+                counts,
+            },
+        );
+        matrix
+    })
+}
+
+/// Roads are cheap, while structures, construction sites and sources can't be walked through.
+/// Everything else is left to the terrain
+fn build_matrix(room: &dyn RoomView) -> LocalCostMatrix {
+    let mut matrix = LocalCostMatrix::new();
+    for structure in room.structures() {
+        match structure.structure_type {
+            StructureType::Road => {
+                if matrix.get(structure.pos.xy()) == 0 {
+                    matrix.set(structure.pos.xy(), ROAD_COST);
+                }
+            }
+            StructureType::Container | StructureType::Rampart => {}
+            _ => matrix.set(structure.pos.xy(), BLOCKED),
+        }
+    }
+    // Creeps can walk over sites, but standing on one stops it being built
+    for site in room.construction_sites() {
+        if !matches!(
+            site.structure_type,
+            StructureType::Road | StructureType::Container | StructureType::Rampart
+        ) {
+            matrix.set(site.pos.xy(), BLOCKED);
+        }
+    }
+    for source in room.sources() {
+        matrix.set(source.pos.xy(), BLOCKED);
+    }
+    matrix
+}
+
+/// Make the tiles dangerous hostiles can reach expensive, and the hostiles themselves blocking.
+/// Returns whether there were any dangerous hostiles
+pub fn avoid_hostiles(room: &dyn RoomView, matrix: &mut LocalCostMatrix) -> bool {
+    let hostiles = dangerous_hostiles(room);
+    if hostiles.is_empty() {
+        return false;
+    }
+    let terrain = terrain::analyse(room);
+    for (xy, cost) in matrix.iter_mut() {
+        let in_reach = hostiles
+            .iter()
+            .any(|hostile| hostile.pos().xy().get_range_to(xy) <= HOSTILE_RANGE);
+        // Any cost on a wall tile would make it walkable
+        if !in_reach || *cost == BLOCKED || (*cost == 0 && !terrain.is_walkable(xy)) {
+            continue;
+        }
+        *cost = (*cost).max(HOSTILE_COST);
+    }
+    for hostile in hostiles {
+        matrix.set(hostile.pos().xy(), BLOCKED);
+    }
+    true
+}
+
+/// Hostiles in the room that can do any damage
+pub fn dangerous_hostiles(room: &dyn RoomView) -> Vec<HostileThreat> {
+    room.hostile_creeps()
+        .into_iter()
+        .map(HostileThreat::new)
+        .filter(|hostile| hostile.is_dangerous())
+        .collect()
+}
@@ -0,0 +1,315 @@
+// Moving creeps along paths we find ourselves, rather than through the game's move_to.
+// Paths are searched with the PathFinder over cached per-room cost matrices (see cost_matrix),
+// stored in the creep's memory, and followed one step a tick until the creep arrives, its
+// destination changes, it is pushed off the path, or the path is blocked by something new.
+// Creeps don't move straight away; each step is handed to traffic, which moves everyone once
+// all the states have run
+mod cost_matrix;
+mod path;
+mod traffic;
+
+pub use cost_matrix::{BLOCKED, PLAIN_COST, ROAD_COST, SWAMP_COST};
+pub use path::Travel;
+pub use traffic::{resolve_traffic, MovePriority};
+
+use crate::terrain;
+use crate::world::{ActionResult, CreepView, World};
+use log::{info, warn};
+use screeps::{Direction, ErrorCode, Position, Terrain};
+use std::collections::HashMap;
+
+// Ticks without progress before searching again with our own creeps treated as obstacles
+const REPATH_AFTER: u32 = 3;
+// Ticks without progress before giving up, unless the state sets its own timeout
+const STUCK_TIMEOUT: u32 = 20;
+// Positions remembered to tell moving back and forth apart from making progress
+const HISTORY_LENGTH: usize = 4;
+
+/// How a creep should get to where it is going
+#[derive(Debug, Clone, Copy)]
+pub struct MoveOptions {
+    // How close to the target the creep needs to get
+    range: u32,
+    // Whether to keep out of reach of dangerous hostiles
+    avoid_hostiles: bool,
+    priority: MovePriority,
+    // Ticks without progress before giving up
+    timeout: u32,
+}
+
+impl MoveOptions {
+    pub fn new(range: u32) -> Self {
+        MoveOptions {
+            range,
+            avoid_hostiles: true,
+            priority: MovePriority::Normal,
+            timeout: STUCK_TIMEOUT,
+        }
+    }
+
+    /// Creeps that are going to fight need to walk up to hostiles instead
+    pub fn avoid_hostiles(mut self, avoid: bool) -> Self {
+        self.avoid_hostiles = avoid;
+        self
+    }
+
+    pub fn priority(mut self, priority: MovePriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn timeout(mut self, ticks: u32) -> Self {
+        self.timeout = ticks;
+        self
+    }
+}
+
+/// Move the creep one step towards being within `range` of the target
+pub fn move_to(
+    creep: &dyn CreepView,
+    world: &dyn World,
+    target: Position,
+    range: u32,
+) -> ActionResult {
+    move_with(creep, world, target, MoveOptions::new(range))
+}
+
+/// Move the creep one step towards the target. Does nothing if it is already there.
+/// The creep's last path stays in its memory after it arrives, so traffic knows to keep it in
+/// range if it has to be moved out of the way.
+/// Fails with `NoPath` if there is no way there, or the creep has been stuck for longer than the
+/// timeout, in which case the state should give up on the target
+pub fn move_with(
+    creep: &dyn CreepView,
+    world: &dyn World,
+    target: Position,
+    options: MoveOptions,
+) -> ActionResult {
+    let pos = creep.pos();
+    if pos.get_range_to(target) <= options.range {
+        return Ok(());
+    }
+    let mut memory = creep.memory();
+    let previous = memory
+        .travel()
+        .filter(|travel| travel.dest == target && travel.range == options.range)
+        .cloned();
+
+    // Being tired isn't being stuck, so the count only changes when the creep could have moved
+    let (mut history, mut stuck) = previous
+        .as_ref()
+        .map(|travel| (travel.history.clone(), travel.stuck))
+        .unwrap_or_default();
+    if creep.fatigue() > 0 {
+        return Ok(());
+    }
+    stuck = if history.contains(&pos) { stuck + 1 } else { 0 };
+    history.push(pos);
+    if history.len() > HISTORY_LENGTH {
+        history.remove(0);
+    }
+    if stuck >= options.timeout {
+        warn!(
+            "{} has been stuck at {} for {} ticks in {:?}, giving up on {}",
+            creep.name(),
+            pos,
+            stuck,
+            memory.current_state(),
+            target
+        );
+        memory.set_travel(None);
+        creep.set_memory(memory);
+        return Err(ErrorCode::NoPath);
+    }
+
+    let mut travel = previous.filter(|travel| {
+        // A path found before hostiles showed up may walk straight past them
+        travel.wary || !options.avoid_hostiles || !hostiles_in_room(world, pos)
+    });
+    if let Some(current) = travel.as_mut() {
+        let on_path = current.advance(pos);
+        let blocked = current
+            .next_step()
+            .is_none_or(|direction| is_blocked(world, pos, direction));
+        if !on_path || blocked || stuck == REPATH_AFTER {
+            travel = None;
+        }
+    }
+    let avoid_creeps = stuck >= REPATH_AFTER;
+    if avoid_creeps && travel.is_none() {
+        info!(
+            "{} is stuck at {} in {:?}, finding a way around our creeps",
+            creep.name(),
+            pos,
+            memory.current_state()
+        );
+    }
+    let mut travel = match travel {
+        Some(travel) => travel,
+        None => find_travel(world, pos, target, options, avoid_creeps).ok_or(ErrorCode::NoPath)?,
+    };
+    travel.history = history;
+    travel.stuck = stuck;
+
+    let result = match travel.next_step() {
+        Some(direction) => {
+            traffic::request(creep, direction, options.priority);
+            Ok(())
+        }
+        None => Err(ErrorCode::NoPath),
+    };
+    memory.set_travel(Some(travel));
+    creep.set_memory(memory);
+    result
+}
+
+/// Cost of the path from one position to within `range` of another, or None if there is no
+/// path. Costs are per tile (see cost_matrix), which is also the number of ticks it takes a creep
+/// with one MOVE for every two other parts to walk the path
+pub fn path_cost(world: &dyn World, from: Position, to: Position, range: u32) -> Option<u32> {
+    let mut matrices = HashMap::new();
+    let mut matrix = |room_name| {
+        let room = world.room(room_name)?;
+        let matrix = cost_matrix::room_matrix(world, room.as_ref());
+        matrices.insert(room_name, (matrix.clone(), terrain::analyse(room.as_ref())));
+        Some(matrix)
+    };
+    let path = world.find_path(from, to, range, &mut matrix)?;
+    let cost = path
+        .iter()
+        .map(|pos| match matrices.get(&pos.room_name()) {
+            Some((matrix, _)) if matrix.get(pos.xy()) > 0 => matrix.get(pos.xy()) as u32,
+            Some((_, analysis)) if analysis.terrain.get_xy(pos.xy()) == Terrain::Swamp => {
+                SWAMP_COST as u32
+            }
+            _ => PLAIN_COST as u32,
+        })
+        .sum();
+    Some(cost)
+}
+
+/// Search for a new path to the target
+fn find_travel(
+    world: &dyn World,
+    from: Position,
+    target: Position,
+    options: MoveOptions,
+    avoid_creeps: bool,
+) -> Option<Travel> {
+    let mut wary = false;
+    let mut matrix = |room_name| {
+        let room = world.room(room_name)?;
+        let mut matrix = cost_matrix::room_matrix(world, room.as_ref());
+        if options.avoid_hostiles {
+            wary |= cost_matrix::avoid_hostiles(room.as_ref(), &mut matrix);
+        }
+        // Creeps further away will have moved on by the time we get there
+        if avoid_creeps && room_name == from.room_name() {
+            for creep in world.creeps_in_room(room_name) {
+                matrix.set(creep.pos().xy(), BLOCKED);
+            }
+        }
+        Some(matrix)
+    };
+    let path = world.find_path(from, target, options.range, &mut matrix)?;
+    Some(Travel::new(target, options.range, from, &path, wary))
+}
+
+/// Whether something has been built (or a hostile is standing) on the next tile of the path.
+/// Our own creeps are left to traffic to move out of the way
+fn is_blocked(world: &dyn World, from: Position, direction: Direction) -> bool {
+    let Ok(next) = from.checked_add_direction(direction) else {
+        return true;
+    };
+    let Some(room) = world.room(next.room_name()) else {
+        return false;
+    };
+    let matrix = cost_matrix::room_matrix(world, room.as_ref());
+    matrix.get(next.xy()) == BLOCKED
+        || room
+            .hostile_creeps()
+            .iter()
+            .any(|hostile| hostile.pos == next)
+}
+
+fn hostiles_in_room(world: &dyn World, pos: Position) -> bool {
+    world
+        .room(pos.room_name())
+        .is_some_and(|room| !cost_matrix::dangerous_hostiles(room.as_ref()).is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{plain_terrain, position, test_room, MockWorld};
+    use screeps::Part;
+
+    fn world_with_creep() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room()).terrain = plain_terrain();
+        world.add_creep(
+            "walker",
+            position(10, 10, test_room()),
+            vec![Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world
+    }
+
+    fn travel(world: &MockWorld) -> Option<Travel> {
+        world.creeps()[0].memory().travel().cloned()
+    }
+
+    #[test]
+    fn stuck_creeps_find_a_way_around_our_creeps_then_give_up() {
+        let mut world = world_with_creep();
+        let target = position(20, 10, test_room());
+        let options = MoveOptions::new(1).timeout(5);
+        let walk =
+            |world: &MockWorld| move_with(world.creeps()[0].as_ref(), world, target, options);
+
+        assert_eq!(walk(&world), Ok(()));
+        let first_step = travel(&world).unwrap().next_step().unwrap();
+
+        // Another of our creeps won't get out of the way
+        world.add_creep(
+            "blocker",
+            position(10, 10, test_room()).checked_add_direction(first_step).unwrap(),
+            vec![Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        for stuck in 1..REPATH_AFTER {
+            assert_eq!(walk(&world), Ok(()));
+            let travel = travel(&world).unwrap();
+            assert_eq!(travel.stuck, stuck);
+            assert_eq!(travel.next_step(), Some(first_step));
+        }
+        assert_eq!(walk(&world), Ok(()));
+        let around = travel(&world).unwrap();
+        assert_eq!(around.stuck, REPATH_AFTER);
+        assert_ne!(around.next_step(), Some(first_step));
+
+        // Tired creeps aren't stuck
+        world.creep_mut("walker").fatigue = 2;
+        assert_eq!(walk(&world), Ok(()));
+        assert_eq!(travel(&world).unwrap().stuck, REPATH_AFTER);
+        world.creep_mut("walker").fatigue = 0;
+
+        while travel(&world).is_some_and(|travel| travel.stuck + 1 < 5) {
+            assert_eq!(walk(&world), Ok(()));
+        }
+        assert_eq!(walk(&world), Err(ErrorCode::NoPath));
+        assert!(travel(&world).is_none());
+    }
+
+    #[test]
+    fn creeps_already_in_range_stay_put() {
+        let world = world_with_creep();
+        let creep = world.creeps()[0].clone();
+
+        assert_eq!(move_to(creep.as_ref(), &world, position(11, 11, test_room()), 1), Ok(()));
+        assert!(travel(&world).is_none());
+    }
+}
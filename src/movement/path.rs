@@ -0,0 +1,175 @@
+// A creep's path, stored in its memory as one direction digit per step so it can be followed
+// across ticks without searching again
+use screeps::{Direction, Position};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Travel {
+    // Where the creep is going, and how close it needs to get
+    pub dest: Position,
+    pub range: u32,
+    // Where the creep was when it last stepped along the path
+    pub last_pos: Position,
+    // Directions left to walk from `last_pos`
+    pub path: String,
+    // Whether hostiles were avoided when the path was found
+    #[serde(default)]
+    pub wary: bool,
+    // The creep's last few positions, oldest first
+    #[serde(default)]
+    pub history: Vec<Position>,
+    // Ticks since the creep last reached a tile it hadn't just been on
+    #[serde(default)]
+    pub stuck: u32,
+}
+
+impl Travel {
+    /// Serialize a path found from `from`, which doesn't include `from` itself
+    pub fn new(dest: Position, range: u32, from: Position, path: &[Position], wary: bool) -> Self {
+        let mut last = from;
+        let mut directions = String::with_capacity(path.len());
+        for pos in path {
+            if let Some(direction) = last.get_direction_to(*pos) {
+                directions.push(char::from(b'0' + direction as u8));
+            }
+            last = *pos;
+        }
+        Travel {
+            dest,
+            range,
+            last_pos: from,
+            path: directions,
+            wary,
+            history: vec![],
+            stuck: 0,
+        }
+    }
+
+    /// The next direction to step in, if there is any path left
+    pub fn next_step(&self) -> Option<Direction> {
+        self.path.chars().next().and_then(direction)
+    }
+
+    /// Catch up with the creep, dropping the steps it has taken since we last looked.
+    /// Returns false if the creep isn't on the path any more
+    pub fn advance(&mut self, pos: Position) -> bool {
+        if pos == self.last_pos {
+            // Tired or blocked, so it hasn't moved
+            return true;
+        }
+        let mut step = self.last_pos;
+        for (taken, digit) in self.path.chars().enumerate().take(2) {
+            let Some(next) = direction(digit).and_then(|d| step.checked_add_direction(d).ok())
+            else {
+                return false;
+            };
+            if next == pos {
+                self.path.drain(..=taken);
+                self.last_pos = pos;
+                return true;
+            }
+            // Stepping onto an exit moves the creep into the next room straight away, so it
+            // can be one step further along than expected
+            if !next.xy().is_room_edge() {
+                return false;
+            }
+            step = next;
+        }
+        false
+    }
+}
+
+fn direction(digit: char) -> Option<Direction> {
+    Some(match digit {
+        '1' => Direction::Top,
+        '2' => Direction::TopRight,
+        '3' => Direction::Right,
+        '4' => Direction::BottomRight,
+        '5' => Direction::Bottom,
+        '6' => Direction::BottomLeft,
+        '7' => Direction::Left,
+        '8' => Direction::TopLeft,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room};
+    use screeps::RoomName;
+
+    fn travel(from: Position, path: &[Position]) -> Travel {
+        Travel::new(position(40, 40, test_room()), 1, from, path, false)
+    }
+
+    #[test]
+    fn paths_are_stored_as_direction_digits() {
+        let from = position(10, 10, test_room());
+        let path = [
+            position(11, 10, test_room()),
+            position(12, 11, test_room()),
+            position(12, 12, test_room()),
+        ];
+        let travel = travel(from, &path);
+        assert_eq!(travel.path, "345");
+        assert_eq!(travel.next_step(), Some(Direction::Right));
+    }
+
+    #[test]
+    fn travel_survives_a_round_trip_through_memory() {
+        let from = position(10, 10, test_room());
+        let mut travel = travel(from, &[position(11, 11, test_room())]);
+        travel.history = vec![from];
+        travel.stuck = 2;
+        let json = serde_json::to_string(&travel).unwrap();
+        assert_eq!(serde_json::from_str::<Travel>(&json).unwrap(), travel);
+
+        // Paths stored before creeps kept track of being stuck
+        let mut old: serde_json::Value = serde_json::from_str(&json).unwrap();
+        for field in ["wary", "history", "stuck"] {
+            old.as_object_mut().unwrap().remove(field);
+        }
+        let old: Travel = serde_json::from_value(old).unwrap();
+        assert_eq!(old.path, "4");
+        assert!(old.history.is_empty());
+        assert_eq!(old.stuck, 0);
+    }
+
+    #[test]
+    fn advance_drops_the_steps_the_creep_has_taken() {
+        let from = position(10, 10, test_room());
+        let mut travel = travel(
+            from,
+            &[position(11, 10, test_room()), position(12, 10, test_room())],
+        );
+
+        // Tired or blocked
+        assert!(travel.advance(from));
+        assert_eq!(travel.path, "33");
+
+        assert!(travel.advance(position(11, 10, test_room())));
+        assert_eq!(travel.path, "3");
+        assert_eq!(travel.last_pos, position(11, 10, test_room()));
+
+        // Pushed off the path
+        assert!(!travel.advance(position(11, 11, test_room())));
+    }
+
+    #[test]
+    fn advance_follows_the_creep_through_an_exit() {
+        let next_room = RoomName::new("W0N1").unwrap();
+        let mut travel = travel(
+            position(48, 25, test_room()),
+            &[
+                position(49, 25, test_room()),
+                position(0, 25, next_room),
+                position(1, 25, next_room),
+            ],
+        );
+
+        // Stepping onto the exit put the creep straight into the next room
+        assert!(travel.advance(position(0, 25, next_room)));
+        assert_eq!(travel.path, "3");
+    }
+}
@@ -0,0 +1,255 @@
+// Settles where every creep moves this tick. States only register the step they want to take;
+// once they have all run, the moves are resolved together in priority order. A creep in the way
+// that isn't moving itself is asked to swap places with the mover, or shoved onto a free tile
+// next to it. Creeps working somewhere (i.e. still in range of where they last travelled to)
+// are only moved to tiles that keep them in range.
+use super::cost_matrix::{self, BLOCKED};
+use crate::terrain;
+use crate::world::{CreepView, World};
+use screeps::{Direction, Position};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+thread_local! {
+    static TRAFFIC: RefCell<Vec<MoveRequest>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Which creep gets to move first when two want the same tile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MovePriority {
+    Low,
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone)]
+struct MoveRequest {
+    creep: String,
+    from: Position,
+    to: Position,
+    priority: MovePriority,
+}
+
+/// Register the step the creep wants to take this tick
+pub fn request(creep: &dyn CreepView, direction: Direction, priority: MovePriority) {
+    let from = creep.pos();
+    let Ok(to) = from.checked_add_direction(direction) else {
+        return;
+    };
+    TRAFFIC.with(|traffic| {
+        let mut traffic = traffic.borrow_mut();
+        traffic.retain(|request| request.creep != creep.name());
+        traffic.push(MoveRequest {
+            creep: creep.name(),
+            from,
+            to,
+            priority,
+        });
+    });
+}
+
+/// Resolve this tick's requests and issue the moves
+pub fn resolve_traffic(world: &dyn World) {
+    let mut requests = TRAFFIC.with(|traffic| traffic.take());
+    // Stable, so creeps with the same priority keep the order they asked in
+    requests.sort_by_key(|request| std::cmp::Reverse(request.priority));
+
+    let creeps = world.creeps();
+    let occupants: HashMap<Position, &dyn CreepView> = creeps
+        .iter()
+        .map(|creep| (creep.pos(), creep.as_ref()))
+        .collect();
+    let requesting: HashMap<&str, &MoveRequest> = requests
+        .iter()
+        .map(|request| (request.creep.as_str(), request))
+        .collect();
+    let mut moves: HashMap<String, (Position, Position)> = HashMap::new();
+    let mut claimed: HashSet<Position> = HashSet::new();
+
+    for request in &requests {
+        if moves.contains_key(&request.creep) || claimed.contains(&request.to) {
+            continue;
+        }
+        let Some(occupant) = occupants.get(&request.to) else {
+            claimed.insert(request.to);
+            moves.insert(request.creep.clone(), (request.from, request.to));
+            continue;
+        };
+        let occupant_name = occupant.name();
+        if moves.contains_key(&occupant_name) {
+            // It's already moving out of the way
+            claimed.insert(request.to);
+            moves.insert(request.creep.clone(), (request.from, request.to));
+            continue;
+        }
+        if let Some(theirs) = requesting.get(occupant_name.as_str()) {
+            // Two creeps heading through each other just swap, otherwise wait for it to move
+            if theirs.to == request.from && !claimed.contains(&request.from) {
+                claimed.extend([request.to, request.from]);
+                moves.insert(request.creep.clone(), (request.from, request.to));
+                moves.insert(occupant_name, (theirs.from, theirs.to));
+            }
+            continue;
+        }
+        let Some(out_of_the_way) = make_way(world, *occupant, request, &occupants, &claimed) else {
+            continue;
+        };
+        claimed.extend([request.to, out_of_the_way]);
+        moves.insert(request.creep.clone(), (request.from, request.to));
+        moves.insert(occupant_name, (request.to, out_of_the_way));
+    }
+
+    for creep in creeps.iter() {
+        let Some((from, to)) = moves.get(&creep.name()) else {
+            continue;
+        };
+        if let Some(direction) = from.get_direction_to(*to) {
+            let _ = creep.move_direction(direction);
+        }
+    }
+}
+
+/// Where a creep that isn't moving can go to let the mover through: the mover's tile if they can
+/// swap, otherwise any free tile next to it
+fn make_way(
+    world: &dyn World,
+    creep: &dyn CreepView,
+    request: &MoveRequest,
+    occupants: &HashMap<Position, &dyn CreepView>,
+    claimed: &HashSet<Position>,
+) -> Option<Position> {
+    let pos = creep.pos();
+    // Keep working creeps in range of their work
+    let anchor = creep
+        .memory()
+        .travel()
+        .filter(|travel| pos.get_range_to(travel.dest) <= travel.range)
+        .map(|travel| (travel.dest, travel.range));
+    let in_range =
+        |tile: Position| anchor.is_none_or(|(dest, range)| tile.get_range_to(dest) <= range);
+    if in_range(request.from) && !claimed.contains(&request.from) {
+        return Some(request.from);
+    }
+
+    let room = world.room(pos.room_name())?;
+    let matrix = cost_matrix::room_matrix(world, room.as_ref());
+    let terrain = terrain::analyse(room.as_ref());
+    pos.xy()
+        .neighbors()
+        .into_iter()
+        .map(|xy| Position::new(xy.x, xy.y, pos.room_name()))
+        .filter(|tile| {
+            let cost = matrix.get(tile.xy());
+            cost != BLOCKED
+                && (cost != 0 || terrain.is_walkable(tile.xy()))
+                && !tile.xy().is_room_edge()
+                && !occupants.contains_key(tile)
+                && !claimed.contains(tile)
+                && in_range(*tile)
+        })
+        // Prefer stepping aside rather than back along the mover's path, and out into the open
+        // rather than into a gap where we'd be in the way again
+        .max_by_key(|tile| (tile.get_range_to(request.from), terrain.openness[tile.xy()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movement::Travel;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{plain_terrain, position, test_room, Intent, MockWorld};
+    use screeps::Part;
+
+    fn world_with_creeps(creeps: &[(&str, Position)]) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room()).terrain = plain_terrain();
+        for (name, pos) in creeps {
+            world.add_creep(
+                name,
+                *pos,
+                vec![Part::Move],
+                CreepMemory::new(Specialisation::Hauler),
+            );
+        }
+        world
+    }
+
+    /// Where each creep was told to step, by name
+    fn moves(world: &MockWorld) -> HashMap<String, Direction> {
+        world
+            .take_intents()
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::MoveDirection { creep, direction } => Some((creep, direction)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn creeps_walking_into_each_other_swap() {
+        let world = world_with_creeps(&[
+            ("left", position(10, 10, test_room())),
+            ("right", position(11, 10, test_room())),
+        ]);
+        let creeps = world.creeps();
+        request(creeps[0].as_ref(), Direction::Right, MovePriority::Normal);
+        request(creeps[1].as_ref(), Direction::Left, MovePriority::Normal);
+        resolve_traffic(&world);
+
+        let moves = moves(&world);
+        assert_eq!(moves.get("left"), Some(&Direction::Right));
+        assert_eq!(moves.get("right"), Some(&Direction::Left));
+    }
+
+    #[test]
+    fn higher_priority_creeps_get_the_tile() {
+        let world = world_with_creeps(&[
+            ("left", position(10, 10, test_room())),
+            ("right", position(12, 10, test_room())),
+        ]);
+        let creeps = world.creeps();
+        request(creeps[0].as_ref(), Direction::Right, MovePriority::Low);
+        request(creeps[1].as_ref(), Direction::Left, MovePriority::High);
+        resolve_traffic(&world);
+
+        let moves = moves(&world);
+        assert_eq!(moves.get("left"), None);
+        assert_eq!(moves.get("right"), Some(&Direction::Left));
+    }
+
+    #[test]
+    fn idle_creeps_swap_with_the_mover() {
+        let world = world_with_creeps(&[
+            ("mover", position(10, 10, test_room())),
+            ("idle", position(11, 10, test_room())),
+        ]);
+        request(world.creeps()[0].as_ref(), Direction::Right, MovePriority::Normal);
+        resolve_traffic(&world);
+
+        let moves = moves(&world);
+        assert_eq!(moves.get("mover"), Some(&Direction::Right));
+        assert_eq!(moves.get("idle"), Some(&Direction::Left));
+    }
+
+    #[test]
+    fn working_creeps_step_aside_within_range_of_their_work() {
+        let worker = position(11, 10, test_room());
+        let work = position(12, 10, test_room());
+        let world =
+            world_with_creeps(&[("mover", position(10, 10, test_room())), ("worker", worker)]);
+        let mut memory = world.creeps()[1].memory();
+        memory.set_travel(Some(Travel::new(work, 1, worker, &[], false)));
+        world.creeps()[1].set_memory(memory);
+
+        request(world.creeps()[0].as_ref(), Direction::Right, MovePriority::Normal);
+        resolve_traffic(&world);
+
+        let moves = moves(&world);
+        assert_eq!(moves.get("mover"), Some(&Direction::Right));
+        let stepped = worker.checked_add_direction(moves["worker"]).unwrap();
+        assert!(stepped.get_range_to(work) <= 1);
+        assert_ne!(stepped, work);
+    }
+}
@@ -0,0 +1,379 @@
+// Works out where everything in a room goes, around its first spawn.
+// The core is a checkerboard: roads run along the diagonals through the spawn every four tiles,
+// which leaves every other tile next to a road, and those are filled outwards from the spawn with
+// the storage (plus a link next to it), the other spawns, towers and extensions. Each source gets
+// a container and a link next to it, the controller a container and a link, and roads join them
+// to the spawn without leaving the room.
+use super::{PlannedStructure, RoomPlan};
+use crate::movement::{BLOCKED, ROAD_COST};
+use crate::terrain::{self, TerrainAnalysis, TileMap, UNREACHABLE};
+use crate::world::{RoomView, World};
+use screeps::{LocalCostMatrix, Position, RoomXY, StructureType};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Buildings keep at least this far from the exits, leaving room to wall them off
+const EXIT_MARGIN: u32 = 3;
+// Tiles this close to the controller are left free for upgraders
+const UPGRADE_RANGE: u8 = 2;
+// Roads aren't worth building before this level
+const ROAD_LEVEL: u8 = 3;
+// Containers aren't worth building before miners and haulers take over
+const CONTAINER_LEVEL: u8 = 2;
+// How many of each are put in the core, counting the first spawn
+const SPAWNS: usize = 3;
+const TOWERS: usize = 6;
+const EXTENSIONS: usize = 60;
+
+/// Plan the room around its first spawn. Returns None if the room has no spawn or controller
+pub fn plan_room(world: &dyn World, room: &dyn RoomView) -> Option<RoomPlan> {
+    let existing = room.structures();
+    let anchor = existing
+        .iter()
+        .find(|s| s.structure_type == StructureType::Spawn)?
+        .pos
+        .xy();
+    let controller = room.controller()?.pos.xy();
+    let mut layout = Layout::new(terrain::analyse(room), anchor);
+
+    // Work around anything already built, and count it towards the limits
+    for structure in &existing {
+        let xy = structure.pos.xy();
+        match structure.structure_type {
+            StructureType::Road
+            | StructureType::Container
+            | StructureType::Spawn
+            | StructureType::Extension
+            | StructureType::Tower
+            | StructureType::Storage
+            | StructureType::Link => layout.add(structure.structure_type, xy),
+            _ => layout.block(xy),
+        }
+    }
+    let sources: Vec<RoomXY> = room.sources().iter().map(|s| s.pos.xy()).collect();
+    for source in &sources {
+        layout.block(*source);
+        layout.reserve(source.neighbors());
+    }
+    layout.reserve(around(controller, UPGRADE_RANGE));
+    // Keep the narrow gaps through the room open
+    layout.reserve(layout.analysis.chokepoints.clone());
+
+    // Containers and links where the energy is produced and used. The controller's link is
+    // planned before the sources' so the first pair unlocked (at RCL5) has one to receive
+    let upgrade_tiles: Vec<RoomXY> = around(controller, UPGRADE_RANGE)
+        .into_iter()
+        .filter(|xy| *xy != controller)
+        .collect();
+    let upgrade_container = layout.closest(upgrade_tiles, StructureType::Container);
+    if let Some(container) = upgrade_container {
+        layout.place(StructureType::Container, container);
+        layout.place_closest(container.neighbors(), StructureType::Link);
+    }
+    let mut sources = sources;
+    sources.sort_by_key(|source| layout.distance(*source));
+    let mut outposts = vec![];
+    for source in sources {
+        if let Some(container) = layout.closest(source.neighbors(), StructureType::Container) {
+            layout.place(StructureType::Container, container);
+            layout.place_closest(container.neighbors(), StructureType::Link);
+            outposts.push(container);
+        }
+    }
+    outposts.extend(upgrade_container);
+
+    layout.plan_core();
+    for outpost in outposts {
+        layout.plan_road(world, room, outpost);
+    }
+    Some(layout.finish())
+}
+
+/// A plan being put together
+struct Layout {
+    analysis: Rc<TerrainAnalysis>,
+    anchor: RoomXY,
+    // Walking distance of every tile from the anchor
+    distances: TileMap<u32>,
+    structures: Vec<(StructureType, RoomXY)>,
+    // Tiles nothing can be built on (sources, existing buildings we don't plan)
+    blocked: Vec<RoomXY>,
+    // Tiles left free for creeps to work from, which only roads and containers can go on
+    reserved: Vec<RoomXY>,
+}
+
+impl Layout {
+    fn new(analysis: Rc<TerrainAnalysis>, anchor: RoomXY) -> Self {
+        let distances = terrain::distances(&analysis.terrain, &[anchor]);
+        Layout {
+            analysis,
+            anchor,
+            distances,
+            structures: vec![],
+            blocked: vec![anchor],
+            reserved: vec![],
+        }
+    }
+
+    fn distance(&self, xy: RoomXY) -> u32 {
+        self.distances[xy]
+    }
+
+    fn add(&mut self, structure_type: StructureType, xy: RoomXY) {
+        self.structures.push((structure_type, xy));
+    }
+
+    fn block(&mut self, xy: RoomXY) {
+        self.blocked.push(xy);
+    }
+
+    fn reserve(&mut self, tiles: impl IntoIterator<Item = RoomXY>) {
+        self.reserved.extend(tiles);
+    }
+
+    /// Whether a new structure of the given type could go on the tile
+    fn is_free(&self, xy: RoomXY, structure_type: StructureType) -> bool {
+        let in_bounds = !xy.is_room_edge()
+            && (structure_type == StructureType::Road
+                || self.analysis.exit_distances[xy] >= EXIT_MARGIN);
+        let can_reserve = matches!(
+            structure_type,
+            StructureType::Road | StructureType::Container
+        );
+        in_bounds
+            && self.distance(xy) != UNREACHABLE
+            && !self.blocked.contains(&xy)
+            && !self.structures.iter().any(|(_, planned)| *planned == xy)
+            && (can_reserve || !self.reserved.contains(&xy))
+    }
+
+    fn place(&mut self, structure_type: StructureType, xy: RoomXY) {
+        self.structures.push((structure_type, xy));
+    }
+
+    /// The free tile closest to the anchor
+    fn closest(
+        &self,
+        tiles: impl IntoIterator<Item = RoomXY>,
+        structure_type: StructureType,
+    ) -> Option<RoomXY> {
+        tiles
+            .into_iter()
+            .filter(|xy| self.is_free(*xy, structure_type))
+            .min_by_key(|xy| self.distance(*xy))
+    }
+
+    fn place_closest(
+        &mut self,
+        tiles: impl IntoIterator<Item = RoomXY>,
+        structure_type: StructureType,
+    ) -> Option<RoomXY> {
+        let xy = self.closest(tiles, structure_type)?;
+        self.place(structure_type, xy);
+        Some(xy)
+    }
+
+    /// Whether the tile is on one of the core's diagonal roads
+    fn is_core_road(&self, xy: RoomXY) -> bool {
+        let dx = xy.x.u8() as i32 - self.anchor.x.u8() as i32;
+        let dy = xy.y.u8() as i32 - self.anchor.y.u8() as i32;
+        (dx + dy).rem_euclid(4) == 0 || (dx - dy).rem_euclid(4) == 0
+    }
+
+    /// Fill the checkerboard around the anchor, closest tiles first
+    fn plan_core(&mut self) {
+        let mut wanted = vec![StructureType::Storage];
+        wanted.extend([StructureType::Spawn; SPAWNS]);
+        wanted.extend([StructureType::Tower; TOWERS]);
+        wanted.extend([StructureType::Extension; EXTENSIONS]);
+        // Count what is already built towards what we want
+        for (structure_type, _) in &self.structures {
+            if let Some(index) = wanted.iter().position(|wanted| wanted == structure_type) {
+                wanted.remove(index);
+            }
+        }
+
+        let mut tiles: Vec<(RoomXY, u32)> = self
+            .distances
+            .iter()
+            .filter(|(_, distance)| *distance != UNREACHABLE)
+            .collect();
+        tiles.sort_by_key(|(xy, distance)| (*distance, xy.x.u8(), xy.y.u8()));
+        let mut wanted = wanted.into_iter();
+        let mut core = vec![self.anchor];
+        for (xy, _) in tiles {
+            if self.is_core_road(xy) || !self.is_free(xy, StructureType::Extension) {
+                continue;
+            }
+            let Some(structure_type) = wanted.next() else {
+                break;
+            };
+            self.place(structure_type, xy);
+            core.push(xy);
+            if structure_type == StructureType::Storage {
+                // The storage link sits next to the storage, off the roads
+                let tiles: Vec<RoomXY> = xy
+                    .neighbors()
+                    .into_iter()
+                    .filter(|xy| !self.is_core_road(*xy))
+                    .collect();
+                if let Some(link) = self.place_closest(tiles, StructureType::Link) {
+                    core.push(link);
+                }
+            }
+        }
+
+        // Roads only where they lead to something
+        let roads: Vec<RoomXY> = core
+            .iter()
+            .flat_map(|xy| xy.neighbors())
+            .filter(|xy| self.is_core_road(*xy))
+            .collect();
+        for xy in roads {
+            if self.is_free(xy, StructureType::Road) {
+                self.place(StructureType::Road, xy);
+            }
+        }
+    }
+
+    /// Roads from the anchor to the given tile, around everything planned so far and without
+    /// leaving the room
+    fn plan_road(&mut self, world: &dyn World, room: &dyn RoomView, to: RoomXY) {
+        let mut matrix = LocalCostMatrix::new();
+        for xy in &self.blocked {
+            matrix.set(*xy, BLOCKED);
+        }
+        for (structure_type, xy) in &self.structures {
+            let cost = match structure_type {
+                StructureType::Road => ROAD_COST,
+                StructureType::Container => continue,
+                _ => BLOCKED,
+            };
+            matrix.set(*xy, cost);
+        }
+        let position = |xy: RoomXY| Position::new(xy.x, xy.y, room.name());
+        let mut matrices = |name| {
+            if name == room.name() {
+                return Some(matrix.clone());
+            }
+            let mut blocked = LocalCostMatrix::new();
+            for xy in terrain::all_tiles() {
+                blocked.set(xy, BLOCKED);
+            }
+            Some(blocked)
+        };
+        let Some(path) = world.find_path(position(self.anchor), position(to), 1, &mut matrices)
+        else {
+            return;
+        };
+        for pos in path {
+            if pos.room_name() == room.name() && self.is_free(pos.xy(), StructureType::Road) {
+                self.place(StructureType::Road, pos.xy());
+            }
+        }
+    }
+
+    /// Work out the level each structure is built at, and put them in the order they should be
+    /// built. Anything over the limits of a fully upgraded room is dropped
+    fn finish(self) -> RoomPlan {
+        let mut counts: HashMap<StructureType, u32> = HashMap::new();
+        let mut structures: Vec<PlannedStructure> = self
+            .structures
+            .into_iter()
+            .filter_map(|(structure_type, xy)| {
+                let count = counts.entry(structure_type).or_default();
+                let level = (1..=8u8)
+                    .find(|level| structure_type.controller_structures(*level as u32) > *count)?;
+                *count += 1;
+                let level = match structure_type {
+                    StructureType::Road => level.max(ROAD_LEVEL),
+                    StructureType::Container => level.max(CONTAINER_LEVEL),
+                    _ => level,
+                };
+                Some(PlannedStructure {
+                    structure_type,
+                    xy,
+                    level,
+                })
+            })
+            .collect();
+        structures.sort_by_key(|planned| build_order(planned.structure_type));
+        RoomPlan { structures }
+    }
+}
+
+/// Which structures get their sites first when several are unlocked at once
+fn build_order(structure_type: StructureType) -> u8 {
+    match structure_type {
+        StructureType::Spawn => 0,
+        StructureType::Extension => 1,
+        StructureType::Tower => 2,
+        StructureType::Storage => 3,
+        StructureType::Container => 4,
+        StructureType::Link => 5,
+        _ => 6,
+    }
+}
+
+/// Tiles within `range` of the given one
+fn around(xy: RoomXY, range: u8) -> Vec<RoomXY> {
+    terrain::all_tiles()
+        .filter(|other| other.get_range_to(xy) <= range)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+
+    fn world_with_spawn() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 8, test_room()), 1);
+        world.add_source(position(10, 12, test_room()));
+        world.add_source(position(40, 38, test_room()));
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world
+    }
+
+    #[test]
+    fn first_links_pair_a_source_with_the_controller() {
+        let world = world_with_spawn();
+        let view = world.room(test_room()).unwrap();
+
+        let plan = plan_room(&world, view.as_ref()).unwrap();
+        let first_links: Vec<RoomXY> = plan
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Link && planned.level == 5)
+            .map(|planned| planned.xy)
+            .collect();
+        assert_eq!(first_links.len(), 2);
+        let controller = position(25, 8, test_room()).xy();
+        let near_controller = first_links
+            .iter()
+            .filter(|xy| xy.get_range_to(controller) <= UPGRADE_RANGE + 1)
+            .count();
+        assert_eq!(near_controller, 1);
+    }
+
+    #[test]
+    fn spawns_are_planned_in_the_core_as_they_unlock() {
+        let world = world_with_spawn();
+        let view = world.room(test_room()).unwrap();
+
+        let plan = plan_room(&world, view.as_ref()).unwrap();
+        let spawns: Vec<&PlannedStructure> = plan
+            .structures
+            .iter()
+            .filter(|planned| planned.structure_type == StructureType::Spawn)
+            .collect();
+        let levels: Vec<u8> = spawns.iter().map(|planned| planned.level).collect();
+        assert_eq!(levels, vec![1, 7, 8]);
+        // The first one is the spawn we planned around
+        let anchor = position(25, 25, test_room()).xy();
+        assert_eq!(spawns[0].xy, anchor);
+        assert!(spawns.iter().all(|planned| planned.xy.get_range_to(anchor) <= 4));
+    }
+}
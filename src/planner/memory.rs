@@ -0,0 +1,33 @@
+use super::RoomPlan;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value, to_value};
+use wasm_bindgen::JsValue;
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RoomMemory {
+    // Where everything in the room goes, see the planner
+    #[serde(default)]
+    plan: Option<RoomPlan>,
+}
+
+impl RoomMemory {
+    pub fn plan(&self) -> Option<&RoomPlan> {
+        self.plan.as_ref()
+    }
+
+    pub fn set_plan(&mut self, plan: RoomPlan) {
+        self.plan = Some(plan);
+    }
+}
+
+impl From<JsValue> for RoomMemory {
+    fn from(js_value: JsValue) -> Self {
+        from_value(js_value).unwrap_or_default()
+    }
+}
+
+impl From<RoomMemory> for JsValue {
+    fn from(memory: RoomMemory) -> Self {
+        to_value(&memory).expect("Failed to convert RoomMemory to JsValue")
+    }
+}
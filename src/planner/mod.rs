@@ -0,0 +1,193 @@
+// Lays out our rooms and places construction sites as the controller level unlocks them.
+// A room is planned the first time we see it with a spawn (see layout), and the plan is kept in
+// room memory so it survives global resets. Deleting it from memory makes the room replan.
+mod layout;
+mod memory;
+
+pub use memory::RoomMemory;
+
+use crate::world::{RoomView, World};
+use log::info;
+use screeps::constants::MAX_CONSTRUCTION_SITES;
+use screeps::{RoomName, RoomXY, StructureType};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+// Most construction sites a room has at once, so its builders aren't spread too thin
+const MAX_ROOM_SITES: usize = 5;
+
+/// Everything we want built in a room, in the order it should be built
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RoomPlan {
+    pub structures: Vec<PlannedStructure>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+pub struct PlannedStructure {
+    pub structure_type: StructureType,
+    pub xy: RoomXY,
+    // Controller level at which it gets built
+    pub level: u8,
+}
+
+/// Keeps the plans of our rooms, so they are only read from memory after a global reset
+#[derive(Default)]
+pub struct Planner {
+    plans: HashMap<RoomName, RoomPlan>,
+}
+
+impl Planner {
+    pub fn new() -> Self {
+        Planner::default()
+    }
+
+    /// Plan any new rooms, and place the sites each room has unlocked
+    pub fn run(&mut self, world: &dyn World) {
+        let mut total_sites: usize = world
+            .rooms()
+            .iter()
+            .map(|room| room.construction_sites().len())
+            .sum();
+        for room in world.rooms() {
+            let Some(controller) = room.controller().filter(|controller| controller.my) else {
+                continue;
+            };
+            let plan = match self.plans.entry(room.name()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match load_or_plan(world, room.as_ref()) {
+                    Some(plan) => entry.insert(plan),
+                    None => continue,
+                },
+            };
+            let budget = (MAX_CONSTRUCTION_SITES as usize).saturating_sub(total_sites);
+            total_sites += place_sites(room.as_ref(), plan, controller.level, budget);
+        }
+    }
+}
+
+/// The room's plan from memory, or a new one if it hasn't been planned yet
+fn load_or_plan(world: &dyn World, room: &dyn RoomView) -> Option<RoomPlan> {
+    let mut memory = room.memory();
+    if let Some(plan) = memory.plan() {
+        return Some(plan.clone());
+    }
+    let plan = layout::plan_room(world, room)?;
+    info!(
+        "Planned {} structures in {}",
+        plan.structures.len(),
+        room.name()
+    );
+    memory.set_plan(plan.clone());
+    room.set_memory(memory);
+    Some(plan)
+}
+
+/// Place sites for planned structures the controller level has unlocked, at most `budget` of
+/// them. Returns how many were placed
+fn place_sites(room: &dyn RoomView, plan: &RoomPlan, level: u8, budget: usize) -> usize {
+    let sites = room.construction_sites();
+    let free = MAX_ROOM_SITES.saturating_sub(sites.len()).min(budget);
+    if free == 0 {
+        return 0;
+    }
+    let structures = room.structures();
+    let mut placed = 0;
+    for planned in plan
+        .structures
+        .iter()
+        .filter(|planned| planned.level <= level)
+    {
+        if placed >= free {
+            break;
+        }
+        let built = structures
+            .iter()
+            .any(|s| s.pos.xy() == planned.xy && s.structure_type == planned.structure_type);
+        if built || sites.iter().any(|site| site.pos.xy() == planned.xy) {
+            continue;
+        }
+        // Anything in the way (e.g. a structure placed by hand) just leaves the tile unbuilt
+        if room
+            .create_construction_site(planned.xy, planned.structure_type)
+            .is_ok()
+        {
+            placed += 1;
+        }
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, Intent, MockWorld};
+
+    fn planned(structure_type: StructureType, x: u8, level: u8) -> PlannedStructure {
+        PlannedStructure {
+            structure_type,
+            xy: position(x, 20, test_room()).xy(),
+            level,
+        }
+    }
+
+    fn world_with_controller(level: u8) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 10, test_room()), level);
+        world
+    }
+
+    fn placed(world: &MockWorld) -> Vec<StructureType> {
+        world
+            .take_intents()
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::CreateConstructionSite { structure_type, .. } => Some(structure_type),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sites_are_only_placed_once_the_level_unlocks_them() {
+        let world = world_with_controller(2);
+        let plan = RoomPlan {
+            structures: vec![
+                planned(StructureType::Road, 10, 3),
+                planned(StructureType::Container, 11, 2),
+                planned(StructureType::Road, 12, 1),
+            ],
+        };
+        let room = world.room(test_room()).unwrap();
+
+        assert_eq!(place_sites(room.as_ref(), &plan, 2, usize::MAX), 2);
+        assert_eq!(placed(&world), vec![StructureType::Container, StructureType::Road]);
+    }
+
+    #[test]
+    fn sites_are_capped_per_room_and_overall() {
+        let mut world = world_with_controller(3);
+        let plan = RoomPlan {
+            structures: (10..20).map(|x| planned(StructureType::Road, x, 1)).collect(),
+        };
+        {
+            let room = world.room(test_room()).unwrap();
+            assert_eq!(place_sites(room.as_ref(), &plan, 3, usize::MAX), MAX_ROOM_SITES);
+            assert_eq!(place_sites(room.as_ref(), &plan, 3, 2), 2);
+            assert_eq!(place_sites(room.as_ref(), &plan, 3, 0), 0);
+        }
+
+        // Sites already in the room count towards its cap, and aren't placed again
+        world.add_construction_site(StructureType::Road, position(10, 20, test_room()));
+        world.add_construction_site(StructureType::Road, position(11, 20, test_room()));
+        world.take_intents();
+        let room = world.room(test_room()).unwrap();
+        assert_eq!(place_sites(room.as_ref(), &plan, 3, usize::MAX), MAX_ROOM_SITES - 2);
+        let first = world.take_intents();
+        assert!(matches!(
+            first[0],
+            Intent::CreateConstructionSite { pos, .. } if pos == position(12, 20, test_room())
+        ));
+    }
+}
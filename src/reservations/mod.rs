@@ -0,0 +1,165 @@
+// Registry of what creeps have claimed, so they stop piling onto the same target.
+// States reserve the resources they are going to deliver or withdraw when they start, and the
+// reservation is released when the state exits or the creep dies. Controllers subtract what is
+// already reserved when choosing targets.
+// Like the state controllers this lives in wasm memory, so it lasts across ticks but not global
+// resets. Restored states reserve again when their controller is rebuilt.
+use screeps::{RawObjectId, ResourceType};
+use std::cell::RefCell;
+
+thread_local! {
+    static RESERVATIONS: RefCell<Reservations> = RefCell::new(Reservations::default());
+}
+
+/// Whether the creep is bringing resources to the target, or taking them away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReservationKind {
+    Deliver,
+    Withdraw,
+}
+
+#[derive(Debug, Clone)]
+struct Reservation {
+    creep: String,
+    target: RawObjectId,
+    kind: ReservationKind,
+    // None if the creep will take or bring any resource
+    resource: Option<ResourceType>,
+    amount: u32,
+}
+
+/// See the module docs
+#[derive(Debug, Default)]
+pub struct Reservations {
+    reservations: Vec<Reservation>,
+}
+
+impl Reservations {
+    /// Reserve an amount of resource against the target, or of any resource if it is None.
+    /// A creep only holds one reservation at a time, so this replaces any it already had
+    pub fn reserve(
+        &mut self,
+        creep: &str,
+        target: RawObjectId,
+        kind: ReservationKind,
+        resource: Option<ResourceType>,
+        amount: u32,
+    ) {
+        self.release(creep);
+        self.reservations.push(Reservation {
+            creep: creep.to_string(),
+            target,
+            kind,
+            resource,
+            amount,
+        });
+    }
+
+    /// Release anything the creep has reserved
+    pub fn release(&mut self, creep: &str) {
+        self.reservations
+            .retain(|reservation| reservation.creep != creep);
+    }
+
+    /// Total amount of the resource reserved against the target, including reservations of any
+    /// resource
+    pub fn reserved(
+        &self,
+        target: RawObjectId,
+        kind: ReservationKind,
+        resource: ResourceType,
+    ) -> u32 {
+        self.reservations
+            .iter()
+            .filter(|r| {
+                r.target == target
+                    && r.kind == kind
+                    && r.resource.is_none_or(|reserved| reserved == resource)
+            })
+            .map(|r| r.amount)
+            .sum()
+    }
+
+    /// Drop reservations of any creep not in the list, i.e. creeps that have died
+    pub fn retain_creeps(&mut self, creeps: &[String]) {
+        self.reservations
+            .retain(|reservation| creeps.contains(&reservation.creep));
+    }
+}
+
+/// Run a function with the reservations registry
+pub fn with_reservations<R>(f: impl FnOnce(&mut Reservations) -> R) -> R {
+    RESERVATIONS.with(|reservations| f(&mut reservations.borrow_mut()))
+}
+
+/// See [`Reservations::reserve`]
+pub fn reserve(
+    creep: &str,
+    target: RawObjectId,
+    kind: ReservationKind,
+    resource: Option<ResourceType>,
+    amount: u32,
+) {
+    with_reservations(|reservations| reservations.reserve(creep, target, kind, resource, amount));
+}
+
+/// See [`Reservations::release`]
+pub fn release(creep: &str) {
+    with_reservations(|reservations| reservations.release(creep));
+}
+
+/// See [`Reservations::reserved`]
+pub fn reserved(target: RawObjectId, kind: ReservationKind, resource: ResourceType) -> u32 {
+    with_reservations(|reservations| reservations.reserved(target, kind, resource))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::MockWorld;
+
+    #[test]
+    fn reservations_add_up_per_target_kind_and_resource() {
+        let world = MockWorld::new();
+        let (container, spawn) = (world.next_id(), world.next_id());
+        let energy = Some(ResourceType::Energy);
+        let mut reservations = Reservations::default();
+        reservations.reserve("a", container, ReservationKind::Withdraw, energy, 50);
+        reservations.reserve("b", container, ReservationKind::Withdraw, None, 30);
+        reservations.reserve("c", container, ReservationKind::Deliver, energy, 20);
+        reservations.reserve("d", spawn, ReservationKind::Deliver, energy, 100);
+
+        let withdrawn = |reservations: &Reservations, resource| {
+            reservations.reserved(container, ReservationKind::Withdraw, resource)
+        };
+        assert_eq!(withdrawn(&reservations, ResourceType::Energy), 80);
+        // Reservations of any resource count against every resource
+        assert_eq!(withdrawn(&reservations, ResourceType::Hydrogen), 30);
+        assert_eq!(
+            reservations.reserved(container, ReservationKind::Deliver, ResourceType::Energy),
+            20
+        );
+    }
+
+    #[test]
+    fn creeps_hold_one_reservation_until_released_or_dead() {
+        let world = MockWorld::new();
+        let (first, second) = (world.next_id(), world.next_id());
+        let mut reservations = Reservations::default();
+        let energy = |reservations: &Reservations, target| {
+            reservations.reserved(target, ReservationKind::Deliver, ResourceType::Energy)
+        };
+        reservations.reserve("a", first, ReservationKind::Deliver, None, 50);
+        reservations.reserve("a", second, ReservationKind::Deliver, None, 50);
+        assert_eq!(energy(&reservations, first), 0);
+        assert_eq!(energy(&reservations, second), 50);
+
+        reservations.release("a");
+        assert_eq!(energy(&reservations, second), 0);
+
+        reservations.reserve("a", first, ReservationKind::Deliver, None, 50);
+        reservations.reserve("b", first, ReservationKind::Deliver, None, 50);
+        reservations.retain_creeps(&["b".to_string()]);
+        assert_eq!(energy(&reservations, first), 50);
+    }
+}
@@ -0,0 +1,127 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::movement::{self, MoveOptions, MovePriority};
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::{
+    local::{ObjectId, RawObjectId},
+    objects::Creep,
+    Position,
+};
+
+// Ticks without getting closer before giving up on the post or target
+const MOVE_TIMEOUT: u32 = 5;
+
+/// Melee attack a hostile creep
+pub struct AttackState {
+    target: ObjectId<Creep>,
+    // Rampart to hold while fighting, instead of chasing the target
+    post: Option<Position>,
+}
+
+impl AttackState {
+    pub fn new(target: ObjectId<Creep>, post: Option<Position>) -> Self {
+        AttackState { target, post }
+    }
+}
+
+impl ScreepState for AttackState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("⚔️");
+        self.update_state_memory(creep);
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::Attack
+    }
+
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.target.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        let Some(target) = world.hostile(self.target) else {
+            return TickResult::Exit;
+        };
+        if target.pos.room_name() != creep.pos().room_name() {
+            return TickResult::Exit;
+        }
+
+        // Fighters walk up to hostiles rather than around them, and go first. If they can't get
+        // there quickly the controller picks another post or target
+        let options = |range| {
+            MoveOptions::new(range)
+                .avoid_hostiles(false)
+                .priority(MovePriority::High)
+                .timeout(MOVE_TIMEOUT)
+        };
+        let moved = match self.post {
+            Some(post) if creep.pos() != post => {
+                movement::move_with(creep, world, post, options(0))
+            }
+            None if !creep.pos().is_near_to(target.pos) => {
+                movement::move_with(creep, world, target.pos, options(1))
+            }
+            _ => Ok(()),
+        };
+
+        // Hit the target if we can reach it, otherwise whatever else has come close
+        let victim = if creep.pos().is_near_to(target.pos) {
+            Some(target.id)
+        } else {
+            world.room(creep.pos().room_name()).and_then(|room| {
+                room.hostile_creeps()
+                    .into_iter()
+                    .find(|hostile| creep.pos().is_near_to(hostile.pos))
+                    .map(|hostile| hostile.id)
+            })
+        };
+        if let Some(victim) = victim {
+            if let Err(e) = creep.attack(victim) {
+                warn!("couldn't attack: {:?}", e);
+                return TickResult::Exit;
+            }
+        }
+        if moved.is_err() {
+            return TickResult::Exit;
+        }
+        // Holding a post the target has moved away from, let the controller pick a new one
+        if victim.is_none() && self.post == Some(creep.pos()) {
+            return TickResult::Exit;
+        }
+        TickResult::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, MockWorld};
+    use screeps::Part;
+
+    #[test]
+    fn leaves_a_post_with_nothing_in_reach() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let post = position(25, 20, test_room());
+        world.add_creep(
+            "defender",
+            post,
+            vec![Part::Attack, Part::Move],
+            CreepMemory::new(Specialisation::Defender),
+        );
+        let hostile =
+            world.add_hostile("Invader", position(25, 22, test_room()), vec![Part::Attack]);
+        let creep = world.creeps()[0].clone();
+
+        let result = AttackState::new(hostile, Some(post)).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+
+        drop(creep);
+        world.room_mut(test_room()).hostiles[0].pos = position(25, 21, test_room());
+        let creep = world.creeps()[0].clone();
+        let result = AttackState::new(hostile, Some(post)).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Continue));
+    }
+}
@@ -1,61 +1,85 @@
 use super::{ScreepState, StateName, TickResult};
+use crate::reservations::{self, ReservationKind};
+use crate::movement;
+use crate::world::{CreepView, World};
 use log::warn;
-use screeps::action_error_codes::BuildErrorCode;
 use screeps::{
     constants::ResourceType,
-    objects::{ConstructionSite, Creep},
-    prelude::*,
-    HasPosition,
+    local::{ObjectId, RawObjectId},
+    objects::ConstructionSite,
+    ErrorCode,
 };
 
 pub struct BuildState {
-    construction_site: ConstructionSite,
+    construction_site: ObjectId<ConstructionSite>,
 }
 
 impl BuildState {
-    pub fn new(construction_site: ConstructionSite) -> Self {
+    pub fn new(construction_site: ObjectId<ConstructionSite>) -> Self {
         BuildState { construction_site }
     }
 }
 
 impl ScreepState for BuildState {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("⚒️", false);
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("⚒️");
         self.update_state_memory(creep);
+        self.reserve(creep);
+    }
+
+    fn reserve(&self, creep: &dyn CreepView) {
+        reservations::reserve(
+            &creep.name(),
+            self.construction_site.into(),
+            ReservationKind::Deliver,
+            Some(ResourceType::Energy),
+            creep.store().get_used_capacity(Some(ResourceType::Energy)),
+        );
     }
 
     fn get_state_name(&self) -> StateName {
         StateName::Build
     }
 
-    fn tick(&self, creep: &Creep) -> TickResult {
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.construction_site.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
         if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
             return TickResult::Exit;
         }
+        let Some(construction_site) = world.construction_site(self.construction_site) else {
+            return TickResult::Exit;
+        };
         // Move to construction site. THis is to free up the resource source if others wanna get in
-        if !creep.pos().is_near_to(self.construction_site.pos()) {
-            let _ = creep.move_to(self.construction_site.pos());
+        if !creep.pos().is_near_to(construction_site.pos) {
+            if movement::move_to(creep, world, construction_site.pos, 1).is_err() {
+                return TickResult::Exit;
+            }
             return TickResult::Continue;
         }
 
-        match creep.build(&self.construction_site) {
+        match creep.build(self.construction_site) {
             Ok(_) => {
                 // Successfully built
                 TickResult::Continue
             }
             Err(e) => {
                 // Handle the error based on the error code
-                return match e {
-                    BuildErrorCode::NotInRange => {
-                        let _ = creep.move_to(self.construction_site.clone());
+                match e {
+                    ErrorCode::NotInRange => {
                         warn!("creep {} is not in range to build", creep.name());
-                        TickResult::Continue
+                        match movement::move_to(creep, world, construction_site.pos, 3) {
+                            Ok(()) => TickResult::Continue,
+                            Err(_) => TickResult::Exit,
+                        }
                     }
                     _ => {
                         warn!("couldn't upgrade: {:?}", e);
                         TickResult::Exit
                     }
-                };
+                }
             }
         }
     }
@@ -0,0 +1,258 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::movement;
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::{
+    constants::ResourceType,
+    local::{ObjectId, RawObjectId},
+    objects::Source,
+};
+
+// The container is patched up once it has lost this fraction of its hits
+const CONTAINER_REPAIR_THRESHOLD: f64 = 0.75;
+
+/// Park on the container next to the source and harvest every tick. Whatever the creep can't
+/// hold falls into the container, or is passed into a link next to it if there is one. Once the
+/// container has decayed far enough the creep repairs it with what it holds instead of
+/// harvesting, taking energy back out of the container if the source has run dry
+pub struct DropMineState {
+    source: ObjectId<Source>,
+    container: RawObjectId,
+}
+
+impl DropMineState {
+    pub fn new(source: ObjectId<Source>, container: RawObjectId) -> Self {
+        DropMineState { source, container }
+    }
+}
+
+impl ScreepState for DropMineState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("⛏️");
+        self.update_state_memory(creep);
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::DropMine
+    }
+
+    // The source is kept in the miner's role data, so only the container needs persisting
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.container)
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        let Some(container) = world.structure(self.container) else {
+            return TickResult::Exit;
+        };
+        let Some(source) = world.source(self.source) else {
+            return TickResult::Exit;
+        };
+        if creep.pos() != container.pos {
+            if movement::move_to(creep, world, container.pos, 0).is_err() {
+                return TickResult::Exit;
+            }
+            return TickResult::Continue;
+        }
+
+        let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
+        let needs_repair =
+            (container.hits as f64) < container.hits_max as f64 * CONTAINER_REPAIR_THRESHOLD;
+        if needs_repair && energy > 0 {
+            // Repairing takes the place of harvesting this tick
+            if let Err(e) = creep.repair(self.container) {
+                warn!(
+                    "creep {} couldn't repair its container: {:?}",
+                    creep.name(),
+                    e
+                );
+            }
+            return TickResult::Continue;
+        }
+
+        let link = find_link(creep, world);
+        if source.energy > 0 {
+            if let Err(e) = creep.harvest(self.source) {
+                warn!("creep {} couldn't harvest: {:?}", creep.name(), e);
+                return TickResult::Exit;
+            }
+            // Hand what has been mined to the link, so it doesn't need hauling
+            if let Some(link) = link.filter(|_| energy > 0) {
+                if let Err(e) = creep.transfer(link, ResourceType::Energy, None) {
+                    warn!("creep {} couldn't fill its link: {:?}", creep.name(), e);
+                }
+            }
+            return TickResult::Continue;
+        }
+
+        // Nothing to harvest until the source regenerates
+        let has_energy = container
+            .store()
+            .get_used_capacity(Some(ResourceType::Energy))
+            > 0;
+        if needs_repair {
+            // Take back some of what was mined to repair the container with
+            if has_energy {
+                if let Err(e) = creep.withdraw(self.container, ResourceType::Energy, None) {
+                    warn!(
+                        "creep {} couldn't take back energy to repair: {:?}",
+                        creep.name(),
+                        e
+                    );
+                }
+            }
+        } else if let Some(link) = link {
+            // Or send on whatever was left in the container before the link was built
+            let result = if energy > 0 {
+                creep.transfer(link, ResourceType::Energy, None)
+            } else if has_energy {
+                creep.withdraw(self.container, ResourceType::Energy, None)
+            } else {
+                Ok(())
+            };
+            if let Err(e) = result {
+                warn!(
+                    "creep {} couldn't empty its container: {:?}",
+                    creep.name(),
+                    e
+                );
+            }
+        }
+
+        TickResult::Continue
+    }
+}
+
+/// A link next to the creep with room for more energy
+fn find_link(creep: &dyn CreepView, world: &dyn World) -> Option<RawObjectId> {
+    let room = world.room(creep.pos().room_name())?;
+    let link = room.links().into_iter().find(|link| {
+        link.pos().is_near_to(creep.pos())
+            && link.store().get_free_capacity(Some(ResourceType::Energy)) > 0
+    })?;
+    Some(link.id().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::{Part, StructureType};
+
+    /// A miner parked on the container next to a source
+    fn world_with_parked_miner() -> (MockWorld, ObjectId<Source>, RawObjectId) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let source = world.add_source(position(10, 10, test_room()));
+        let container = world.add_structure(
+            StructureType::Container,
+            position(11, 11, test_room()),
+            Some(StoreInfo::new(2000).with(ResourceType::Energy, 500)),
+        );
+        world.add_creep(
+            "miner",
+            position(11, 11, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Miner),
+        );
+        (world, source, container)
+    }
+
+    #[test]
+    fn harvests_every_tick_and_fills_an_adjacent_link() {
+        let (mut world, source, container) = world_with_parked_miner();
+        let link = world.add_structure(
+            StructureType::Link,
+            position(12, 12, test_room()),
+            Some(StoreInfo::restricted(800, ResourceType::Energy)),
+        );
+        world.creep_mut("miner").store = StoreInfo::new(50).with(ResourceType::Energy, 20);
+        let creep = world.creeps()[0].clone();
+
+        let state = DropMineState::new(source, container);
+        assert!(matches!(
+            state.tick(creep.as_ref(), &world),
+            TickResult::Continue
+        ));
+        let intents = world.take_intents();
+        assert!(intents.contains(&Intent::Harvest {
+            creep: "miner".to_string(),
+            source,
+        }));
+        assert!(intents.contains(&Intent::Transfer {
+            creep: "miner".to_string(),
+            target: link,
+            resource: ResourceType::Energy,
+            amount: None,
+        }));
+    }
+
+    #[test]
+    fn repairs_the_container_while_the_source_is_empty() {
+        let (mut world, source, container) = world_with_parked_miner();
+        world.room_mut(test_room()).sources[0].energy = 0;
+        world.room_mut(test_room()).structures[0].hits = 100;
+        let creep = world.creeps()[0].clone();
+        let state = DropMineState::new(source, container);
+
+        // Takes some of what was mined back out first
+        state.tick(creep.as_ref(), &world);
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Withdraw {
+                creep: "miner".to_string(),
+                target: container,
+                resource: ResourceType::Energy,
+                amount: None,
+            }]
+        );
+
+        drop(creep);
+        world.creep_mut("miner").store = StoreInfo::new(50).with(ResourceType::Energy, 50);
+        let creep = world.creeps()[0].clone();
+        state.tick(creep.as_ref(), &world);
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Repair {
+                creep: "miner".to_string(),
+                structure: container,
+            }]
+        );
+    }
+
+    #[test]
+    fn repairs_the_container_instead_of_harvesting_once_it_has_decayed() {
+        let (mut world, source, container) = world_with_parked_miner();
+        world.room_mut(test_room()).structures[0].hits = 700;
+        world.creep_mut("miner").store = StoreInfo::new(50).with(ResourceType::Energy, 10);
+        let creep = world.creeps()[0].clone();
+        let state = DropMineState::new(source, container);
+
+        assert!(matches!(
+            state.tick(creep.as_ref(), &world),
+            TickResult::Continue
+        ));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Repair {
+                creep: "miner".to_string(),
+                structure: container,
+            }]
+        );
+
+        // Still in good enough repair, so keep mining
+        drop(creep);
+        world.room_mut(test_room()).structures[0].hits = 800;
+        let creep = world.creeps()[0].clone();
+        state.tick(creep.as_ref(), &world);
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Harvest {
+                creep: "miner".to_string(),
+                source,
+            }]
+        );
+    }
+}
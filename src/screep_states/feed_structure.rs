@@ -1,50 +1,146 @@
 use super::{ScreepState, StateName, TickResult};
-use screeps::action_error_codes::TransferErrorCode;
-use screeps::{constants::ResourceType, local::ObjectId, objects::Creep, prelude::*};
-use wasm_bindgen::JsCast;
+use crate::reservations::{self, ReservationKind};
+use crate::movement;
+use crate::world::{CreepView, World};
+use screeps::{constants::ResourceType, local::RawObjectId, ErrorCode};
 
-pub struct FeedStructureState<T: Transferable + MaybeHasId + JsCast> {
-    structure: ObjectId<T>,
+/// Deliver a resource to a structure, or every resource the creep carries in turn if it is None
+pub struct FeedStructureState {
+    structure: RawObjectId,
+    resource: Option<ResourceType>,
 }
 
-impl<T: Transferable + MaybeHasId + JsCast> FeedStructureState<T> {
-    pub fn new(structure: ObjectId<T>) -> Self {
-        FeedStructureState { structure }
+impl FeedStructureState {
+    pub fn new(structure: RawObjectId, resource: Option<ResourceType>) -> Self {
+        FeedStructureState { structure, resource }
+    }
+
+    /// The resource to transfer next, None once the creep has nothing left to deliver
+    fn next_resource(&self, creep: &dyn CreepView) -> Option<ResourceType> {
+        match self.resource {
+            Some(resource) => {
+                (creep.store().get_used_capacity(Some(resource)) > 0).then_some(resource)
+            }
+            None => creep.store().store_types().into_iter().next(),
+        }
     }
 }
 
-impl<T: Transferable + MaybeHasId + JsCast> ScreepState for FeedStructureState<T> {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("🍋", false);
+impl ScreepState for FeedStructureState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("🍋");
         self.update_state_memory(creep);
+        self.reserve(creep);
+    }
+
+    fn reserve(&self, creep: &dyn CreepView) {
+        reservations::reserve(
+            &creep.name(),
+            self.structure,
+            ReservationKind::Deliver,
+            self.resource,
+            creep.store().get_used_capacity(self.resource),
+        );
     }
 
     fn get_state_name(&self) -> StateName {
         StateName::FeedStructure
     }
 
-    fn tick(&self, creep: &Creep) -> TickResult {
-        if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.structure)
+    }
+
+    fn get_resource(&self) -> Option<ResourceType> {
+        self.resource
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        let Some(resource) = self.next_resource(creep) else {
             return TickResult::Exit;
-        }
-        let Some(structure) = self.structure.resolve() else {
+        };
+        let Some(structure) = world.structure(self.structure) else {
             return TickResult::Exit;
         };
-        match creep.transfer(&structure, ResourceType::Energy, None) {
+        match creep.transfer(self.structure, resource, None) {
             Ok(_) => {
                 // Successfully transferred to the structure
                 TickResult::Continue
             }
             Err(e) => {
                 // Handle the error based on the error code
-                return match e {
-                    TransferErrorCode::NotInRange => {
-                        let _ = creep.move_to(&structure);
-                        TickResult::Continue
+                match e {
+                    ErrorCode::NotInRange => {
+                        match movement::move_to(creep, world, structure.pos, 1) {
+                            Ok(()) => TickResult::Continue,
+                            Err(_) => TickResult::Exit,
+                        }
                     }
                     _ => TickResult::Exit,
-                };
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::{Part, StructureType};
+
+    /// A creep carrying energy and a hydrogen, and a storage `range` tiles away
+    fn world_with_storage(range: u8) -> (MockWorld, RawObjectId) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let storage = world.add_structure(
+            StructureType::Storage,
+            position(20 + range, 20, test_room()),
+            Some(StoreInfo::new(1_000_000)),
+        );
+        world.add_creep(
+            "hauler",
+            position(20, 20, test_room()),
+            vec![Part::Carry, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world.creep_mut("hauler").store = StoreInfo::new(100)
+            .with(ResourceType::Energy, 50)
+            .with(ResourceType::Hydrogen, 50);
+        (world, storage)
+    }
+
+    #[test]
+    fn delivers_only_the_given_resource() {
+        let (world, storage) = world_with_storage(1);
+        let creep = world.creeps()[0].clone();
+
+        let state = FeedStructureState::new(storage, Some(ResourceType::Hydrogen));
+        assert!(matches!(state.tick(creep.as_ref(), &world), TickResult::Continue));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Transfer {
+                creep: "hauler".to_string(),
+                target: storage,
+                resource: ResourceType::Hydrogen,
+                amount: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn moves_towards_a_structure_out_of_range() {
+        let (world, storage) = world_with_storage(5);
+        let creep = world.creeps()[0].clone();
+
+        let state = FeedStructureState::new(storage, None);
+        assert!(matches!(state.tick(creep.as_ref(), &world), TickResult::Continue));
+        movement::resolve_traffic(&world);
+        assert!(world
+            .take_intents()
+            .iter()
+            .any(|intent| matches!(intent, Intent::MoveDirection { .. })));
+    }
+}
@@ -1,26 +1,40 @@
 use super::{ScreepState, StateName, TickResult};
+use crate::movement;
+use crate::world::{CreepView, World};
 use log::warn;
 use screeps::{
     constants::ResourceType,
-    local::ObjectId,
-    objects::{Creep, Source},
-    prelude::*,
+    local::{ObjectId, RawObjectId},
+    objects::Source,
 };
 
 /// Harvest energy from the source
 pub struct HarvestState {
     source: ObjectId<Source>,
+    // Wait next to the source while it is empty, rather than exiting
+    wait_for_regen: bool,
 }
 
 impl HarvestState {
     pub fn new(source: ObjectId<Source>) -> Self {
-        HarvestState { source }
+        HarvestState {
+            source,
+            wait_for_regen: false,
+        }
+    }
+
+    /// Harvest from the source, waiting next to it if it is empty until it regenerates
+    pub fn new_waiting(source: ObjectId<Source>) -> Self {
+        HarvestState {
+            source,
+            wait_for_regen: true,
+        }
     }
 }
 
 impl ScreepState for HarvestState {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("⚡", false);
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("⚡");
         self.update_state_memory(creep);
     }
 
@@ -28,24 +42,92 @@ impl ScreepState for HarvestState {
         StateName::Harvest
     }
 
-    fn tick(&self, creep: &Creep) -> TickResult {
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.source.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
         // Check if we have any free capacity to harvest energy
         if creep.store().get_free_capacity(Some(ResourceType::Energy)) == 0 {
             return TickResult::Exit;
         }
-        let Some(source) = self.source.resolve() else {
+        let Some(source) = world.source(self.source) else {
             return TickResult::Exit;
         };
 
-        if creep.pos().is_near_to(source.pos()) {
-            if creep.harvest(&source).is_err() {
+        if creep.pos().is_near_to(source.pos) {
+            if source.energy == 0 && self.wait_for_regen {
+                return TickResult::Continue;
+            }
+            if creep.harvest(self.source).is_err() {
                 warn!("couldn't harvest for some unknown reason");
                 return TickResult::Exit;
             };
-        } else {
-            let _ = creep.move_to(&source);
+        } else if movement::move_to(creep, world, source.pos, 1).is_err() {
+            return TickResult::Exit;
         }
 
         TickResult::Continue
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::Part;
+
+    fn world_next_to_source() -> (MockWorld, ObjectId<Source>) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let source = world.add_source(position(10, 10, test_room()));
+        world.add_creep(
+            "miner",
+            position(11, 10, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Miner),
+        );
+        (world, source)
+    }
+
+    #[test]
+    fn harvests_the_source_when_next_to_it() {
+        let (world, source) = world_next_to_source();
+        let creep = world.creeps()[0].clone();
+
+        let result = HarvestState::new(source).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Continue));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Harvest {
+                creep: "miner".to_string(),
+                source,
+            }]
+        );
+    }
+
+    #[test]
+    fn waits_at_an_empty_source_only_when_asked_to() {
+        let (mut world, source) = world_next_to_source();
+        world.room_mut(test_room()).sources[0].energy = 0;
+        let creep = world.creeps()[0].clone();
+
+        let waiting = HarvestState::new_waiting(source).tick(creep.as_ref(), &world);
+        assert!(matches!(waiting, TickResult::Continue));
+        let result = HarvestState::new(source).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+    }
+
+    #[test]
+    fn exits_once_full() {
+        let (mut world, source) = world_next_to_source();
+        world.creep_mut("miner").store = StoreInfo::new(50).with(ResourceType::Energy, 50);
+        let creep = world.creeps()[0].clone();
+
+        let result = HarvestState::new(source).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+        assert!(world.take_intents().is_empty());
+    }
+}
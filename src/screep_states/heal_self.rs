@@ -0,0 +1,28 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::world::{CreepView, World};
+use log::warn;
+
+/// Heal ourselves back to full hits, for creeps with HEAL parts
+pub struct HealSelfState;
+
+impl ScreepState for HealSelfState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("🩹");
+        self.update_state_memory(creep);
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::HealSelf
+    }
+
+    fn tick(&self, creep: &dyn CreepView, _world: &dyn World) -> TickResult {
+        if creep.hits() >= creep.hits_max() {
+            return TickResult::Exit;
+        }
+        if let Err(e) = creep.heal(creep.id()) {
+            warn!("couldn't heal: {:?}", e);
+            return TickResult::Exit;
+        }
+        TickResult::Continue
+    }
+}
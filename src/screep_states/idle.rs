@@ -1,13 +1,13 @@
 use super::{ScreepState, StateName, TickResult};
-use screeps::objects::Creep;
+use crate::world::{CreepView, World};
 
 /// Idle state can be used as a fallback when no other state is applicable
 /// This state will do nothing and constantly search for a better state on each tick
 pub struct IdleState;
 
 impl ScreepState for IdleState {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("💤", false);
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("💤");
         self.update_state_memory(creep);
     }
 
@@ -15,7 +15,7 @@ impl ScreepState for IdleState {
         StateName::Idle
     }
 
-    fn tick(&self, _creep: &Creep) -> TickResult {
+    fn tick(&self, _creep: &dyn CreepView, _world: &dyn World) -> TickResult {
         // Do nothing, just idle until new state can be chosen
         TickResult::Exit
     }
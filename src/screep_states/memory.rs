@@ -1,25 +1,100 @@
+use crate::movement::Travel;
 use crate::screep_states::StateName;
 use crate::state_controllers::Specialisation;
-use serde::{Deserialize, Serialize};
+use screeps::{ObjectId, RawObjectId, ResourceType, Source};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_wasm_bindgen::{from_value, to_value};
 use wasm_bindgen::JsValue;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct CreepMemory {
     // What state is the creep currently in?
     current_state: StateName,
+    // Target of the current state, so it can be rebuilt after a global reset
+    #[serde(default)]
+    state_target: Option<RawObjectId>,
+    // Resource the current state is moving, None if it moves everything
+    #[serde(default)]
+    state_resource: Option<ResourceType>,
     // What specialisation is this creep?
     specialisation: Specialisation,
-    // Optional field for additional data - skipped during serde operations
-    additional_data: Option<String>,
+    // Data specific to the creep's specialisation
+    #[serde(default, deserialize_with = "deserialize_role_data")]
+    role_data: Option<RoleData>,
+    // Path the creep is following, see the movement module
+    #[serde(default)]
+    travel: Option<Travel>,
+    // Free-form data from older versions, only read so it can be migrated into role_data
+    #[serde(default, rename = "additional_data", skip_serializing)]
+    legacy_data: Option<String>,
+}
+
+/// Persisted data that only makes sense for a particular specialisation.
+/// Add a variant here when a specialisation needs to remember something between ticks
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub enum RoleData {
+    Miner {
+        // The source this miner is assigned to
+        source: ObjectId<Source>,
+    },
+    // Miners from older versions only remembered the index of their source in the room, which
+    // the miner swaps for the source's id the next time it runs
+    MinerIndex {
+        source_index: u8,
+    },
+}
+
+impl RoleData {
+    /// Convert the old `additional_data` string into role data, based on the specialisation
+    fn from_legacy(specialisation: &Specialisation, data: &str) -> Option<Self> {
+        match specialisation {
+            Specialisation::Miner => data
+                .parse()
+                .ok()
+                .map(|source_index| RoleData::MinerIndex { source_index }),
+            _ => None,
+        }
+    }
+}
+
+/// Role data written by older versions may not match the current layout. Miner source indexes
+/// are kept for the miner to migrate, anything else is dropped rather than losing the whole
+/// memory, and the creep's state controller works it out again
+fn deserialize_role_data<'de, D>(deserializer: D) -> Result<Option<RoleData>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    enum IndexedRoleData {
+        Miner { source_index: u8 },
+    }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredRoleData {
+        Current(RoleData),
+        Indexed(IndexedRoleData),
+        Outdated(IgnoredAny),
+    }
+    Ok(match Option::<StoredRoleData>::deserialize(deserializer)? {
+        Some(StoredRoleData::Current(data)) => Some(data),
+        Some(StoredRoleData::Indexed(IndexedRoleData::Miner { source_index })) => {
+            Some(RoleData::MinerIndex { source_index })
+        }
+        Some(StoredRoleData::Outdated(_)) | None => None,
+    })
 }
 
 impl CreepMemory {
     pub fn new(specialisation: Specialisation) -> Self {
         CreepMemory {
             current_state: StateName::Idle,
+            state_target: None,
+            state_resource: None,
             specialisation,
-            additional_data: None,
+            role_data: None,
+            travel: None,
+            legacy_data: None,
         }
     }
 
@@ -27,26 +102,62 @@ impl CreepMemory {
         &self.current_state
     }
 
+    pub fn state_target(&self) -> Option<RawObjectId> {
+        self.state_target
+    }
+
+    pub fn state_resource(&self) -> Option<ResourceType> {
+        self.state_resource
+    }
+
     pub fn specialisation(&self) -> &Specialisation {
         &self.specialisation
     }
 
-    pub fn additional_data(&self) -> Option<String> {
-        self.additional_data.clone()
+    pub fn role_data(&self) -> Option<&RoleData> {
+        self.role_data.as_ref()
+    }
+
+    pub fn travel(&self) -> Option<&Travel> {
+        self.travel.as_ref()
     }
 
     pub fn set_current_state(&mut self, state: StateName) {
         self.current_state = state;
     }
 
-    pub fn set_additional_data(&mut self, data: String) {
-        self.additional_data = Some(data);
+    pub fn set_state_target(&mut self, target: Option<RawObjectId>) {
+        self.state_target = target;
+    }
+
+    pub fn set_state_resource(&mut self, resource: Option<ResourceType>) {
+        self.state_resource = resource;
+    }
+
+    pub fn set_role_data(&mut self, data: RoleData) {
+        self.role_data = Some(data);
+    }
+
+    pub fn set_travel(&mut self, travel: Option<Travel>) {
+        self.travel = travel;
+    }
+
+    /// Move any old style `additional_data` into typed role data
+    fn migrate(mut self) -> Self {
+        if let Some(data) = self.legacy_data.take() {
+            if self.role_data.is_none() {
+                self.role_data = RoleData::from_legacy(&self.specialisation, &data);
+            }
+        }
+        self
     }
 }
 
 impl From<JsValue> for CreepMemory {
     fn from(js_value: JsValue) -> Self {
-        from_value(js_value).unwrap_or_else(|_| CreepMemory::new(Specialisation::Unknown))
+        from_value(js_value)
+            .map(CreepMemory::migrate)
+            .unwrap_or_else(|_| CreepMemory::new(Specialisation::Unknown))
     }
 }
 
@@ -55,3 +166,52 @@ impl From<CreepMemory> for JsValue {
         to_value(&memory).expect("Failed to convert CreepMemory to JsValue")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(json: &str) -> CreepMemory {
+        serde_json::from_str::<CreepMemory>(json)
+            .map(CreepMemory::migrate)
+            .unwrap()
+    }
+
+    #[test]
+    fn additional_data_migrates_to_role_data() {
+        let memory = load(
+            r#"{"current_state":"Harvest","specialisation":"Miner","additional_data":"1"}"#,
+        );
+        assert_eq!(
+            memory.role_data(),
+            Some(&RoleData::MinerIndex { source_index: 1 })
+        );
+
+        // Only miners ever stored anything there
+        let memory = load(
+            r#"{"current_state":"Idle","specialisation":"Hauler","additional_data":"1"}"#,
+        );
+        assert_eq!(memory.role_data(), None);
+    }
+
+    #[test]
+    fn indexed_miner_role_data_survives() {
+        let memory = load(
+            r#"{"current_state":"Idle","specialisation":"Miner","role_data":{"Miner":{"source_index":0}}}"#,
+        );
+        assert_eq!(
+            memory.role_data(),
+            Some(&RoleData::MinerIndex { source_index: 0 })
+        );
+        assert_eq!(memory.specialisation(), &Specialisation::Miner);
+    }
+
+    #[test]
+    fn unknown_role_data_is_dropped_without_losing_the_memory() {
+        let memory = load(
+            r#"{"current_state":"Upgrade","specialisation":"Miner","role_data":{"Miner":{"index":"a"}}}"#,
+        );
+        assert_eq!(memory.role_data(), None);
+        assert_eq!(memory.current_state(), &StateName::Upgrade);
+    }
+}
@@ -1,23 +1,39 @@
+pub use attack::AttackState;
 pub use build::BuildState;
+pub use drop_mine::DropMineState;
 pub use feed_structure::FeedStructureState;
 pub use harvest::HarvestState;
+pub use heal_self::HealSelfState;
 pub use idle::IdleState;
+pub use pickup::PickupState;
+use crate::reservations;
+use crate::tower_manager::RepairPolicy;
+use crate::world::{CreepView, World};
 use log::debug;
-use screeps::objects::Creep;
-use screeps::SharedCreepProperties;
+use screeps::{RawObjectId, ResourceType};
 use serde::{Deserialize, Serialize};
+pub use ranged_attack::{RangedAttackState, RANGED_ATTACK_RANGE};
+pub use recycle::RecycleState;
+pub use repair::RepairState;
 pub use upgrade::UpgradeState;
 pub use withdraw::WithdrawState;
 
+mod attack;
 mod build;
+mod drop_mine;
 mod feed_structure;
 mod harvest;
+mod heal_self;
 mod idle;
 mod memory;
+mod pickup;
+mod ranged_attack;
+mod recycle;
+mod repair;
 mod upgrade;
 mod withdraw;
 
-pub use memory::CreepMemory;
+pub use memory::{CreepMemory, RoleData};
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub enum StateName {
@@ -27,6 +43,13 @@ pub enum StateName {
     FeedStructure,
     Idle,
     Withdraw,
+    Attack,
+    RangedAttack,
+    HealSelf,
+    Recycle,
+    Repair,
+    Pickup,
+    DropMine,
 }
 
 impl From<StateName> for &'static str {
@@ -38,23 +61,32 @@ impl From<StateName> for &'static str {
             StateName::FeedStructure => "FeedStructure",
             StateName::Idle => "Idle",
             StateName::Withdraw => "Withdraw",
+            StateName::Attack => "Attack",
+            StateName::RangedAttack => "RangedAttack",
+            StateName::HealSelf => "HealSelf",
+            StateName::Recycle => "Recycle",
+            StateName::Repair => "Repair",
+            StateName::Pickup => "Pickup",
+            StateName::DropMine => "DropMine",
         }
     }
 }
 
 // What state is this screep in
 pub trait ScreepState {
-    fn update_state_memory(&self, creep: &Creep) {
-        let mut memory: CreepMemory = creep.memory().into();
+    fn update_state_memory(&self, creep: &dyn CreepView) {
+        let mut memory = creep.memory();
         memory.set_current_state(self.get_state_name());
-        creep.set_memory(&memory.into());
+        memory.set_state_target(self.get_target());
+        memory.set_state_resource(self.get_resource());
+        creep.set_memory(memory);
     }
 
     /// Called when the state is started, can be used to initialize counters or send messages
-    fn on_start(&self, creep: &Creep);
+    fn on_start(&self, creep: &dyn CreepView);
 
     /// Log the current state of the creep for debugging purposes
-    fn log_state(&self, creep: &Creep) {
+    fn log_state(&self, creep: &dyn CreepView) {
         let state_str: &'static str = self.get_state_name().into();
         debug!("-> Creep {} is in {} state.", creep.name(), state_str);
     }
@@ -62,12 +94,29 @@ pub trait ScreepState {
     /// Get the name of the state for logging purposes
     fn get_state_name(&self) -> StateName;
 
+    /// Get the id of the object this state is working on, if any.
+    /// This is persisted to memory so the state can be rebuilt after a global reset
+    fn get_target(&self) -> Option<RawObjectId> {
+        None
+    }
+
+    /// Get the resource this state is moving, if it only moves one.
+    /// Persisted alongside the target
+    fn get_resource(&self) -> Option<ResourceType> {
+        None
+    }
+
     /// Run a tick for the given creep and return the result
-    fn tick(&self, creep: &Creep) -> TickResult;
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult;
+
+    /// Reserve whatever this state is going to deliver or withdraw, so other creeps don't
+    /// pick the same target. Called when the state starts, or is restored after a global reset
+    fn reserve(&self, _creep: &dyn CreepView) {}
 
-    /// Called when the state is exited, can be used to clean up or reset counters
-    fn on_exit(&self) {
-        return;
+    /// Called when the state is exited, can be used to clean up or reset counters.
+    /// Releases anything the creep reserved
+    fn on_exit(&self, creep: &dyn CreepView) {
+        reservations::release(&creep.name());
     }
 }
 
@@ -81,3 +130,73 @@ pub enum TickResult {
     // exit and choose a state based on current needs
     Exit,
 }
+
+/// Rebuild the state a creep was in from its memory, e.g. after a global reset.
+/// Returns None if the state needs a target and none was stored
+pub fn restore_state(
+    memory: &CreepMemory,
+    repair_policy: &RepairPolicy,
+) -> Option<Box<dyn ScreepState>> {
+    let target = memory.state_target();
+    let state: Box<dyn ScreepState> = match memory.current_state() {
+        StateName::Harvest => Box::new(HarvestState::new(target?.into())),
+        StateName::Upgrade => Box::new(UpgradeState::new(target?.into())),
+        StateName::Build => Box::new(BuildState::new(target?.into())),
+        StateName::FeedStructure => {
+            Box::new(FeedStructureState::new(target?, memory.state_resource()))
+        }
+        StateName::Withdraw => Box::new(WithdrawState::new(target?, memory.state_resource())),
+        StateName::Attack => Box::new(AttackState::new(target?.into(), None)),
+        StateName::RangedAttack => Box::new(RangedAttackState::new(target?.into(), None)),
+        StateName::Recycle => Box::new(RecycleState::new(target?)),
+        StateName::Repair => Box::new(RepairState::new(target?, repair_policy.clone())),
+        StateName::Pickup => Box::new(PickupState::new(target?.into())),
+        StateName::DropMine => match memory.role_data() {
+            Some(RoleData::Miner { source }) => Box::new(DropMineState::new(*source, target?)),
+            _ => return None,
+        },
+        StateName::Idle => Box::new(IdleState),
+        StateName::HealSelf => Box::new(HealSelfState),
+    };
+    Some(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_controllers::Specialisation;
+    use crate::world::MockWorld;
+
+    #[test]
+    fn states_are_restored_from_memory() {
+        let target = MockWorld::new().next_id();
+        let mut memory = CreepMemory::new(Specialisation::Hauler);
+        memory.set_current_state(StateName::Withdraw);
+        memory.set_state_target(Some(target));
+        memory.set_state_resource(Some(ResourceType::Energy));
+
+        let state = restore_state(&memory, &RepairPolicy::default())
+            .expect("withdraw state is restored");
+        assert_eq!(state.get_state_name(), StateName::Withdraw);
+        assert_eq!(state.get_target(), Some(target));
+        assert_eq!(state.get_resource(), Some(ResourceType::Energy));
+    }
+
+    #[test]
+    fn states_without_a_target_are_restored() {
+        let mut memory = CreepMemory::new(Specialisation::Defender);
+        memory.set_current_state(StateName::HealSelf);
+
+        let state = restore_state(&memory, &RepairPolicy::default())
+            .expect("heal state is restored");
+        assert_eq!(state.get_state_name(), StateName::HealSelf);
+    }
+
+    #[test]
+    fn states_missing_their_target_are_not_restored() {
+        let mut memory = CreepMemory::new(Specialisation::Hauler);
+        memory.set_current_state(StateName::Withdraw);
+
+        assert!(restore_state(&memory, &RepairPolicy::default()).is_none());
+    }
+}
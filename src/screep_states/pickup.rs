@@ -0,0 +1,126 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::reservations::{self, ReservationKind};
+use crate::movement;
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::{local::{ObjectId, RawObjectId}, Resource};
+
+/// Pick up a pile of resources from the ground
+pub struct PickupState {
+    resource: ObjectId<Resource>,
+}
+
+impl PickupState {
+    pub fn new(resource: ObjectId<Resource>) -> Self {
+        PickupState { resource }
+    }
+}
+
+impl ScreepState for PickupState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("🫳");
+        self.update_state_memory(creep);
+        self.reserve(creep);
+    }
+
+    fn reserve(&self, creep: &dyn CreepView) {
+        // A pile only holds one resource, so whatever it is the creep takes it
+        let free = creep.store().get_free_capacity(None);
+        reservations::reserve(
+            &creep.name(),
+            self.resource.into(),
+            ReservationKind::Withdraw,
+            None,
+            free.max(0) as u32,
+        );
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::Pickup
+    }
+
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.resource.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        if creep.store().get_free_capacity(None) <= 0 {
+            return TickResult::Exit;
+        }
+        // The pile is gone once it has all been picked up or has decayed away
+        let Some(pile) = world.dropped_resource(self.resource) else {
+            return TickResult::Exit;
+        };
+
+        if creep.pos().is_near_to(pile.pos) {
+            if let Err(e) = creep.pickup(self.resource) {
+                warn!("creep {} couldn't pick up: {:?}", creep.name(), e);
+                return TickResult::Exit;
+            }
+        } else if movement::move_to(creep, world, pile.pos, 1).is_err() {
+            return TickResult::Exit;
+        }
+
+        TickResult::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::{Part, ResourceType};
+
+    fn world_next_to_pile() -> (MockWorld, ObjectId<Resource>) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let pile = world.add_dropped(position(20, 20, test_room()), ResourceType::Energy, 200);
+        world.add_creep(
+            "hauler",
+            position(21, 20, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        (world, pile)
+    }
+
+    #[test]
+    fn picks_up_the_pile_when_next_to_it() {
+        let (world, pile) = world_next_to_pile();
+        let creep = world.creeps()[0].clone();
+
+        let result = PickupState::new(pile).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Continue));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Pickup {
+                creep: "hauler".to_string(),
+                resource: pile,
+            }]
+        );
+    }
+
+    #[test]
+    fn exits_once_the_pile_has_decayed() {
+        let (mut world, pile) = world_next_to_pile();
+        world.room_mut(test_room()).dropped.clear();
+        let creep = world.creeps()[0].clone();
+
+        let result = PickupState::new(pile).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+        assert!(world.take_intents().is_empty());
+    }
+
+    #[test]
+    fn exits_once_full() {
+        let (mut world, pile) = world_next_to_pile();
+        world.creep_mut("hauler").store = StoreInfo::new(50).with(ResourceType::Hydrogen, 50);
+        let creep = world.creeps()[0].clone();
+
+        let result = PickupState::new(pile).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+        assert!(world.take_intents().is_empty());
+    }
+}
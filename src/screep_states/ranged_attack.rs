@@ -0,0 +1,96 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::movement::{self, MoveOptions, MovePriority};
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::{
+    local::{ObjectId, RawObjectId},
+    objects::Creep,
+    Position,
+};
+
+// Range of RANGED_ATTACK parts
+pub const RANGED_ATTACK_RANGE: u32 = 3;
+// Ticks without getting closer before giving up on the post or target
+const MOVE_TIMEOUT: u32 = 5;
+
+/// Shoot a hostile creep from range
+pub struct RangedAttackState {
+    target: ObjectId<Creep>,
+    // Rampart to hold while fighting, instead of chasing the target
+    post: Option<Position>,
+}
+
+impl RangedAttackState {
+    pub fn new(target: ObjectId<Creep>, post: Option<Position>) -> Self {
+        RangedAttackState { target, post }
+    }
+}
+
+impl ScreepState for RangedAttackState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("🏹");
+        self.update_state_memory(creep);
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::RangedAttack
+    }
+
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.target.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        let Some(target) = world.hostile(self.target) else {
+            return TickResult::Exit;
+        };
+        if target.pos.room_name() != creep.pos().room_name() {
+            return TickResult::Exit;
+        }
+
+        let in_range = |pos: Position| creep.pos().in_range_to(pos, RANGED_ATTACK_RANGE);
+        // Fighters walk up to hostiles rather than around them, and go first. If they can't get
+        // there quickly the controller picks another post or target
+        let options = |range| {
+            MoveOptions::new(range)
+                .avoid_hostiles(false)
+                .priority(MovePriority::High)
+                .timeout(MOVE_TIMEOUT)
+        };
+        let moved = match self.post {
+            Some(post) if creep.pos() != post => {
+                movement::move_with(creep, world, post, options(0))
+            }
+            None if !in_range(target.pos) => {
+                movement::move_with(creep, world, target.pos, options(RANGED_ATTACK_RANGE))
+            }
+            _ => Ok(()),
+        };
+
+        // Shoot the target if we can reach it, otherwise whatever else has come close
+        let victim = if in_range(target.pos) {
+            Some(target.id)
+        } else {
+            world.room(creep.pos().room_name()).and_then(|room| {
+                room.hostile_creeps()
+                    .into_iter()
+                    .find(|hostile| in_range(hostile.pos))
+                    .map(|hostile| hostile.id)
+            })
+        };
+        if let Some(victim) = victim {
+            if let Err(e) = creep.ranged_attack(victim) {
+                warn!("couldn't ranged attack: {:?}", e);
+                return TickResult::Exit;
+            }
+        }
+        if moved.is_err() {
+            return TickResult::Exit;
+        }
+        // Holding a post the target has moved away from, let the controller pick a new one
+        if victim.is_none() && self.post == Some(creep.pos()) {
+            return TickResult::Exit;
+        }
+        TickResult::Continue
+    }
+}
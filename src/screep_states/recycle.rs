@@ -0,0 +1,53 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::movement::{self, MoveOptions, MovePriority};
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::local::RawObjectId;
+
+/// Walk to a spawn and have it recycle us, for creeps which are no longer needed
+pub struct RecycleState {
+    spawn: RawObjectId,
+}
+
+impl RecycleState {
+    pub fn new(spawn: RawObjectId) -> Self {
+        RecycleState { spawn }
+    }
+}
+
+impl ScreepState for RecycleState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("♻️");
+        self.update_state_memory(creep);
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::Recycle
+    }
+
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.spawn)
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        let Some(structure) = world.structure(self.spawn) else {
+            return TickResult::Exit;
+        };
+        if !creep.pos().is_near_to(structure.pos) {
+            // Anyone still working goes first
+            let options = MoveOptions::new(1).priority(MovePriority::Low);
+            if movement::move_with(creep, world, structure.pos, options).is_err() {
+                return TickResult::Exit;
+            }
+            return TickResult::Continue;
+        }
+        let Some(spawn) = world.spawns().into_iter().find(|s| s.pos() == structure.pos) else {
+            return TickResult::Exit;
+        };
+        if let Err(e) = spawn.recycle_creep(creep.id()) {
+            warn!("couldn't recycle: {:?}", e);
+            return TickResult::Exit;
+        }
+        TickResult::Continue
+    }
+}
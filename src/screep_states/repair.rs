@@ -0,0 +1,154 @@
+use super::{ScreepState, StateName, TickResult};
+use crate::reservations::{self, ReservationKind};
+use crate::movement;
+use crate::tower_manager::RepairPolicy;
+use crate::world::{CreepView, World};
+use log::warn;
+use screeps::{constants::ResourceType, local::RawObjectId, ErrorCode};
+
+/// Repair a structure until it is back up to the hits the repair policy wants for it
+pub struct RepairState {
+    structure: RawObjectId,
+    policy: RepairPolicy,
+}
+
+impl RepairState {
+    pub fn new(structure: RawObjectId, policy: RepairPolicy) -> Self {
+        RepairState { structure, policy }
+    }
+}
+
+impl ScreepState for RepairState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("🔧");
+        self.update_state_memory(creep);
+        self.reserve(creep);
+    }
+
+    fn reserve(&self, creep: &dyn CreepView) {
+        reservations::reserve(
+            &creep.name(),
+            self.structure,
+            ReservationKind::Deliver,
+            Some(ResourceType::Energy),
+            creep.store().get_used_capacity(Some(ResourceType::Energy)),
+        );
+    }
+
+    fn get_state_name(&self) -> StateName {
+        StateName::Repair
+    }
+
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.structure)
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
+            return TickResult::Exit;
+        }
+        let Some(structure) = world.structure(self.structure) else {
+            return TickResult::Exit;
+        };
+        let level = world
+            .room(structure.pos.room_name())
+            .and_then(|room| room.controller())
+            .map_or(0, |controller| controller.level);
+        if structure.hits >= self.policy.target_hits(&structure, level) {
+            return TickResult::Exit;
+        }
+        if !creep.pos().in_range_to(structure.pos, 3) {
+            if movement::move_to(creep, world, structure.pos, 3).is_err() {
+                return TickResult::Exit;
+            }
+            return TickResult::Continue;
+        }
+
+        match creep.repair(self.structure) {
+            Ok(()) => TickResult::Continue,
+            Err(ErrorCode::NotInRange) => {
+                match movement::move_to(creep, world, structure.pos, 3) {
+                    Ok(()) => TickResult::Continue,
+                    Err(_) => TickResult::Exit,
+                }
+            }
+            Err(e) => {
+                warn!("creep {} couldn't repair: {:?}", creep.name(), e);
+                TickResult::Exit
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::{Part, StructureType};
+
+    /// A generalist with energy next to a damaged wall, in a room at level 3
+    fn world_next_to_wall(hits: u32) -> (MockWorld, RawObjectId) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 10, test_room()), 3);
+        let wall = world.add_structure(StructureType::Wall, position(20, 20, test_room()), None);
+        let structure = world.room_mut(test_room()).structures.last_mut().unwrap();
+        structure.hits = hits;
+        structure.hits_max = 300_000_000;
+        world.add_creep(
+            "generalist",
+            position(21, 20, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Generalist),
+        );
+        world.creep_mut("generalist").store =
+            StoreInfo::new(50).with(ResourceType::Energy, 50);
+        (world, wall)
+    }
+
+    #[test]
+    fn repairs_until_the_policy_target() {
+        let (world, wall) = world_next_to_wall(10_000);
+        let creep = world.creeps()[0].clone();
+
+        let result = RepairState::new(wall, RepairPolicy::default()).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Continue));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Repair {
+                creep: "generalist".to_string(),
+                structure: wall,
+            }]
+        );
+    }
+
+    #[test]
+    fn exits_once_the_structure_is_repaired() {
+        // Walls are kept at 30k hits at level 3, nowhere near their maximum
+        let (world, wall) = world_next_to_wall(30_000);
+        let creep = world.creeps()[0].clone();
+
+        let result = RepairState::new(wall, RepairPolicy::default()).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+        assert!(world.take_intents().is_empty());
+    }
+
+    #[test]
+    fn exits_once_out_of_energy_or_the_structure_is_gone() {
+        let (mut world, wall) = world_next_to_wall(10_000);
+        world.creep_mut("generalist").store = StoreInfo::new(50);
+        let creep = world.creeps()[0].clone();
+        let result = RepairState::new(wall, RepairPolicy::default()).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+
+        drop(creep);
+        world.creep_mut("generalist").store = StoreInfo::new(50).with(ResourceType::Energy, 50);
+        world.room_mut(test_room()).structures.pop();
+        let creep = world.creeps()[0].clone();
+        let result = RepairState::new(wall, RepairPolicy::default()).tick(creep.as_ref(), &world);
+        assert!(matches!(result, TickResult::Exit));
+        assert!(world.take_intents().is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use super::{ScreepState, StateName, TickResult};
+use crate::movement;
+use crate::world::{CreepView, World};
 use log::warn;
-use screeps::action_error_codes::UpgradeControllerErrorCode;
 use screeps::{
     constants::ResourceType,
-    local::ObjectId,
-    objects::{Creep, StructureController},
+    local::{ObjectId, RawObjectId},
+    objects::StructureController,
+    ErrorCode,
 };
 
 // UpgradeState is used to upgrade the room controller
@@ -19,8 +21,8 @@ impl UpgradeState {
 }
 
 impl ScreepState for UpgradeState {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("⬆️", false);
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("⬆️");
         self.update_state_memory(creep);
     }
 
@@ -28,30 +30,36 @@ impl ScreepState for UpgradeState {
         StateName::Upgrade
     }
 
-    fn tick(&self, creep: &Creep) -> TickResult {
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.controller.into())
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
         if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
             return TickResult::Exit;
         }
-        let Some(controller) = self.controller.resolve() else {
+        let Some(controller) = world.structure(self.controller.into()) else {
             return TickResult::Exit;
         };
-        match creep.upgrade_controller(&controller) {
+        match creep.upgrade_controller(self.controller) {
             Ok(_) => {
                 // Successfully upgraded the controller
                 TickResult::Continue
             }
             Err(e) => {
                 // Handle the error based on the error code
-                return match e {
-                    UpgradeControllerErrorCode::NotInRange => {
-                        let _ = creep.move_to(&controller);
-                        TickResult::Continue
+                match e {
+                    ErrorCode::NotInRange => {
+                        match movement::move_to(creep, world, controller.pos, 3) {
+                            Ok(()) => TickResult::Continue,
+                            Err(_) => TickResult::Exit,
+                        }
                     }
                     _ => {
                         warn!("couldn't upgrade: {:?}", e);
                         TickResult::Exit
                     }
-                };
+                }
             }
         }
     }
@@ -1,50 +1,141 @@
 use super::{ScreepState, StateName, TickResult};
+use crate::reservations::{self, ReservationKind};
+use crate::movement;
+use crate::world::{CreepView, World};
 use log::warn;
-use screeps::{constants::ResourceType, local::ObjectId, objects::Creep, prelude::*};
-use wasm_bindgen::JsCast;
+use screeps::{constants::ResourceType, local::RawObjectId};
 
-/// Harvest energy from the source
-pub struct WithdrawState<T: Withdrawable + MaybeHasId + JsCast> {
-    structure: ObjectId<T>,
+/// Withdraw a resource from a structure, tombstone or ruin, or drain everything in it if the
+/// resource is None
+pub struct WithdrawState {
+    target: RawObjectId,
+    resource: Option<ResourceType>,
 }
 
-impl<T: Withdrawable + MaybeHasId + JsCast> WithdrawState<T> {
-    pub fn new(structure: ObjectId<T>) -> Self {
-        WithdrawState { structure }
+impl WithdrawState {
+    pub fn new(target: RawObjectId, resource: Option<ResourceType>) -> Self {
+        WithdrawState { target, resource }
     }
 }
 
-impl<T: Withdrawable + MaybeHasId + JsCast> ScreepState for WithdrawState<T> {
-    fn on_start(&self, creep: &Creep) {
-        let _ = creep.say("📤", false);
+impl ScreepState for WithdrawState {
+    fn on_start(&self, creep: &dyn CreepView) {
+        creep.say("📤");
         self.update_state_memory(creep);
+        self.reserve(creep);
+    }
+
+    fn reserve(&self, creep: &dyn CreepView) {
+        let free = creep.store().get_free_capacity(self.resource);
+        reservations::reserve(
+            &creep.name(),
+            self.target,
+            ReservationKind::Withdraw,
+            self.resource,
+            free.max(0) as u32,
+        );
     }
 
     fn get_state_name(&self) -> StateName {
         StateName::Withdraw
     }
 
-    fn tick(&self, creep: &Creep) -> TickResult {
-        // Check if we have any free capacity to harvest energy
-        if creep.store().get_free_capacity(Some(ResourceType::Energy)) == 0 {
+    fn get_target(&self) -> Option<RawObjectId> {
+        Some(self.target)
+    }
+
+    fn get_resource(&self) -> Option<ResourceType> {
+        self.resource
+    }
+
+    fn tick(&self, creep: &dyn CreepView, world: &dyn World) -> TickResult {
+        // Check if we have any free capacity left
+        if creep.store().get_free_capacity(self.resource) <= 0 {
             return TickResult::Exit;
         }
-        let Some(structure) = self.structure.resolve() else {
+        // The target can be a tombstone or ruin as well as a structure
+        let Some((pos, store)) = world
+            .remains(self.target)
+            .map(|remains| (remains.pos, remains.store))
+            .or_else(|| {
+                world
+                    .structure(self.target)
+                    .map(|structure| (structure.pos, structure.store()))
+            })
+        else {
             return TickResult::Exit;
         };
+        let resource = match self.resource {
+            Some(resource) => resource,
+            None => match store.store_types().into_iter().next() {
+                Some(resource) => resource,
+                None => return TickResult::Exit,
+            },
+        };
 
-        if creep.pos().is_near_to(structure.pos()) {
-            if creep
-                .withdraw(&structure, ResourceType::Energy, None)
-                .is_err()
-            {
+        if creep.pos().is_near_to(pos) {
+            if creep.withdraw(self.target, resource, None).is_err() {
                 warn!("couldn't withdraw for some unknown reason");
                 return TickResult::Exit;
             };
-        } else {
-            let _ = creep.move_to(&structure);
+        } else if movement::move_to(creep, world, pos, 1).is_err() {
+            return TickResult::Exit;
         }
 
         TickResult::Continue
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, RemainsKind, StoreInfo};
+    use screeps::Part;
+
+    fn world_with_hauler() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_creep(
+            "hauler",
+            position(20, 20, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world
+    }
+
+    #[test]
+    fn drains_whatever_a_tombstone_holds() {
+        let mut world = world_with_hauler();
+        let tombstone = world.add_remains(
+            RemainsKind::Tombstone,
+            position(21, 20, test_room()),
+            StoreInfo::new(1000).with(ResourceType::Oxygen, 20),
+            100,
+        );
+        let creep = world.creeps()[0].clone();
+
+        let state = WithdrawState::new(tombstone, None);
+        assert!(matches!(state.tick(creep.as_ref(), &world), TickResult::Continue));
+        assert_eq!(
+            world.take_intents(),
+            vec![Intent::Withdraw {
+                creep: "hauler".to_string(),
+                target: tombstone,
+                resource: ResourceType::Oxygen,
+                amount: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn exits_when_the_target_is_gone() {
+        let world = world_with_hauler();
+        let creep = world.creeps()[0].clone();
+
+        let state = WithdrawState::new(world.next_id(), Some(ResourceType::Energy));
+        assert!(matches!(state.tick(creep.as_ref(), &world), TickResult::Exit));
+    }
+}
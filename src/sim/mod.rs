@@ -0,0 +1,962 @@
+// Headless simulator for running the colony natively over many ticks.
+// Each tick the bot's logic runs against a MockWorld exactly like it runs against the live game,
+// then the recorded intents are applied using simplified versions of the game's rules. This is
+// enough to check things like "does a fresh room reach RCL 2" without a server.
+use crate::defense::{self, SafeMode};
+use crate::link_manager::LinkManager;
+use crate::movement;
+use crate::planner::Planner;
+use crate::screep_states::CreepMemory;
+use crate::state_controllers::SCManager;
+use crate::tower_manager::{tower_power, RepairPolicy, TowerManager};
+use crate::world::{
+    plain_terrain, position, Intent, MockWorld, RemainsInfo, RemainsKind, StoreInfo, StructureInfo,
+};
+use screeps::constants::{
+    controller_levels, extension_energy_capacity, ATTACK_POWER, BUILD_POWER, CONTAINER_CAPACITY,
+    CONTAINER_DECAY, CONTAINER_DECAY_TIME_OWNED, CREEP_SPAWN_TIME, ENERGY_DECAY, ENERGY_REGEN_TIME,
+    HARVEST_POWER, HEAL_POWER, LINK_CAPACITY, LINK_COOLDOWN, LINK_LOSS_RATIO, RAMPART_DECAY_AMOUNT,
+    RAMPART_DECAY_TIME, RANGED_ATTACK_POWER, REPAIR_POWER, ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME,
+    SAFE_MODE_COOLDOWN, SAFE_MODE_DURATION, SPAWN_ENERGY_CAPACITY, SPAWN_ENERGY_START,
+    STORAGE_CAPACITY, TOMBSTONE_DECAY_PER_PART, TOWER_CAPACITY, TOWER_ENERGY_COST,
+    TOWER_POWER_ATTACK, TOWER_POWER_HEAL, TOWER_POWER_REPAIR, UPGRADE_CONTROLLER_POWER,
+};
+use screeps::{
+    ConstructionSite, Creep, Direction, LocalRoomTerrain, ObjectId, Part, Position, RawObjectId,
+    Resource, ResourceType, RoomName, RoomXY, Source, StructureController, StructureType, Terrain,
+};
+
+/// A creep which is still being spawned
+struct SpawningCreep {
+    spawn: String,
+    name: String,
+    body: Vec<Part>,
+    memory: CreepMemory,
+    remaining: u32,
+}
+
+/// Runs the bot against a mock world, see the module docs
+pub struct Simulator {
+    world: MockWorld,
+    state_manager: SCManager,
+    tower_manager: TowerManager,
+    link_manager: LinkManager,
+    safe_mode: SafeMode,
+    planner: Planner,
+    spawning: Vec<SpawningCreep>,
+    // Notifications the bot has sent, oldest first
+    notifications: Vec<String>,
+    // Moves issued this tick, which all happen together once the other intents are applied
+    moves: Vec<(String, Direction)>,
+}
+
+impl Simulator {
+    /// Create a simulator for the given world
+    pub fn new(world: MockWorld) -> Self {
+        let repair_policy = RepairPolicy::default();
+        let mut simulator = Simulator {
+            world,
+            state_manager: SCManager::new(repair_policy.clone()),
+            tower_manager: TowerManager::new(repair_policy),
+            link_manager: LinkManager::new(),
+            safe_mode: SafeMode::new(),
+            planner: Planner::new(),
+            spawning: vec![],
+            notifications: vec![],
+            moves: vec![],
+        };
+        simulator.update_room_energy();
+        simulator
+    }
+
+    /// A fresh RCL 1 room with a single spawn, two sources and plain terrain, as if we just
+    /// placed our first spawn
+    pub fn starter_room() -> Self {
+        let room = RoomName::new("W1N1").expect("valid room name");
+        let mut world = MockWorld::new();
+        world.add_room(room);
+        world.add_controller(position(25, 8, room), 1);
+        world.add_source(position(10, 12, room));
+        world.add_source(position(40, 38, room));
+        world.add_spawn("Spawn1", position(25, 25, room));
+        let mut simulator = Simulator::new(world);
+        simulator.set_terrain(room, plain_terrain());
+        simulator
+    }
+
+    pub fn world(&self) -> &MockWorld {
+        &self.world
+    }
+
+    /// Mutable access to the world, for setting up scenarios between ticks
+    pub fn world_mut(&mut self) -> &mut MockWorld {
+        &mut self.world
+    }
+
+    pub fn set_terrain(&mut self, room: RoomName, terrain: LocalRoomTerrain) {
+        self.world.room_mut(room).terrain = terrain;
+    }
+
+    /// Notifications the bot has sent, oldest first
+    pub fn notifications(&self) -> &[String] {
+        &self.notifications
+    }
+
+    /// Run a single tick: the bot issues its intents, then the world is updated
+    pub fn tick(&mut self) {
+        self.world.time += 1;
+        let threats = defense::assess(&self.world);
+        self.state_manager.run(&self.world, &threats);
+        movement::resolve_traffic(&self.world);
+        self.safe_mode.run(&self.world, &threats);
+        self.tower_manager.run_all_towers(&self.world, &threats);
+        self.link_manager.run_all_links(&self.world);
+        self.planner.run(&self.world);
+        for intent in self.world.take_intents() {
+            self.apply(intent);
+        }
+        self.apply_moves();
+        self.end_tick();
+    }
+
+    /// Run for the given number of ticks
+    pub fn run(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    /// Run until `done` returns true, for at most `max_ticks`.
+    /// Returns the number of ticks it took, or None if it never happened
+    pub fn run_until(&mut self, max_ticks: u32, done: impl Fn(&MockWorld) -> bool) -> Option<u32> {
+        for ticks in 0..max_ticks {
+            if done(&self.world) {
+                return Some(ticks);
+            }
+            self.tick();
+        }
+        done(&self.world).then_some(max_ticks)
+    }
+
+    fn apply(&mut self, intent: Intent) {
+        match intent {
+            Intent::Say { .. } => {}
+            Intent::MoveDirection { creep, direction } => self.moves.push((creep, direction)),
+            Intent::Harvest { creep, source } => self.apply_harvest(&creep, source),
+            Intent::Transfer {
+                creep,
+                target,
+                resource,
+                amount,
+            } => self.apply_transfer(&creep, target, resource, amount),
+            Intent::Withdraw {
+                creep,
+                target,
+                resource,
+                amount,
+            } => self.apply_withdraw(&creep, target, resource, amount),
+            Intent::Pickup { creep, resource } => self.apply_pickup(&creep, resource),
+            Intent::Build { creep, site } => self.apply_build(&creep, site),
+            Intent::Repair { creep, structure } => self.apply_repair(&creep, structure),
+            Intent::UpgradeController { creep, controller } => {
+                self.apply_upgrade(&creep, controller)
+            }
+            Intent::SpawnCreep {
+                spawn,
+                body,
+                name,
+                memory,
+            } => self.apply_spawn(&spawn, body, name, memory),
+            Intent::Attack { creep, target } => {
+                self.apply_attack(&creep, target, ATTACK_POWER, Part::Attack)
+            }
+            Intent::RangedAttack { creep, target } => {
+                self.apply_attack(&creep, target, RANGED_ATTACK_POWER, Part::RangedAttack)
+            }
+            Intent::Heal { creep, target } => self.apply_heal(&creep, target),
+            Intent::RecycleCreep { creep, .. } => self.apply_recycle(creep),
+            Intent::TowerAttack { tower, target } => self.apply_tower_attack(tower.into(), target),
+            Intent::TowerHeal { tower, target } => self.apply_tower_heal(tower.into(), target),
+            Intent::TowerRepair { tower, target } => self.apply_tower_repair(tower.into(), target),
+            Intent::LinkTransfer {
+                link,
+                target,
+                amount,
+            } => self.apply_link_transfer(link.into(), target.into(), amount),
+            Intent::ActivateSafeMode { controller } => self.apply_safe_mode(controller),
+            Intent::CreateConstructionSite {
+                pos,
+                structure_type,
+            } => self.apply_construction_site(pos, structure_type),
+            Intent::Notify { message } => self.notifications.push(message),
+        }
+    }
+
+    /// Creeps all move at once, so they can follow each other or swap places. A move fails if
+    /// the tile can't be walked on, another creep got there first, or a creep is staying on it
+    fn apply_moves(&mut self) {
+        let mut steps: Vec<(String, Position)> = vec![];
+        for (name, direction) in std::mem::take(&mut self.moves) {
+            let from = self.world.creep_mut(&name).pos;
+            let Ok(step) = from.checked_add_direction(direction) else {
+                continue;
+            };
+            if step.room_name() == from.room_name()
+                && self.is_walkable(step)
+                && !steps.iter().any(|(_, other)| *other == step)
+            {
+                steps.push((name, step));
+            }
+        }
+        loop {
+            let staying: Vec<Position> = self
+                .world
+                .creeps
+                .iter()
+                .filter(|creep| !steps.iter().any(|(name, _)| *name == creep.name))
+                .map(|creep| creep.pos)
+                .collect();
+            let before = steps.len();
+            steps.retain(|(_, step)| !staying.contains(step));
+            if steps.len() == before {
+                break;
+            }
+        }
+
+        for (name, step) in steps {
+            let fatigue_per_part = match self.terrain_at(step) {
+                _ if self.has_structure(step, StructureType::Road) => 1,
+                Terrain::Swamp => 10,
+                _ => 2,
+            };
+            let creep = self.world.creep_mut(&name);
+            creep.pos = step;
+            creep.fatigue += weight(&creep.body, &creep.store) * fatigue_per_part;
+        }
+    }
+
+    fn apply_harvest(&mut self, name: &str, source: ObjectId<Source>) {
+        let power = count_parts(&self.world.creep_mut(name).body, Part::Work) * HARVEST_POWER;
+        let Some(source) = self
+            .world
+            .rooms
+            .iter_mut()
+            .flat_map(|room| room.sources.iter_mut())
+            .find(|s| s.id == source)
+        else {
+            return;
+        };
+        let amount = power.min(source.energy);
+        source.energy -= amount;
+        // The regeneration timer starts the first time a full source is harvested
+        source
+            .ticks_to_regeneration
+            .get_or_insert(ENERGY_REGEN_TIME);
+
+        let creep = self.world.creep_mut(name);
+        let mut left = amount - add_to_store(&mut creep.store, ResourceType::Energy, amount);
+        // Whatever the creep can't carry drops into a container on its tile, or onto the ground
+        let pos = creep.pos;
+        if let Some(container) = self.world.rooms.iter_mut().find_map(|room| {
+            room.structures
+                .iter_mut()
+                .find(|s| s.pos == pos && s.structure_type == StructureType::Container)
+        }) {
+            if let Some(store) = container.store.as_mut() {
+                left -= add_to_store(store, ResourceType::Energy, left);
+            }
+        }
+        self.drop_resource(pos, ResourceType::Energy, left);
+    }
+
+    fn apply_pickup(&mut self, name: &str, resource: ObjectId<Resource>) {
+        let Some(pile) = self
+            .world
+            .rooms
+            .iter_mut()
+            .flat_map(|room| room.dropped.iter_mut())
+            .find(|pile| pile.id == resource)
+        else {
+            return;
+        };
+        let Some(creep) = self
+            .world
+            .creeps
+            .iter_mut()
+            .find(|creep| creep.name == name)
+        else {
+            return;
+        };
+        pile.amount -= add_to_store(&mut creep.store, pile.resource_type, pile.amount);
+        for room in self.world.rooms.iter_mut() {
+            room.dropped.retain(|pile| pile.amount > 0);
+        }
+    }
+
+    fn apply_transfer(
+        &mut self,
+        name: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) {
+        let available = self
+            .world
+            .creep_mut(name)
+            .store
+            .get_used_capacity(Some(resource));
+        let Some(store) = self.structure_mut(target).and_then(|s| s.store.as_mut()) else {
+            return;
+        };
+        let moved = add_to_store(store, resource, amount.unwrap_or(available).min(available));
+        remove_from_store(&mut self.world.creep_mut(name).store, resource, moved);
+    }
+
+    fn apply_withdraw(
+        &mut self,
+        name: &str,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) {
+        let free = self
+            .world
+            .creep_mut(name)
+            .store
+            .get_free_capacity(Some(resource))
+            .max(0) as u32;
+        let Some(store) = self.withdrawable_mut(target) else {
+            return;
+        };
+        let moved = remove_from_store(store, resource, amount.unwrap_or(free).min(free));
+        add_to_store(&mut self.world.creep_mut(name).store, resource, moved);
+    }
+
+    fn apply_build(&mut self, name: &str, site: ObjectId<ConstructionSite>) {
+        let creep = self.world.creep_mut(name);
+        let power = count_parts(&creep.body, Part::Work) * BUILD_POWER;
+        let energy = creep.store.get_used_capacity(Some(ResourceType::Energy));
+        let Some(room) = self
+            .world
+            .rooms
+            .iter_mut()
+            .find(|room| room.construction_sites.iter().any(|s| s.id == site))
+        else {
+            return;
+        };
+        let index = room
+            .construction_sites
+            .iter()
+            .position(|s| s.id == site)
+            .expect("site is in this room");
+        let info = &mut room.construction_sites[index];
+        let amount = power.min(energy).min(info.progress_total - info.progress);
+        info.progress += amount;
+
+        // Finished sites turn into the structure straight away
+        if info.progress >= info.progress_total {
+            let info = room.construction_sites.remove(index);
+            let level = room.controller.as_ref().map_or(0, |c| c.level);
+            room.structures.push(StructureInfo {
+                id: RawObjectId::from(info.id),
+                structure_type: info.structure_type,
+                pos: info.pos,
+                hits: info.structure_type.initial_hits().unwrap_or(1),
+                hits_max: info.structure_type.initial_hits().unwrap_or(1),
+                store: new_structure_store(info.structure_type, level),
+            });
+        }
+        remove_from_store(
+            &mut self.world.creep_mut(name).store,
+            ResourceType::Energy,
+            amount,
+        );
+    }
+
+    fn apply_repair(&mut self, name: &str, structure: RawObjectId) {
+        let creep = self.world.creep_mut(name);
+        let power = count_parts(&creep.body, Part::Work) * REPAIR_POWER;
+        let energy = creep.store.get_used_capacity(Some(ResourceType::Energy));
+        let Some(target) = self.structure_mut(structure) else {
+            return;
+        };
+        // Each energy repairs REPAIR_POWER hits
+        let amount = power
+            .min(energy * REPAIR_POWER)
+            .min(target.hits_max - target.hits);
+        target.hits += amount;
+        remove_from_store(
+            &mut self.world.creep_mut(name).store,
+            ResourceType::Energy,
+            amount.div_ceil(REPAIR_POWER),
+        );
+    }
+
+    fn apply_upgrade(&mut self, name: &str, controller: ObjectId<StructureController>) {
+        let creep = self.world.creep_mut(name);
+        let power = count_parts(&creep.body, Part::Work) * UPGRADE_CONTROLLER_POWER;
+        let amount = power.min(creep.store.get_used_capacity(Some(ResourceType::Energy)));
+        remove_from_store(&mut creep.store, ResourceType::Energy, amount);
+
+        let Some(controller) = self
+            .world
+            .rooms
+            .iter_mut()
+            .filter_map(|room| room.controller.as_mut())
+            .find(|c| c.id == controller)
+        else {
+            return;
+        };
+        controller.progress += amount;
+        if let Some(needed) = controller_levels(controller.level as u32) {
+            if controller.progress >= needed {
+                controller.level += 1;
+                controller.progress -= needed;
+                controller.safe_mode_available += 1;
+            }
+        }
+        controller.progress_total = controller_levels(controller.level as u32).unwrap_or(0);
+    }
+
+    fn apply_safe_mode(&mut self, controller: ObjectId<StructureController>) {
+        if let Some(controller) = self
+            .world
+            .rooms
+            .iter_mut()
+            .filter_map(|room| room.controller.as_mut())
+            .find(|c| c.id == controller)
+        {
+            controller.safe_mode = Some(SAFE_MODE_DURATION);
+            controller.safe_mode_available -= 1;
+            controller.safe_mode_cooldown = Some(SAFE_MODE_COOLDOWN);
+        }
+    }
+
+    fn apply_construction_site(&mut self, pos: Position, structure_type: StructureType) {
+        let id = self.world.add_construction_site(structure_type, pos);
+        if let Some(site) = self
+            .world
+            .room_mut(pos.room_name())
+            .construction_sites
+            .iter_mut()
+            .find(|site| site.id == id)
+        {
+            site.progress_total = structure_type.construction_cost().unwrap_or(1);
+        }
+    }
+
+    fn apply_spawn(&mut self, spawn: &str, body: Vec<Part>, name: String, memory: CreepMemory) {
+        let Some(mock_spawn) = self.world.spawns.iter_mut().find(|s| s.name == spawn) else {
+            return;
+        };
+        if mock_spawn.spawning {
+            return;
+        }
+        mock_spawn.spawning = true;
+        let room_name = mock_spawn.pos.room_name();
+
+        // Spawns are drained before extensions
+        let mut cost: u32 = body.iter().map(|part| part.cost()).sum();
+        let room = self.world.room_mut(room_name);
+        for structure_type in [StructureType::Spawn, StructureType::Extension] {
+            for structure in room
+                .structures
+                .iter_mut()
+                .filter(|s| s.structure_type == structure_type)
+            {
+                if let Some(store) = structure.store.as_mut() {
+                    cost -= remove_from_store(store, ResourceType::Energy, cost);
+                }
+            }
+        }
+
+        self.spawning.push(SpawningCreep {
+            spawn: spawn.to_string(),
+            name,
+            remaining: body.len() as u32 * CREEP_SPAWN_TIME,
+            body,
+            memory,
+        });
+    }
+
+    fn apply_attack(&mut self, name: &str, target: ObjectId<Creep>, power: u32, part: Part) {
+        let creep = self.world.creep_mut(name);
+        let damage = count_parts(&creep.body, part) * power;
+        self.damage_hostile(target, |_| damage);
+    }
+
+    fn apply_heal(&mut self, name: &str, target: ObjectId<Creep>) {
+        let healer = self.world.creep_mut(name);
+        let heal = count_parts(&healer.body, Part::Heal) * HEAL_POWER;
+        if let Some(creep) = self.world.creeps.iter_mut().find(|c| c.id == target) {
+            creep.hits = (creep.hits + heal).min(creep.hits_max);
+        }
+    }
+
+    fn apply_recycle(&mut self, target: ObjectId<Creep>) {
+        self.world.creeps.retain(|creep| creep.id != target);
+    }
+
+    fn apply_tower_attack(&mut self, tower: RawObjectId, target: ObjectId<Creep>) {
+        let Some(tower_pos) = self.use_tower(tower) else {
+            return;
+        };
+        self.damage_hostile(target, |pos| {
+            tower_power(TOWER_POWER_ATTACK, tower_pos.get_range_to(pos))
+        });
+    }
+
+    /// Damage a hostile creep, removing it if it dies. The damage can depend on where the
+    /// hostile is
+    fn damage_hostile(&mut self, target: ObjectId<Creep>, damage: impl Fn(Position) -> u32) {
+        for room in self.world.rooms.iter_mut() {
+            let Some(hostile) = room.hostiles.iter_mut().find(|h| h.id == target) else {
+                continue;
+            };
+            let mut damage = damage(hostile.pos);
+            hostile.hits = hostile.hits.saturating_sub(damage);
+            // Parts take damage in order, from the front of the body
+            for part in hostile.body.iter_mut() {
+                let taken = damage.min(part.hits);
+                part.hits -= taken;
+                damage -= taken;
+            }
+            room.hostiles.retain(|h| h.hits > 0);
+        }
+    }
+
+    fn apply_tower_heal(&mut self, tower: RawObjectId, target: ObjectId<Creep>) {
+        let Some(tower_pos) = self.use_tower(tower) else {
+            return;
+        };
+        if let Some(creep) = self.world.creeps.iter_mut().find(|c| c.id == target) {
+            let heal = tower_power(TOWER_POWER_HEAL, tower_pos.get_range_to(creep.pos));
+            creep.hits = (creep.hits + heal).min(creep.hits_max);
+        }
+    }
+
+    fn apply_tower_repair(&mut self, tower: RawObjectId, target: RawObjectId) {
+        let Some(tower_pos) = self.use_tower(tower) else {
+            return;
+        };
+        if let Some(target) = self.structure_mut(target) {
+            let range = tower_pos.get_range_to(target.pos);
+            target.hits =
+                (target.hits + tower_power(TOWER_POWER_REPAIR, range)).min(target.hits_max);
+        }
+    }
+
+    /// Take the energy for a single tower action, returning the tower's position if it had
+    /// enough
+    fn use_tower(&mut self, tower: RawObjectId) -> Option<Position> {
+        let tower = self.structure_mut(tower)?;
+        let store = tower.store.as_mut()?;
+        if store.get_used_capacity(Some(ResourceType::Energy)) < TOWER_ENERGY_COST {
+            return None;
+        }
+        remove_from_store(store, ResourceType::Energy, TOWER_ENERGY_COST);
+        Some(tower.pos)
+    }
+
+    fn apply_link_transfer(&mut self, link: RawObjectId, target: RawObjectId, amount: Option<u32>) {
+        let Some(from) = self.structure_mut(link) else {
+            return;
+        };
+        let from_pos = from.pos;
+        let energy = from.store().get_used_capacity(Some(ResourceType::Energy));
+        let Some(to) = self.structure_mut(target) else {
+            return;
+        };
+        let range = from_pos.get_range_to(to.pos);
+        let Some(store) = to.store.as_mut() else {
+            return;
+        };
+        let free = store.get_free_capacity(Some(ResourceType::Energy)).max(0) as u32;
+        let sent = amount.unwrap_or(energy).min(energy).min(free);
+        // Some of what is sent is lost on the way
+        let lost = (sent as f32 * LINK_LOSS_RATIO).ceil() as u32;
+        add_to_store(store, ResourceType::Energy, sent - lost);
+        if let Some(store) = self.structure_mut(link).and_then(|s| s.store.as_mut()) {
+            remove_from_store(store, ResourceType::Energy, sent);
+        }
+        if let Some(room) = self
+            .world
+            .rooms
+            .iter_mut()
+            .find(|room| room.name == from_pos.room_name())
+        {
+            room.cooldowns.insert(link, LINK_COOLDOWN * range);
+        }
+    }
+
+    /// Everything that happens at the end of a tick, once all intents are applied
+    fn end_tick(&mut self) {
+        for creep in self.world.creeps.iter_mut() {
+            let move_parts = count_parts(&creep.body, Part::Move);
+            creep.fatigue = creep.fatigue.saturating_sub(move_parts * 2);
+            creep.ticks_to_live -= 1;
+        }
+        // Creeps that die leave a tombstone holding whatever they were carrying
+        let (dead, alive) = std::mem::take(&mut self.world.creeps)
+            .into_iter()
+            .partition(|creep| creep.ticks_to_live == 0);
+        self.world.creeps = alive;
+        for creep in dead {
+            let ticks = TOMBSTONE_DECAY_PER_PART * creep.body.len() as u32;
+            self.world
+                .add_remains(RemainsKind::Tombstone, creep.pos, creep.store, ticks);
+        }
+
+        // Dropped resources slowly disappear, and tombstones and ruins crumble, spilling what
+        // is left in them onto the ground
+        for room in self.world.rooms.iter_mut() {
+            for pile in room.dropped.iter_mut() {
+                pile.amount -= pile.amount.div_ceil(ENERGY_DECAY);
+            }
+            room.dropped.retain(|pile| pile.amount > 0);
+            for remains in room.remains.iter_mut() {
+                remains.ticks_to_decay = remains.ticks_to_decay.saturating_sub(1);
+            }
+        }
+        for room in self.world.rooms.iter_mut() {
+            for cooldown in room.cooldowns.values_mut() {
+                *cooldown -= 1;
+            }
+            room.cooldowns.retain(|_, cooldown| *cooldown > 0);
+        }
+        let crumbled: Vec<RemainsInfo> = self
+            .world
+            .rooms
+            .iter_mut()
+            .flat_map(|room| {
+                let (crumbled, left) = std::mem::take(&mut room.remains)
+                    .into_iter()
+                    .partition(|remains| remains.ticks_to_decay == 0);
+                room.remains = left;
+                crumbled
+            })
+            .collect::<Vec<_>>();
+        for remains in crumbled {
+            for (resource_type, amount) in remains.store.contents {
+                self.drop_resource(remains.pos, resource_type, amount);
+            }
+        }
+
+        // Finished creeps leave the spawn onto any free tile next to it
+        for spawning in self.spawning.iter_mut() {
+            spawning.remaining = spawning.remaining.saturating_sub(1);
+        }
+        let finished: Vec<SpawningCreep> = self.spawning.drain(..).collect();
+        for spawning in finished {
+            let spawn_pos = self
+                .world
+                .spawns
+                .iter()
+                .find(|s| s.name == spawning.spawn)
+                .map(|s| s.pos);
+            let exit = spawn_pos.and_then(|pos| {
+                pos.xy()
+                    .neighbors()
+                    .into_iter()
+                    .map(|xy| position_at(xy, pos.room_name()))
+                    .find(|pos| self.is_walkable(*pos) && !self.is_occupied(*pos))
+            });
+            match exit {
+                Some(pos) if spawning.remaining == 0 => {
+                    self.world
+                        .add_creep(&spawning.name, pos, spawning.body, spawning.memory);
+                    if let Some(spawn) = self
+                        .world
+                        .spawns
+                        .iter_mut()
+                        .find(|s| s.name == spawning.spawn)
+                    {
+                        spawn.spawning = false;
+                    }
+                }
+                _ => self.spawning.push(spawning),
+            }
+        }
+
+        for source in self
+            .world
+            .rooms
+            .iter_mut()
+            .flat_map(|room| room.sources.iter_mut())
+        {
+            if let Some(ticks) = source.ticks_to_regeneration {
+                if ticks <= 1 {
+                    source.energy = source.energy_capacity;
+                    source.ticks_to_regeneration = None;
+                } else {
+                    source.ticks_to_regeneration = Some(ticks - 1);
+                }
+            }
+        }
+
+        for controller in self
+            .world
+            .rooms
+            .iter_mut()
+            .filter_map(|room| room.controller.as_mut())
+        {
+            controller.safe_mode = controller
+                .safe_mode
+                .filter(|ticks| *ticks > 1)
+                .map(|ticks| ticks - 1);
+            controller.safe_mode_cooldown = controller
+                .safe_mode_cooldown
+                .filter(|ticks| *ticks > 1)
+                .map(|ticks| ticks - 1);
+        }
+
+        // Roads, containers and ramparts wear away, and are destroyed once they run out of hits
+        let time = self.world.time;
+        for room in self.world.rooms.iter_mut() {
+            room.structures.retain_mut(|structure| {
+                let (amount, every) = match structure.structure_type {
+                    StructureType::Road => (ROAD_DECAY_AMOUNT, ROAD_DECAY_TIME),
+                    StructureType::Container => (CONTAINER_DECAY, CONTAINER_DECAY_TIME_OWNED),
+                    StructureType::Rampart => (RAMPART_DECAY_AMOUNT, RAMPART_DECAY_TIME),
+                    _ => return true,
+                };
+                if time.is_multiple_of(every) {
+                    structure.hits = structure.hits.saturating_sub(amount);
+                }
+                structure.hits > 0
+            });
+        }
+
+        // Spawns slowly refill themselves while the room is low on energy
+        for room in self.world.rooms.iter_mut() {
+            if room.energy_available >= SPAWN_ENERGY_START {
+                continue;
+            }
+            for spawn in room
+                .structures
+                .iter_mut()
+                .filter(|s| s.structure_type == StructureType::Spawn)
+            {
+                if let Some(store) = spawn.store.as_mut() {
+                    add_to_store(store, ResourceType::Energy, 1);
+                }
+            }
+        }
+        self.update_room_energy();
+    }
+
+    /// Recalculate each room's energy from its spawns and extensions
+    fn update_room_energy(&mut self) {
+        for room in self.world.rooms.iter_mut() {
+            let stores: Vec<StoreInfo> = room
+                .structures
+                .iter()
+                .filter(|s| {
+                    matches!(
+                        s.structure_type,
+                        StructureType::Spawn | StructureType::Extension
+                    )
+                })
+                .map(|s| s.store())
+                .collect();
+            room.energy_available = stores
+                .iter()
+                .map(|store| store.get_used_capacity(Some(ResourceType::Energy)))
+                .sum();
+            room.energy_capacity_available = stores
+                .iter()
+                .map(|store| store.get_capacity(Some(ResourceType::Energy)))
+                .sum();
+        }
+    }
+
+    fn structure_mut(&mut self, id: RawObjectId) -> Option<&mut StructureInfo> {
+        self.world
+            .rooms
+            .iter_mut()
+            .flat_map(|room| room.structures.iter_mut())
+            .find(|s| s.id == id)
+    }
+
+    /// The store of a structure, tombstone or ruin
+    fn withdrawable_mut(&mut self, id: RawObjectId) -> Option<&mut StoreInfo> {
+        let room = self.world.rooms.iter_mut().find(|room| {
+            room.structures.iter().any(|s| s.id == id) || room.remains.iter().any(|r| r.id == id)
+        })?;
+        match room.structures.iter_mut().find(|s| s.id == id) {
+            Some(structure) => structure.store.as_mut(),
+            None => room
+                .remains
+                .iter_mut()
+                .find(|r| r.id == id)
+                .map(|remains| &mut remains.store),
+        }
+    }
+
+    /// Drop resources on the ground, adding to any pile of the same resource already there
+    fn drop_resource(&mut self, pos: Position, resource_type: ResourceType, amount: u32) {
+        if amount == 0 {
+            return;
+        }
+        let room = self.world.room_mut(pos.room_name());
+        match room
+            .dropped
+            .iter_mut()
+            .find(|pile| pile.pos == pos && pile.resource_type == resource_type)
+        {
+            Some(pile) => pile.amount += amount,
+            None => {
+                self.world.add_dropped(pos, resource_type, amount);
+            }
+        }
+    }
+
+    fn terrain_at(&self, pos: Position) -> Terrain {
+        self.world
+            .rooms
+            .iter()
+            .find(|room| room.name == pos.room_name())
+            .map_or(Terrain::Plain, |room| room.terrain.get_xy(pos.xy()))
+    }
+
+    fn has_structure(&self, pos: Position, structure_type: StructureType) -> bool {
+        self.world.rooms.iter().any(|room| {
+            room.structures
+                .iter()
+                .any(|s| s.pos == pos && s.structure_type == structure_type)
+        })
+    }
+
+    /// Whether a creep could stand on the given tile, ignoring other creeps
+    fn is_walkable(&self, pos: Position) -> bool {
+        if self.terrain_at(pos) == Terrain::Wall {
+            return false;
+        }
+        let Some(room) = self.world.rooms.iter().find(|r| r.name == pos.room_name()) else {
+            return true;
+        };
+        let blocked_by_structure = room.structures.iter().any(|s| {
+            s.pos == pos
+                && !matches!(
+                    s.structure_type,
+                    StructureType::Road | StructureType::Container | StructureType::Rampart
+                )
+        });
+        let blocked_by_source = room.sources.iter().any(|s| s.pos == pos);
+        !blocked_by_structure && !blocked_by_source
+    }
+
+    fn is_occupied(&self, pos: Position) -> bool {
+        self.world.creeps.iter().any(|creep| creep.pos == pos)
+    }
+}
+
+fn position_at(xy: RoomXY, room: RoomName) -> Position {
+    Position::new(xy.x, xy.y, room)
+}
+
+fn count_parts(body: &[Part], part: Part) -> u32 {
+    body.iter().filter(|p| **p == part).count() as u32
+}
+
+/// Number of body parts which generate fatigue when moving. Empty carry parts weigh nothing
+fn weight(body: &[Part], store: &StoreInfo) -> u32 {
+    let carry_parts = count_parts(body, Part::Carry);
+    let loaded_carry = store.get_used_capacity(None).div_ceil(50).min(carry_parts);
+    body.len() as u32 - count_parts(body, Part::Move) - carry_parts + loaded_carry
+}
+
+/// Store a newly built structure starts with, if it has one
+fn new_structure_store(structure_type: StructureType, level: u8) -> Option<StoreInfo> {
+    let energy = |capacity| Some(StoreInfo::restricted(capacity, ResourceType::Energy));
+    match structure_type {
+        StructureType::Spawn => energy(SPAWN_ENERGY_CAPACITY),
+        StructureType::Extension => energy(extension_energy_capacity(level as u32)),
+        StructureType::Tower => energy(TOWER_CAPACITY),
+        StructureType::Link => energy(LINK_CAPACITY),
+        StructureType::Container => Some(StoreInfo::new(CONTAINER_CAPACITY)),
+        StructureType::Storage => Some(StoreInfo::new(STORAGE_CAPACITY)),
+        _ => None,
+    }
+}
+
+/// Add up to `amount` of a resource to a store, returning how much fit
+fn add_to_store(store: &mut StoreInfo, resource: ResourceType, amount: u32) -> u32 {
+    let added = amount.min(store.get_free_capacity(Some(resource)).max(0) as u32);
+    let current = store.get_used_capacity(Some(resource));
+    store.set(resource, current + added);
+    added
+}
+
+/// Remove up to `amount` of a resource from a store, returning how much was taken
+fn remove_from_store(store: &mut StoreInfo, resource: ResourceType, amount: u32) -> u32 {
+    let current = store.get_used_capacity(Some(resource));
+    let removed = amount.min(current);
+    store.set(resource, current - removed);
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{terrain_from_rows, test_room};
+
+    fn controller_level(world: &MockWorld) -> u8 {
+        world.rooms[0].controller.as_ref().map_or(0, |c| c.level)
+    }
+
+    #[test]
+    fn fresh_room_reaches_rcl2_within_5k_ticks() {
+        let mut simulator = Simulator::starter_room();
+        let ticks = simulator.run_until(5000, |world| controller_level(world) >= 2);
+        assert!(ticks.is_some(), "room is still at RCL {}", controller_level(simulator.world()));
+    }
+
+    #[test]
+    fn creeps_find_their_way_around_walls() {
+        // A ridge between the spawn and the second source, with a gap at the bottom
+        let rows: Vec<String> = (0..50)
+            .map(|y| {
+                (0..50)
+                    .map(|x| {
+                        let edge = x == 0 || y == 0 || x == 49 || y == 49;
+                        let ridge = x == 32 && y < 45;
+                        if edge || ridge {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let mut simulator = Simulator::starter_room();
+        simulator.set_terrain(test_room(), terrain_from_rows(&rows));
+        let far_source = simulator.world().rooms[0].sources[1].pos;
+
+        let ticks = simulator.run_until(3000, |world| {
+            world
+                .creeps
+                .iter()
+                .any(|creep| creep.pos.get_range_to(far_source) <= 1)
+        });
+        assert!(ticks.is_some(), "no creep reached the source behind the ridge");
+    }
+
+    #[test]
+    fn hostiles_at_the_spawn_trigger_safe_mode() {
+        let mut simulator = Simulator::starter_room();
+        simulator.run(10);
+        simulator.world_mut().add_hostile(
+            "Attacker",
+            position(26, 26, test_room()),
+            vec![Part::Work, Part::Attack, Part::Move],
+        );
+        simulator.tick();
+
+        assert_eq!(simulator.notifications().len(), 1);
+        let controller = simulator.world().rooms[0].controller.as_ref().unwrap();
+        assert!(controller.safe_mode.is_some());
+    }
+}
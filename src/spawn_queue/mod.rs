@@ -0,0 +1,300 @@
+// Queue of creeps waiting to be spawned in each room.
+// Anything that needs a creep (the room's economy, replacements, defense) pushes a request, and
+// each free spawn takes the most important request from its room. Creeps born from a request are
+// recorded until the next run, so whoever asked for them can follow up.
+use crate::screep_states::CreepMemory;
+use crate::state_controllers::Specialisation;
+use crate::utils::Body;
+use crate::world::World;
+use log::{info, warn};
+use screeps::RoomName;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// How urgently a creep is needed, higher priorities are spawned first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpawnPriority {
+    Low,
+    Normal,
+    High,
+    // Only for when the room can't function without this creep
+    Critical,
+}
+
+/// A creep that someone would like spawned
+#[derive(Debug, Clone)]
+pub struct SpawnRequest {
+    pub specialisation: Specialisation,
+    pub priority: SpawnPriority,
+    pub body: Body,
+    pub memory: CreepMemory,
+    // Game tick after which the request is dropped if it hasn't started spawning
+    pub deadline: u32,
+}
+
+impl SpawnRequest {
+    pub fn cost(&self) -> u32 {
+        self.body.cost
+    }
+}
+
+/// A request that a spawn has started on, waiting for the creep to be born
+struct InProgress {
+    room: RoomName,
+    name: String,
+    request: SpawnRequest,
+    // Game tick by which the creep should have been born. If it hasn't, the spawn failed or was
+    // cancelled, and the request is given up on so the room can spawn again
+    deadline: u32,
+}
+
+/// See the module docs
+#[derive(Default)]
+pub struct SpawnQueue {
+    requests: HashMap<RoomName, Vec<SpawnRequest>>,
+    in_progress: Vec<InProgress>,
+    // Creeps born since the previous run, along with the request they were spawned for
+    born: Vec<(String, SpawnRequest)>,
+}
+
+impl SpawnQueue {
+    pub fn new() -> Self {
+        SpawnQueue::default()
+    }
+
+    /// Add a request to the room's queue
+    pub fn push(&mut self, room: RoomName, request: SpawnRequest) {
+        info!(
+            "Queued {:?} in {} with {:?} priority",
+            request.specialisation, room, request.priority
+        );
+        self.requests.entry(room).or_default().push(request);
+    }
+
+    /// Requests waiting in the room, highest priority first
+    pub fn pending(&self, room: RoomName) -> Vec<&SpawnRequest> {
+        let mut pending: Vec<&SpawnRequest> = self
+            .requests
+            .get(&room)
+            .map(|requests| requests.iter().collect())
+            .unwrap_or_default();
+        pending.sort_by_key(|request| Reverse(request.priority));
+        pending
+    }
+
+    /// Requests in the room which are either waiting or currently spawning
+    pub fn queued(&self, room: RoomName) -> Vec<&SpawnRequest> {
+        let mut queued = self.pending(room);
+        queued.extend(
+            self.in_progress
+                .iter()
+                .filter(|spawning| spawning.room == room)
+                .map(|spawning| &spawning.request),
+        );
+        queued
+    }
+
+    /// Creeps born during the last run, with the request each one was spawned for
+    pub fn born(&self) -> &[(String, SpawnRequest)] {
+        &self.born
+    }
+
+    /// Start spawning requests on every free spawn, and record any creeps that were born
+    pub fn run(&mut self, world: &dyn World) {
+        let time = world.time();
+        self.born = self.take_born(world);
+        for (name, request) in self.born.iter() {
+            info!("{:?} {} has been born", request.specialisation, name);
+        }
+        for requests in self.requests.values_mut() {
+            requests.retain(|request| request.deadline >= time);
+        }
+
+        // Energy spent by spawns earlier this tick isn't reflected until next tick
+        let mut energy_spent: HashMap<RoomName, u32> = HashMap::new();
+        let mut spawned = 0;
+        for spawn in world.spawns() {
+            if spawn.is_spawning() {
+                continue;
+            }
+            let room_name = spawn.room_name();
+            let Some(room) = world.room(room_name) else {
+                continue;
+            };
+            let spent = energy_spent.entry(room_name).or_default();
+            let energy = room.energy_available().saturating_sub(*spent);
+            let Some(request) = self.pop(room_name, energy, room.energy_capacity_available())
+            else {
+                continue;
+            };
+
+            let name = format!("{:?}-{}-{}", request.specialisation, time, spawned);
+            match spawn.spawn_creep(&request.body.parts, &name, request.memory.clone()) {
+                Ok(()) => {
+                    info!("{} spawning {}", spawn.name(), name);
+                    *spent += request.cost();
+                    spawned += 1;
+                    self.in_progress.push(InProgress {
+                        room: room_name,
+                        name,
+                        deadline: time + request.body.spawn_time + 1,
+                        request,
+                    });
+                }
+                Err(e) => {
+                    warn!("couldn't spawn {}: {:?}", name, e);
+                    self.push(room_name, request);
+                }
+            }
+        }
+    }
+
+    /// Take the next request to spawn with the energy available.
+    /// A request we can't afford yet holds up anything of lower priority so the energy is saved
+    /// for it, unless it costs more than the room could ever hold
+    fn pop(&mut self, room: RoomName, energy: u32, capacity: u32) -> Option<SpawnRequest> {
+        let requests = self.requests.get_mut(&room)?;
+        // Stable sort, so equal priorities keep the order they were pushed in
+        requests.sort_by_key(|request| Reverse(request.priority));
+        let index = requests
+            .iter()
+            .position(|request| request.cost() <= capacity)?;
+        if requests[index].cost() > energy {
+            return None;
+        }
+        Some(requests.remove(index))
+    }
+
+    /// Remove creeps that have been born from the in progress list, along with any which are
+    /// overdue
+    fn take_born(&mut self, world: &dyn World) -> Vec<(String, SpawnRequest)> {
+        let time = world.time();
+        let names: Vec<String> = world.creeps().iter().map(|creep| creep.name()).collect();
+        let (born, waiting): (Vec<InProgress>, Vec<InProgress>) = self
+            .in_progress
+            .drain(..)
+            .partition(|spawning| names.contains(&spawning.name));
+        self.in_progress = waiting
+            .into_iter()
+            .filter(|spawning| {
+                let overdue = time > spawning.deadline;
+                if overdue {
+                    warn!("{} was never born, giving up on it", spawning.name);
+                }
+                !overdue
+            })
+            .collect();
+        born.into_iter()
+            .map(|spawning| (spawning.name, spawning.request))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::BodyPlan;
+    use crate::world::{position, test_room, Intent, MockWorld};
+    use screeps::Part;
+
+    fn world_with_spawn(energy: u32) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.room_mut(test_room()).energy_available = energy;
+        world.room_mut(test_room()).energy_capacity_available = 300;
+        world
+    }
+
+    fn request(specialisation: Specialisation, priority: SpawnPriority) -> SpawnRequest {
+        SpawnRequest {
+            body: BodyPlan::new().ratio(Part::Carry, 1).build(100),
+            memory: CreepMemory::new(specialisation.clone()),
+            specialisation,
+            priority,
+            deadline: 100,
+        }
+    }
+
+    fn spawned(world: &MockWorld) -> Vec<String> {
+        world
+            .take_intents()
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::SpawnCreep { name, .. } => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spawns_the_highest_priority_request_first() {
+        let world = world_with_spawn(300);
+        let mut queue = SpawnQueue::new();
+        queue.push(test_room(), request(Specialisation::Upgrader, SpawnPriority::Low));
+        queue.push(test_room(), request(Specialisation::Hauler, SpawnPriority::High));
+
+        queue.run(&world);
+        assert_eq!(spawned(&world), vec!["Hauler-0-0".to_string()]);
+        assert_eq!(queue.pending(test_room()).len(), 1);
+    }
+
+    #[test]
+    fn saves_energy_for_a_request_it_cannot_afford_yet() {
+        let world = world_with_spawn(50);
+        let mut queue = SpawnQueue::new();
+        queue.push(test_room(), request(Specialisation::Hauler, SpawnPriority::High));
+        queue.push(test_room(), request(Specialisation::Upgrader, SpawnPriority::Low));
+
+        queue.run(&world);
+        assert!(spawned(&world).is_empty());
+        assert_eq!(queue.pending(test_room()).len(), 2);
+    }
+
+    #[test]
+    fn records_creeps_once_they_are_born() {
+        let mut world = world_with_spawn(300);
+        let mut queue = SpawnQueue::new();
+        queue.push(test_room(), request(Specialisation::Hauler, SpawnPriority::Normal));
+        queue.run(&world);
+        assert!(queue.born().is_empty());
+        assert_eq!(queue.queued(test_room()).len(), 1);
+
+        let name = spawned(&world).remove(0);
+        world.add_creep(
+            &name,
+            position(25, 26, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world.time += 1;
+        queue.run(&world);
+        assert_eq!(queue.born().len(), 1);
+        assert_eq!(queue.born()[0].0, name);
+        assert!(queue.queued(test_room()).is_empty());
+
+        // Only kept until the next run
+        queue.run(&world);
+        assert!(queue.born().is_empty());
+    }
+
+    #[test]
+    fn gives_up_on_creeps_that_are_never_born() {
+        let mut world = world_with_spawn(300);
+        let mut queue = SpawnQueue::new();
+        let request = request(Specialisation::Hauler, SpawnPriority::Normal);
+        let spawn_time = request.body.spawn_time;
+        queue.push(test_room(), request);
+        queue.run(&world);
+        assert_eq!(queue.queued(test_room()).len(), 1);
+
+        world.time += spawn_time + 1;
+        queue.run(&world);
+        assert_eq!(queue.queued(test_room()).len(), 1);
+
+        world.time += 1;
+        queue.run(&world);
+        assert!(queue.queued(test_room()).is_empty());
+        assert!(queue.born().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+// Recovery for rooms whose economy has collapsed.
+// Bodies are normally sized from the room's energy capacity, so if every creep that refills the
+// spawn and extensions dies, the room can end up waiting forever for energy it will never have.
+// While bootstrapping, the room spawns small generalists from whatever energy it has instead.
+use super::{SCGeneralist, Specialisation};
+use crate::utils::{Body, BodyPlan};
+use crate::world::{RoomView, World};
+use log::{info, warn};
+use screeps::{Part, RoomName};
+use std::collections::HashMap;
+
+// How long the room's energy can go without rising before we consider it stalled
+const STALL_TICKS: u32 = 50;
+
+struct RoomEconomy {
+    last_energy: u32,
+    // Tick the energy last rose, or was full
+    last_rise: u32,
+    // Whether the room had any haulers or generalists last tick
+    had_carriers: bool,
+    bootstrapping: bool,
+}
+
+/// Tracks each room's energy to detect when its economy has collapsed
+#[derive(Default)]
+pub struct Bootstrap {
+    rooms: HashMap<RoomName, RoomEconomy>,
+}
+
+impl Bootstrap {
+    pub fn new() -> Self {
+        Bootstrap::default()
+    }
+
+    /// Update the room's economy for this tick, returning true while it needs bootstrapping.
+    /// A room has collapsed when there is nothing left to carry energy around (no haulers or
+    /// generalists) and it can't afford the bodies it normally spawns, either because its
+    /// carriers have just died or because its energy has stopped rising. New rooms, and rooms we
+    /// know nothing about after a global reset, go through the latter so they aren't bootstrapped
+    /// just for having no creeps yet. It recovers once it has carriers again and its spawns and
+    /// extensions are full
+    pub fn update(&mut self, world: &dyn World, room: &dyn RoomView) -> bool {
+        let time = world.time();
+        let energy = room.energy_available();
+        let capacity = room.energy_capacity_available();
+        let has_carriers = world.creeps_in_room(room.name()).iter().any(|creep| {
+            matches!(
+                creep.memory().specialisation(),
+                Specialisation::Hauler | Specialisation::Generalist
+            )
+        });
+        let economy = self.rooms.entry(room.name()).or_insert(RoomEconomy {
+            last_energy: energy,
+            last_rise: time,
+            had_carriers: has_carriers,
+            bootstrapping: false,
+        });
+        if energy > economy.last_energy || energy >= capacity {
+            economy.last_rise = time;
+        }
+        economy.last_energy = energy;
+        let lost_carriers = economy.had_carriers && !has_carriers;
+        economy.had_carriers = has_carriers;
+
+        let stalled = time - economy.last_rise >= STALL_TICKS;
+        let affordable = energy >= SCGeneralist::worker_body(room).cost;
+
+        if !economy.bootstrapping && !has_carriers && !affordable && (lost_carriers || stalled) {
+            warn!(
+                "Economy in {} has collapsed ({}/{} energy), bootstrapping",
+                room.name(),
+                energy,
+                capacity
+            );
+            economy.bootstrapping = true;
+        } else if economy.bootstrapping && has_carriers && energy >= capacity {
+            info!("Economy in {} has recovered", room.name());
+            economy.bootstrapping = false;
+        }
+        economy.bootstrapping
+    }
+}
+
+/// The biggest generalist body we can spawn right now with the given energy.
+/// Empty if we can't even afford the smallest one
+pub fn bootstrap_body(energy: u32) -> Body {
+    BodyPlan::new()
+        .ratio(Part::Work, 1)
+        .ratio(Part::Carry, 1)
+        .move_ratio(2, 2)
+        .build(energy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screep_states::CreepMemory;
+    use crate::world::{position, test_room, MockWorld, StoreInfo};
+    use screeps::{ResourceType, StructureType};
+
+    /// A room with a spawn and ten extensions, holding the given energy out of 800
+    fn world_with_extensions(energy: u32) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        for x in 20..30 {
+            world.add_structure(
+                StructureType::Extension,
+                position(x, 20, test_room()),
+                Some(StoreInfo::restricted(50, ResourceType::Energy)),
+            );
+        }
+        world.room_mut(test_room()).energy_available = energy;
+        world.room_mut(test_room()).energy_capacity_available = 800;
+        world
+    }
+
+    fn add_hauler(world: &mut MockWorld) {
+        world.add_creep(
+            "hauler",
+            position(25, 26, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+    }
+
+    fn update(bootstrap: &mut Bootstrap, world: &MockWorld) -> bool {
+        let room = world.room(test_room()).unwrap();
+        bootstrap.update(world, room.as_ref())
+    }
+
+    #[test]
+    fn new_room_without_creeps_is_not_bootstrapped() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.room_mut(test_room()).energy_available = 300;
+        world.room_mut(test_room()).energy_capacity_available = 300;
+        let mut bootstrap = Bootstrap::new();
+
+        for _ in 0..100 {
+            assert!(!update(&mut bootstrap, &world));
+            world.time += 1;
+        }
+    }
+
+    #[test]
+    fn losing_every_carrier_bootstraps_the_room() {
+        let mut world = world_with_extensions(100);
+        add_hauler(&mut world);
+        let mut bootstrap = Bootstrap::new();
+        assert!(!update(&mut bootstrap, &world));
+
+        world.creeps.clear();
+        world.time += 1;
+        assert!(update(&mut bootstrap, &world));
+
+        // Recovered once there's a carrier again and the extensions are full
+        add_hauler(&mut world);
+        world.room_mut(test_room()).energy_available = 800;
+        world.time += 1;
+        assert!(!update(&mut bootstrap, &world));
+    }
+
+    #[test]
+    fn room_without_creeps_bootstraps_once_its_energy_stalls() {
+        let mut world = world_with_extensions(100);
+        let mut bootstrap = Bootstrap::new();
+
+        for _ in 0..STALL_TICKS {
+            assert!(!update(&mut bootstrap, &world));
+            world.time += 1;
+        }
+        assert!(update(&mut bootstrap, &world));
+    }
+
+    #[test]
+    fn losing_carriers_with_enough_energy_for_a_normal_body_is_not_a_collapse() {
+        let mut world = world_with_extensions(800);
+        add_hauler(&mut world);
+        let mut bootstrap = Bootstrap::new();
+        assert!(!update(&mut bootstrap, &world));
+
+        world.creeps.clear();
+        world.time += 1;
+        assert!(!update(&mut bootstrap, &world));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::defense::{HostileThreat, RoomThreat};
+use crate::screep_states::*;
+use crate::utils::{Body, BodyPlan};
+use crate::world::{CreepView, RoomView, World};
+use screeps::{Part, Position, StructureType};
+
+use super::{Specialisation, StateController};
+
+/// Defender State Controller for creeps that fight off hostiles in their room, either in melee or
+/// from range. Defenders hold ramparts when the room has them, and are recycled once the room is
+/// clear
+pub struct SCDefender {
+    pub current_state: Box<dyn ScreepState>,
+    ranged: bool,
+}
+
+impl SCDefender {
+    pub fn new() -> Self {
+        SCDefender {
+            current_state: Box::new(IdleState {}),
+            ranged: false,
+        }
+    }
+
+    pub fn new_ranged() -> Self {
+        SCDefender {
+            current_state: Box::new(IdleState {}),
+            ranged: true,
+        }
+    }
+}
+
+impl StateController for SCDefender {
+    fn get_name(&self) -> &'static str {
+        if self.ranged {
+            Specialisation::RangedDefender.into()
+        } else {
+            Specialisation::Defender.into()
+        }
+    }
+
+    fn current_state(&self) -> &dyn ScreepState {
+        self.current_state.as_ref()
+    }
+
+    fn set_current_state(&mut self, state: Box<dyn ScreepState>) {
+        self.current_state = state;
+    }
+
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState> {
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
+
+        // Back off and heal up before we lose too many parts
+        if creep.body().contains(&Part::Heal) && creep.hits() < creep.hits_max() / 2 {
+            return Box::new(HealSelfState);
+        }
+
+        let threat = RoomThreat::new(room.hostile_creeps());
+        if let Some(target) = find_target(creep, &threat) {
+            let range = if self.ranged { RANGED_ATTACK_RANGE } else { 1 };
+            let post = find_post(creep, world, room.as_ref(), target.pos(), range);
+            return if self.ranged {
+                Box::new(RangedAttackState::new(target.info.id, post))
+            } else {
+                Box::new(AttackState::new(target.info.id, post))
+            };
+        }
+
+        // The room is clear, so we aren't needed any more
+        let spawn = room
+            .structures()
+            .into_iter()
+            .filter(|s| s.structure_type == StructureType::Spawn)
+            .min_by_key(|s| creep.pos().get_range_to(s.pos));
+        if let Some(spawn) = spawn {
+            return Box::new(RecycleState::new(spawn.id));
+        }
+        Box::new(IdleState {})
+    }
+
+    fn should_interrupt(&self, creep: &dyn CreepView, world: &dyn World) -> bool {
+        // Hostiles can turn up while we walk back to be recycled
+        if self.current_state.get_state_name() != StateName::Recycle {
+            return false;
+        }
+        world.room(creep.pos().room_name()).is_some_and(|room| {
+            find_target(creep, &RoomThreat::new(room.hostile_creeps())).is_some()
+        })
+    }
+
+    fn get_best_worker_body(&self, room: &dyn RoomView) -> Body {
+        let energy = room.energy_capacity_available();
+        if !self.ranged {
+            return BodyPlan::new()
+                .ratio(Part::Tough, 1)
+                .ratio(Part::Attack, 1)
+                .build(energy);
+        }
+        let body = BodyPlan::new()
+            .ratio(Part::RangedAttack, 2)
+            .ratio(Part::Heal, 1)
+            .build(energy);
+        // Low level rooms can't afford HEAL parts yet
+        if body.is_empty() {
+            return BodyPlan::new().ratio(Part::RangedAttack, 1).build(energy);
+        }
+        body
+    }
+}
+
+/// The nearest hostile that can do any damage
+fn find_target<'a>(creep: &dyn CreepView, threat: &'a RoomThreat) -> Option<&'a HostileThreat> {
+    threat
+        .hostiles
+        .iter()
+        .filter(|hostile| hostile.is_dangerous())
+        .min_by_key(|hostile| creep.pos().get_range_to(hostile.pos()))
+}
+
+/// The free rampart closest to the target that we can hit it from, if the room has any
+fn find_post(
+    creep: &dyn CreepView,
+    world: &dyn World,
+    room: &dyn RoomView,
+    target: Position,
+    range: u32,
+) -> Option<Position> {
+    let occupied: Vec<Position> = world
+        .creeps_in_room(room.name())
+        .iter()
+        .filter(|other| other.name() != creep.name())
+        .map(|other| other.pos())
+        .collect();
+    room.structures()
+        .into_iter()
+        .filter(|s| s.structure_type == StructureType::Rampart && !occupied.contains(&s.pos))
+        .filter(|s| s.pos.in_range_to(target, range))
+        .min_by_key(|s| {
+            (
+                s.pos.get_range_to(target),
+                creep.pos().get_range_to(s.pos),
+            )
+        })
+        .map(|s| s.pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+
+    fn world_with_defender(body: Vec<Part>) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.add_creep(
+            "defender",
+            position(25, 20, test_room()),
+            body,
+            CreepMemory::new(Specialisation::RangedDefender),
+        );
+        world
+    }
+
+    #[test]
+    fn defender_attacks_the_nearest_dangerous_hostile() {
+        let mut world = world_with_defender(vec![Part::RangedAttack, Part::Move]);
+        // Can't hurt anything, so not worth shooting at
+        world.add_hostile("Invader", position(24, 20, test_room()), vec![Part::Move]);
+        let hostile =
+            world.add_hostile("Invader", position(10, 20, test_room()), vec![Part::Attack]);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCDefender::new_ranged();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(
+            controller.current_state().get_state_name(),
+            StateName::RangedAttack
+        );
+        assert_eq!(controller.current_state().get_target(), Some(hostile.into()));
+    }
+
+    #[test]
+    fn wounded_defender_heals_itself() {
+        let mut world = world_with_defender(vec![Part::RangedAttack, Part::Heal, Part::Move]);
+        world.add_hostile("Invader", position(10, 20, test_room()), vec![Part::Attack]);
+        world.creep_mut("defender").hits = 100;
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCDefender::new_ranged();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::HealSelf);
+    }
+
+    #[test]
+    fn defender_is_recycled_once_the_room_is_clear() {
+        let world = world_with_defender(vec![Part::Attack, Part::Move]);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCDefender::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Recycle);
+    }
+
+    #[test]
+    fn recycling_defender_turns_back_for_new_hostiles() {
+        let mut world = world_with_defender(vec![Part::Attack, Part::Move]);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCDefender::new();
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Recycle);
+
+        drop(creep);
+        world.add_hostile("Invader", position(10, 20, test_room()), vec![Part::Attack]);
+        let creep = world.creeps()[0].clone();
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Attack);
+    }
+
+    #[test]
+    fn defender_only_holds_ramparts_it_can_hit_the_target_from() {
+        let mut world = world_with_defender(vec![Part::Attack, Part::Move]);
+        world.add_structure(StructureType::Rampart, position(20, 20, test_room()), None);
+        let near = position(11, 20, test_room());
+        world.add_structure(StructureType::Rampart, near, None);
+        let creep = world.creeps()[0].clone();
+        let room = world.room(test_room()).unwrap();
+
+        let target = position(10, 20, test_room());
+        assert_eq!(find_post(creep.as_ref(), &world, room.as_ref(), target, 1), Some(near));
+        let target = position(10, 30, test_room());
+        assert_eq!(find_post(creep.as_ref(), &world, room.as_ref(), target, 1), None);
+    }
+}
@@ -1,22 +1,36 @@
 use crate::screep_states::*;
+use crate::tower_manager::RepairPolicy;
 use crate::utils;
+use crate::utils::{Body, BodyPlan};
 use crate::utils::prelude::*;
+use crate::world::{CreepView, RoomView, World};
 use log::warn;
-use screeps::{constants::ResourceType, enums::StructureObject, find, objects::Creep, prelude::*, ObjectId, Part, Room};
+use screeps::{constants::ResourceType, prelude::*, Part, RawObjectId, StructureType};
 
 use super::{Specialisation, StateController};
 
 /// Generalist State Controller for managing a sawdcreep that performs a variety of tasks
 pub struct SCGeneralist {
     pub current_state: Box<dyn ScreepState>,
+    repair_policy: RepairPolicy,
 }
 
 impl SCGeneralist {
-    pub fn new() -> Self {
+    pub fn new(repair_policy: RepairPolicy) -> Self {
         SCGeneralist {
             current_state: Box::new(IdleState {}),
+            repair_policy,
         }
     }
+
+    // Create a generalist with both Carry and Work with one Move per Carry and Work
+    pub fn worker_body(room: &dyn RoomView) -> Body {
+        BodyPlan::new()
+            .ratio(Part::Work, 1)
+            .ratio(Part::Carry, 1)
+            .roads(utils::has_roads(room))
+            .build(utils::get_total_upgrade_energy(room))
+    }
 }
 
 impl StateController for SCGeneralist {
@@ -24,44 +38,52 @@ impl StateController for SCGeneralist {
         Specialisation::Generalist.into()
     }
 
-    fn current_state(&self) -> &Box<dyn ScreepState> {
-        &self.current_state
+    fn current_state(&self) -> &dyn ScreepState {
+        self.current_state.as_ref()
     }
 
     fn set_current_state(&mut self, state: Box<dyn ScreepState>) {
         self.current_state = state;
     }
 
-    fn choose_next_state(&mut self, creep: &Creep) -> Box<dyn ScreepState> {
-        let room = creep.room().expect("couldn't resolve creep room");
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState> {
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
         let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
         if energy == 0 {
+            // Loose energy decays, so collect it before it is lost
+            if let Some(loot) = find_best_loot(creep, room.as_ref(), Some(ResourceType::Energy)) {
+                return match loot {
+                    Loot::Pile(pile) => Box::new(PickupState::new(pile.id)),
+                    Loot::Remains(remains) => {
+                        Box::new(WithdrawState::new(remains.id, Some(ResourceType::Energy)))
+                    }
+                };
+            }
+
             // Find the closest container with energy to drain
-            let mut closest_container: Option<ObjectId<screeps::objects::StructureContainer>> = None;
+            let mut closest_container: Option<RawObjectId> = None;
             let mut min_distance = u32::MAX;
-            for structure in room.find(find::STRUCTURES, None).iter() {
-                if let StructureObject::StructureContainer(container) = structure {
-                    if container
-                        .store()
-                        .get_used_capacity(Some(ResourceType::Energy))
-                        > 0
-                    {
-                        let distance = creep.pos().get_range_to(container.pos());
-                        if distance < min_distance {
-                            min_distance = distance;
-                            closest_container = Some(container.id());
-                        }
+            for structure in room.structures().iter() {
+                if structure.structure_type == StructureType::Container
+                    && unreserved_energy(structure) > 0
+                {
+                    let distance = creep.pos().get_range_to(structure.pos());
+                    if distance < min_distance {
+                        min_distance = distance;
+                        closest_container = Some(structure.id);
                     }
                 }
             }
 
             // If we found a container with energy, harvest from it
             if let Some(container_id) = closest_container {
-                return Box::new(WithdrawState::new(container_id));
+                return Box::new(WithdrawState::new(container_id, Some(ResourceType::Energy)));
             } else {
                 // Attempt to find some sources to harvest
-                if let Some(source) = find_nearest_object(&creep.pos(), &room, find::SOURCES_ACTIVE) {
-                    return Box::new(HarvestState::new(source));
+                if let Some(source) = find_nearest_object(&creep.pos(), &find_active_sources(world, room.as_ref(), creep)) {
+                    return Box::new(HarvestState::new(source.id));
                 } else {
                     warn!("No sources found for creep {}", creep.name());
                     return Box::new(IdleState {});
@@ -70,74 +92,139 @@ impl StateController for SCGeneralist {
         }
 
         // Check if the base needs energy
-        let upgrade_energy = get_total_upgrade_energy(&room);
+        let upgrade_energy = get_total_upgrade_energy(room.as_ref());
         let energy_available = room.energy_available();
         if energy_available < upgrade_energy {
             // Find a structure to feed energy to
-            for structure in room.find(find::STRUCTURES, None).iter() {
-                if let StructureObject::StructureSpawn(spawn) = structure {
-                    if spawn.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                        return Box::new(
-                            FeedStructureState::<screeps::objects::StructureSpawn>::new(spawn.id()),
-                        );
-                    }
-                } else if let StructureObject::StructureExtension(extension) = structure {
-                    if extension
-                        .store()
-                        .get_free_capacity(Some(ResourceType::Energy))
-                        > 0
-                    {
-                        return Box::new(
-                            FeedStructureState::<screeps::objects::StructureExtension>::new(
-                                extension.id(),
-                            ),
-                        );
-                    }
+            for structure in room.structures().iter() {
+                if matches!(
+                    structure.structure_type,
+                    StructureType::Spawn | StructureType::Extension
+                ) && unreserved_free_energy(structure) > 0
+                {
+                    return Box::new(FeedStructureState::new(
+                        structure.id,
+                        Some(ResourceType::Energy),
+                    ));
                 }
             }
         }
 
-        let build_count = self.count_state_instances(&room, &StateName::Build);
-        let upgrade_count = self.count_state_instances(&room, &StateName::Upgrade);
+        // Only one generalist repairs at a time, the rest keep the room running
+        let repair_count = self.count_state_instances(world, room.as_ref(), &StateName::Repair);
+        if repair_count < 1 {
+            if let Some(structure) = find_repair_target(creep, room.as_ref(), &self.repair_policy) {
+                return Box::new(RepairState::new(structure.id, self.repair_policy.clone()));
+            }
+        }
+
+        let build_count = self.count_state_instances(world, room.as_ref(), &StateName::Build);
+        let upgrade_count = self.count_state_instances(world, room.as_ref(), &StateName::Upgrade);
         // limit build creeps to 2, only build if we have an upgrade creep
         if build_count < 2 && upgrade_count > 0 {
-            if let Some(site) = find_nearest_construction_site(creep, &room) {
-                return Box::new(BuildState::new(site.clone()));
+            if let Some(site) = find_nearest_construction_site(creep, room.as_ref()) {
+                return Box::new(BuildState::new(site.id));
             }
         }
 
         // upgrade controller
-        for structure in room.find(find::STRUCTURES, None).iter() {
-            if let StructureObject::StructureController(controller) = structure {
-                return Box::new(UpgradeState::new(controller.id()));
-            }
+        if let Some(controller) = room.controller() {
+            return Box::new(UpgradeState::new(controller.id));
         }
 
         // return idle state if no other states are compatible
         Box::new(IdleState {})
     }
 
-    // Create a generalist with both Carry and Work with one Move per Carry and Work
-    fn get_best_worker_body(&self, room: &Room) -> Vec<Part> {
-        let mut base_body = vec![];
-        let blueprint = vec![
-            Part::Move,
-            Part::Carry,
-            Part::Work,
-            Part::Move,
-        ];
-        let blueprint_cost = blueprint.iter().map(|p: &Part| p.cost()).sum::<u32>();
-        let energy_available: u32 = utils::get_total_upgrade_energy(room);
-        let mut cost = base_body.iter().map(|p: &Part| p.cost()).sum::<u32>();
-
-        // keep adding parts from blueprint until we reach the energy limit
-        while cost + blueprint_cost <= energy_available {
-            for part in blueprint.iter() {
-                base_body.push(*part);
-                cost += part.cost();
-            }
+    fn get_best_worker_body(&self, room: &dyn RoomView) -> Body {
+        SCGeneralist::worker_body(room)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{plain_terrain, position, test_room, MockWorld, StoreInfo};
+
+    /// A room with an empty spawn, a source and a generalist carrying `energy`
+    fn world_with_generalist(energy: u32) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 10, test_room()), 1);
+        world.add_source(position(10, 10, test_room()));
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.room_mut(test_room()).structures[1].store =
+            Some(StoreInfo::restricted(300, ResourceType::Energy));
+        world.add_creep(
+            "generalist",
+            position(20, 20, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Generalist),
+        );
+        world.creep_mut("generalist").store =
+            StoreInfo::new(50).with(ResourceType::Energy, energy);
+        world
+    }
+
+    #[test]
+    fn empty_generalist_harvests_without_containers() {
+        let world = world_with_generalist(0);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCGeneralist::new(RepairPolicy::default());
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Harvest);
+    }
+
+    #[test]
+    fn generalist_feeds_the_spawn_before_upgrading() {
+        let mut world = world_with_generalist(50);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCGeneralist::new(RepairPolicy::default());
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(
+            controller.current_state().get_state_name(),
+            StateName::FeedStructure
+        );
+
+        drop(creep);
+        world.room_mut(test_room()).structures[1].store =
+            Some(StoreInfo::restricted(300, ResourceType::Energy).with(ResourceType::Energy, 300));
+        world.room_mut(test_room()).energy_available = 300;
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCGeneralist::new(RepairPolicy::default());
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Upgrade);
+    }
+
+    #[test]
+    fn generalist_skips_sources_with_no_free_tiles() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room()).terrain = plain_terrain();
+        world.add_controller(position(25, 10, test_room()), 1);
+        // Only three tiles next to a source in the corner, and all of them are taken
+        world.add_source(position(1, 1, test_room()));
+        let far = world.add_source(position(40, 40, test_room()));
+        for (i, (x, y)) in [(2, 1), (1, 2), (2, 2)].into_iter().enumerate() {
+            world.add_creep(
+                &format!("harvester{}", i),
+                position(x, y, test_room()),
+                vec![Part::Work, Part::Carry, Part::Move],
+                CreepMemory::new(Specialisation::Generalist),
+            );
         }
+        world.add_creep(
+            "generalist",
+            position(5, 5, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Generalist),
+        );
+        let creep = world.creeps()[3].clone();
+        let mut controller = SCGeneralist::new(RepairPolicy::default());
 
-        base_body
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Harvest);
+        assert_eq!(controller.current_state().get_target(), Some(far.into()));
     }
 }
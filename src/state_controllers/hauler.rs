@@ -1,12 +1,13 @@
 use super::{Specialisation, StateController};
+use crate::link_manager::{links_with_role, LinkRole};
 use crate::screep_states::*;
 use crate::utils;
-use log::{info, warn};
-use screeps::{
-    constants::ResourceType, enums::StructureObject, find, objects::Creep, prelude::*, ObjectId,
-    Part, Room,
-};
-use crate::utils::{find_nearest_construction_site, get_total_upgrade_energy};
+use crate::utils::{Body, BodyPlan};
+use crate::utils::{find_best_loot, get_total_upgrade_energy, Loot};
+use crate::utils::{unreserved_amount, unreserved_free_energy};
+use crate::world::{CreepView, RoomView, StructureInfo, World};
+use log::warn;
+use screeps::{constants::ResourceType, Part, RawObjectId, StructureType};
 
 /// Hauler State Controller for getting energy from containers and moving to the Storage
 pub struct SCHauler {
@@ -20,30 +21,59 @@ impl SCHauler {
         }
     }
 
-    fn find_container(&self, room: &Room) -> Option<ObjectId<screeps::objects::StructureContainer>> {
-        // Find the container with the most energy to drain
-        let mut best_container: Option<ObjectId<screeps::objects::StructureContainer>> = None;
-        let mut max_energy = 0;
-        for structure in room.find(find::STRUCTURES, None).iter() {
-            if let StructureObject::StructureContainer(container) = structure {
-                if container
-                    .store()
-                    .get_used_capacity(Some(ResourceType::Energy))
-                    > 0
-                {
-                    let energy_in_container = container
-                        .store()
-                        .get_used_capacity(Some(ResourceType::Energy));
-                    if energy_in_container > max_energy {
-                        max_energy = energy_in_container;
-                        best_container = Some(container.id());
-                    }
-                }
+    fn find_container(
+        &self,
+        room: &dyn RoomView,
+        resource: Option<ResourceType>,
+    ) -> Option<RawObjectId> {
+        // Find the container (or storage link) with the most of the resource (or everything)
+        // to drain
+        let mut best_container: Option<RawObjectId> = None;
+        let mut max_amount = 0;
+        let structures = room.structures();
+        let links: Vec<&StructureInfo> = structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Link)
+            .collect();
+        let storage_links = links_with_role(room, LinkRole::Storage);
+        let has_receiver =
+            !storage_links.is_empty() || !links_with_role(room, LinkRole::Controller).is_empty();
+        let mut stores: Vec<StructureInfo> = structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Container)
+            // Containers with a link next to them have their energy sent through the link, so
+            // aren't worth the trip, once there is a link for it to be sent to
+            .filter(|container| {
+                !has_receiver || !links.iter().any(|link| link.pos.is_near_to(container.pos))
+            })
+            .cloned()
+            .collect();
+        stores.extend(storage_links);
+        for structure in stores.iter() {
+            // Don't count what other haulers are already on their way to collect
+            let amount_in_container = unreserved_amount(structure.id, &structure.store(), resource);
+            if amount_in_container > max_amount {
+                max_amount = amount_in_container;
+                best_container = Some(structure.id);
             }
         }
 
         best_container
     }
+
+    /// Somewhere to take resources other than energy, which spawns and extensions can't hold
+    fn find_depot(&self, room: &dyn RoomView) -> Option<RawObjectId> {
+        room.structures()
+            .iter()
+            .filter(|structure| {
+                matches!(
+                    structure.structure_type,
+                    StructureType::Storage | StructureType::Terminal
+                )
+            })
+            .find(|structure| structure.store().get_free_capacity(None) > 0)
+            .map(|structure| structure.id)
+    }
 }
 
 impl StateController for SCHauler {
@@ -51,8 +81,8 @@ impl StateController for SCHauler {
         Specialisation::Hauler.into()
     }
 
-    fn current_state(&self) -> &Box<dyn ScreepState> {
-        &self.current_state
+    fn current_state(&self) -> &dyn ScreepState {
+        self.current_state.as_ref()
     }
 
     fn set_current_state(&mut self, state: Box<dyn ScreepState>) {
@@ -60,87 +90,93 @@ impl StateController for SCHauler {
     }
 
 
-    fn choose_next_state(&mut self, creep: &Creep) -> Box<dyn ScreepState> {
-        let room = creep.room().expect("couldn't resolve creep room");
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState> {
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
         let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
+        let carried = creep.store().get_used_capacity(None);
+        // Only collect resources other than energy when there is somewhere to take them
+        let depot = self.find_depot(room.as_ref());
+        let cargo = match depot {
+            Some(_) => None,
+            None => Some(ResourceType::Energy),
+        };
+
+        // Anything other than energy goes to the storage or terminal, never spawns or extensions
+        if carried > energy {
+            if let Some(depot) = depot {
+                return Box::new(FeedStructureState::new(depot, None));
+            }
+        }
 
         if energy == 0 {
-            // Find the container with the most energy to drain
-            let best_container = self.find_container(&room);
+            // Loose resources decay, so collect them before they are lost
+            if let Some(loot) = find_best_loot(creep, room.as_ref(), cargo) {
+                return match loot {
+                    Loot::Pile(pile) => Box::new(PickupState::new(pile.id)),
+                    Loot::Remains(remains) => Box::new(WithdrawState::new(remains.id, cargo)),
+                };
+            }
 
-            // If we found a container with energy, harvest from it
+            // Find the container with the most to drain
+            let best_container = self.find_container(room.as_ref(), cargo);
+
+            // If we found a container with something in it, drain it
             if let Some(container_id) = best_container {
-                return Box::new(WithdrawState::new(container_id));
+                return Box::new(WithdrawState::new(container_id, cargo));
             } else {
                 warn!("No containers found for creep {}", creep.name());
                 return Box::new(IdleState {});
             }
         }
 
+        let energy_only = Some(ResourceType::Energy);
         // Attempt to find a storage structure to feed energy to
-        for structure in room.find(find::STRUCTURES, None).iter() {
-            if let StructureObject::StructureStorage(storage) = structure {
-                if storage
+        for structure in room.structures().iter() {
+            if structure.structure_type == StructureType::Storage
+                && structure
                     .store()
                     .get_free_capacity(Some(ResourceType::Energy))
                     > 0
-                {
-                    return Box::new(
-                        FeedStructureState::<screeps::objects::StructureStorage>::new(
-                            storage.id(),
-                        ),
-                    );
-                }
+            {
+                return Box::new(FeedStructureState::new(structure.id, energy_only));
             }
         }
 
         // Check if the base needs energy
-        let upgrade_energy = get_total_upgrade_energy(&room);
+        let upgrade_energy = get_total_upgrade_energy(room.as_ref());
         let energy_available = room.energy_available();
         if energy_available < upgrade_energy {
             // Find a structure to feed energy to
-            for structure in room.find(find::STRUCTURES, None).iter() {
-                if let StructureObject::StructureSpawn(spawn) = structure {
-                    if spawn.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                        return Box::new(
-                            FeedStructureState::<screeps::objects::StructureSpawn>::new(spawn.id()),
-                        );
-                    }
-                } else if let StructureObject::StructureExtension(extension) = structure {
-                    if extension
-                        .store()
-                        .get_free_capacity(Some(ResourceType::Energy))
-                        > 0
-                    {
-                        return Box::new(
-                            FeedStructureState::<screeps::objects::StructureExtension>::new(
-                                extension.id(),
-                            ),
-                        );
-                    }
+            for structure in room.structures().iter() {
+                if matches!(
+                    structure.structure_type,
+                    StructureType::Spawn | StructureType::Extension
+                ) && unreserved_free_energy(structure) > 0
+                {
+                    return Box::new(FeedStructureState::new(structure.id, energy_only));
                 }
             }
         }
 
         // Check if we have towers that need energy
-        for structure in room.find(find::STRUCTURES, None).iter() {
-            if let StructureObject::StructureTower(tower) = structure {
-                if tower.store().get_free_capacity(Some(ResourceType::Energy)) > 0 {
-                    return Box::new(FeedStructureState::<screeps::objects::StructureTower>::new(
-                        tower.id(),
-                    ));
-                }
+        for structure in room.structures().iter() {
+            if structure.structure_type == StructureType::Tower
+                && unreserved_free_energy(structure) > 0
+            {
+                return Box::new(FeedStructureState::new(structure.id, energy_only));
             }
         }
 
         // Try fill up from container because we have nothing better to do...
         if creep.store().get_free_capacity(Some(ResourceType::Energy)) > 0
         {
-            // Find the container with the most energy to drain
-            let best_container = self.find_container(&room);
-            // If we found a container with energy, harvest from it
+            // Find the container with the most to drain
+            let best_container = self.find_container(room.as_ref(), cargo);
+            // If we found a container with something in it, drain it
             if let Some(container_id) = best_container {
-                return Box::new(WithdrawState::new(container_id));
+                return Box::new(WithdrawState::new(container_id, cargo));
             } else {
                 warn!("No containers found for creep {}", creep.name());
                 return Box::new(IdleState {});
@@ -152,24 +188,156 @@ impl StateController for SCHauler {
     }
 
     // TODO What is the best ratio for carry to move?
-    fn get_best_worker_body(&self, room: &Room) -> Vec<Part> {
-        let mut base_body = vec![];
-        let blueprint = vec![
-            Part::Move,
-            Part::Carry,
-        ];
-        let blueprint_cost = blueprint.iter().map(|p: &Part| p.cost()).sum::<u32>();
-        let energy_available: u32 = utils::get_total_upgrade_energy(room);
-        let mut cost = base_body.iter().map(|p: &Part| p.cost()).sum::<u32>();
-
-        // keep adding parts from blueprint until we reach the energy limit
-        while cost + blueprint_cost <= energy_available {
-            for part in blueprint.iter() {
-                base_body.push(*part);
-                cost += part.cost();
-            }
-        }
+    fn get_best_worker_body(&self, room: &dyn RoomView) -> Body {
+        BodyPlan::new()
+            .ratio(Part::Carry, 1)
+            .roads(utils::has_roads(room))
+            .build(utils::get_total_upgrade_energy(room))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+
+    /// A room with a half empty spawn and an empty tower, and a hauler next to both
+    fn world_with_hauler(energy: u32) -> (MockWorld, RawObjectId, RawObjectId) {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let spawn = world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.room_mut(test_room()).structures[0].store =
+            Some(StoreInfo::restricted(300, ResourceType::Energy).with(ResourceType::Energy, 150));
+        world.room_mut(test_room()).energy_available = 150;
+        let tower = world.add_structure(
+            StructureType::Tower,
+            position(27, 25, test_room()),
+            Some(StoreInfo::restricted(1000, ResourceType::Energy)),
+        );
+        world.add_creep(
+            "hauler",
+            position(26, 25, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world.creep_mut("hauler").store = StoreInfo::new(50).with(ResourceType::Energy, energy);
+        (world, spawn, tower)
+    }
+
+    #[test]
+    fn hauler_with_energy_feeds_spawn_before_tower() {
+        let (world, spawn, _) = world_with_hauler(50);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCHauler::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_target(), Some(spawn));
+
+        world.take_intents();
+        controller.run_tick(creep.as_ref(), &world);
+        assert!(world.take_intents().contains(&Intent::Transfer {
+            creep: "hauler".to_string(),
+            target: spawn,
+            resource: ResourceType::Energy,
+            amount: None,
+        }));
+    }
+
+    #[test]
+    fn hauler_feeds_tower_once_spawn_is_full() {
+        let (mut world, _, tower) = world_with_hauler(50);
+        world.room_mut(test_room()).structures[0].store =
+            Some(StoreInfo::restricted(300, ResourceType::Energy).with(ResourceType::Energy, 300));
+        world.room_mut(test_room()).energy_available = 300;
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCHauler::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_target(), Some(tower));
+    }
+
+    #[test]
+    fn empty_hauler_drains_fullest_container() {
+        let (mut world, _, _) = world_with_hauler(0);
+        world.add_structure(
+            StructureType::Container,
+            position(20, 20, test_room()),
+            Some(StoreInfo::new(2000).with(ResourceType::Energy, 100)),
+        );
+        let fullest = world.add_structure(
+            StructureType::Container,
+            position(30, 30, test_room()),
+            Some(StoreInfo::new(2000).with(ResourceType::Energy, 900)),
+        );
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCHauler::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Withdraw);
+        assert_eq!(controller.current_state().get_target(), Some(fullest));
+    }
+
+    #[test]
+    fn hauler_only_skips_containers_by_a_link_once_the_link_has_a_receiver() {
+        let (mut world, _, _) = world_with_hauler(0);
+        world.add_source(position(10, 10, test_room()));
+        let by_link = world.add_structure(
+            StructureType::Container,
+            position(11, 11, test_room()),
+            Some(StoreInfo::new(2000).with(ResourceType::Energy, 900)),
+        );
+        world.add_structure(
+            StructureType::Link,
+            position(12, 12, test_room()),
+            Some(StoreInfo::restricted(800, ResourceType::Energy)),
+        );
+        let other = world.add_structure(
+            StructureType::Container,
+            position(30, 30, test_room()),
+            Some(StoreInfo::new(2000).with(ResourceType::Energy, 100)),
+        );
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCHauler::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_target(), Some(by_link));
+
+        drop(creep);
+        world.add_structure(
+            StructureType::Storage,
+            position(35, 35, test_room()),
+            Some(StoreInfo::new(1_000_000)),
+        );
+        world.add_structure(
+            StructureType::Link,
+            position(36, 36, test_room()),
+            Some(StoreInfo::restricted(800, ResourceType::Energy)),
+        );
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCHauler::new();
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_target(), Some(other));
+    }
+
+    #[test]
+    fn haulers_do_not_double_book_a_delivery() {
+        let (mut world, spawn, tower) = world_with_hauler(50);
+        // The spawn only has room for one hauler's load
+        world.room_mut(test_room()).structures[0].store =
+            Some(StoreInfo::restricted(300, ResourceType::Energy).with(ResourceType::Energy, 250));
+        world.add_creep(
+            "second",
+            position(26, 26, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world.creep_mut("second").store = StoreInfo::new(50).with(ResourceType::Energy, 50);
+        let creeps = world.creeps();
+        let (mut first, mut second) = (SCHauler::new(), SCHauler::new());
 
-        base_body
+        first.run_tick(creeps[0].as_ref(), &world);
+        second.run_tick(creeps[1].as_ref(), &world);
+        assert_eq!(first.current_state().get_target(), Some(spawn));
+        assert_eq!(second.current_state().get_target(), Some(tower));
     }
 }
@@ -1,104 +1,310 @@
+use super::bootstrap::{bootstrap_body, Bootstrap};
 use super::{Specialisation, StateController};
+use crate::defense::{RoomThreat, ThreatLevel};
 use crate::info;
-use crate::screep_states::CreepMemory;
-use log::warn;
-use screeps::{
-    find, game, objects::Creep, Room, SharedCreepProperties, SpawnOptions, StructureObject,
-};
+use crate::movement;
+use crate::reservations;
+use crate::screep_states::{restore_state, CreepMemory, RoleData};
+use crate::spawn_queue::{SpawnPriority, SpawnQueue, SpawnRequest};
+use crate::tower_manager::{loaded_towers, RepairPolicy};
+use crate::world::{CreepView, RoomView, World};
+use screeps::constants::{CREEP_SPAWN_TIME, MAX_CREEP_SIZE};
+use screeps::{Position, RoomName, StructureType};
 use std::collections::HashMap;
 
+// Rooms stop asking for creeps for their economy once they have this many
+const MAX_ROOM_CREEPS: usize = 10;
+// Ticks before a room's spawn request is dropped, so it can reconsider what it needs
+const ROOM_REQUEST_TIMEOUT: u32 = 100;
+// Generalists a room will spawn while bootstrapping, before waiting for them to recover it
+const MAX_BOOTSTRAP_CREEPS: usize = 2;
+// Most defenders a room will have at once
+const MAX_DEFENDERS: usize = 2;
+// Longer than any walk from a spawn to a job in the same room
+const MAX_TRAVEL_TIME: u32 = 250;
+
 /// The SCManager is responsible for managing the state controllers of all creeps in the room.
 pub struct SCManager {
     pub state_controllers: HashMap<String, Box<dyn StateController>>,
+    pub spawn_queue: SpawnQueue,
+    bootstrap: Bootstrap,
+    // Handed to the controllers that repair, see Specialisation::controller
+    repair_policy: RepairPolicy,
 }
 
 impl SCManager {
-    pub fn new() -> Self {
+    pub fn new(repair_policy: RepairPolicy) -> Self {
         SCManager {
             state_controllers: HashMap::new(),
+            spawn_queue: SpawnQueue::new(),
+            bootstrap: Bootstrap::new(),
+            repair_policy,
         }
     }
 
-    pub fn run(&mut self) {
-        self.run_spawns();
+    pub fn run(&mut self, world: &dyn World, threats: &HashMap<RoomName, RoomThreat>) {
+        self.run_spawns(world, threats);
         // Run the tick for all state controllers
-        self.run_tick_for_all();
+        self.run_tick_for_all(world);
     }
 
-    pub fn run_tick_for_all(&mut self) {
-        for creep in game::creeps().values() {
+    pub fn run_tick_for_all(&mut self, world: &dyn World) {
+        // Forget about creeps that have died, along with anything they reserved
+        let alive: Vec<String> = world.creeps().iter().map(|creep| creep.name()).collect();
+        self.state_controllers.retain(|name, _| alive.contains(name));
+        reservations::with_reservations(|reservations| reservations.retain_creeps(&alive));
+
+        for creep in world.creeps() {
             let name = creep.name();
             let maybe_controller = self.state_controllers.get_mut(&name);
             if let Some(controller) = maybe_controller {
-                controller.run_tick(&creep);
+                controller.run_tick(creep.as_ref(), world);
             } else {
-                self.spawn_new_controller(&creep);
+                self.spawn_new_controller(creep.as_ref());
+            }
+        }
+    }
+
+    /// Queue up any creeps the rooms need, then let the spawn queue start spawning them
+    pub fn run_spawns(&mut self, world: &dyn World, threats: &HashMap<RoomName, RoomThreat>) {
+        self.queue_replacements(world);
+        for room in world.rooms() {
+            let has_spawn = world.spawns().iter().any(|s| s.room_name() == room.name());
+            if !has_spawn {
+                continue;
+            }
+            if let Some(threat) = threats.get(&room.name()) {
+                self.queue_defender(world, room.as_ref(), threat);
+            }
+            if self.bootstrap.update(world, room.as_ref()) {
+                self.queue_bootstrap_creep(world, room.as_ref());
+                continue;
             }
+            if world.creeps_in_room(room.name()).len() >= MAX_ROOM_CREEPS {
+                continue;
+            }
+            // The room only asks for one creep at a time, as what it needs next depends on
+            // what has already been spawned
+            if !self.spawn_queue.queued(room.name()).is_empty() {
+                continue;
+            }
+
+            // Determine specialisation, and get body parts and memory
+            let specialisation = self.get_next_specialty(world, room.as_ref());
+            info!("Next specialisation for {}: {:?}", room.name(), specialisation);
+            let controller = specialisation.clone().controller(&self.repair_policy);
+            let priority = match specialisation {
+                Specialisation::Generalist => SpawnPriority::High,
+                Specialisation::Builder | Specialisation::Upgrader => SpawnPriority::Low,
+                _ => SpawnPriority::Normal,
+            };
+            self.spawn_queue.push(
+                room.name(),
+                SpawnRequest {
+                    body: controller.get_best_worker_body(room.as_ref()),
+                    memory: CreepMemory::new(specialisation.clone()),
+                    specialisation,
+                    priority,
+                    deadline: world.time() + ROOM_REQUEST_TIMEOUT,
+                },
+            );
+        }
+
+        self.spawn_queue.run(world);
+    }
+
+    /// While a room is bootstrapping, spawn small generalists from whatever energy it has
+    fn queue_bootstrap_creep(&mut self, world: &dyn World, room: &dyn RoomView) {
+        let queued = self.spawn_queue.queued(room.name());
+        if queued.iter().any(|request| request.priority == SpawnPriority::Critical) {
+            return;
         }
+        let generalist_count = world
+            .creeps_in_room(room.name())
+            .iter()
+            .filter(|creep| creep.memory().specialisation() == &Specialisation::Generalist)
+            .count();
+        if generalist_count >= MAX_BOOTSTRAP_CREEPS {
+            return;
+        }
+        let body = bootstrap_body(room.energy_available());
+        if body.is_empty() {
+            return;
+        }
+        self.spawn_queue.push(
+            room.name(),
+            SpawnRequest {
+                specialisation: Specialisation::Generalist,
+                priority: SpawnPriority::Critical,
+                body,
+                memory: CreepMemory::new(Specialisation::Generalist),
+                deadline: world.time() + ROOM_REQUEST_TIMEOUT,
+            },
+        );
+    }
+
+    /// Queue a defender while the room has hostiles the towers can't deal with by themselves.
+    /// Melee and ranged defenders are alternated
+    fn queue_defender(&mut self, world: &dyn World, room: &dyn RoomView, threat: &RoomThreat) {
+        if threat.level < ThreatLevel::Medium || !threat.exceeds_towers(&loaded_towers(room)) {
+            return;
+        }
+        let is_defender = |specialisation: &Specialisation| {
+            matches!(
+                specialisation,
+                Specialisation::Defender | Specialisation::RangedDefender
+            )
+        };
+        let queued = self.spawn_queue.queued(room.name());
+        if queued.iter().any(|request| is_defender(&request.specialisation)) {
+            return;
+        }
+        let defenders: Vec<Specialisation> = world
+            .creeps_in_room(room.name())
+            .iter()
+            .map(|creep| creep.memory().specialisation().clone())
+            .filter(is_defender)
+            .collect();
+        if defenders.len() >= MAX_DEFENDERS {
+            return;
+        }
+
+        let melee = defenders
+            .iter()
+            .filter(|specialisation| **specialisation == Specialisation::Defender)
+            .count();
+        let specialisation = if melee <= defenders.len() - melee {
+            Specialisation::Defender
+        } else {
+            Specialisation::RangedDefender
+        };
+        let controller = specialisation.clone().controller(&self.repair_policy);
+        let body = controller.get_best_worker_body(room);
+        if body.is_empty() {
+            return;
+        }
+        self.spawn_queue.push(
+            room.name(),
+            SpawnRequest {
+                memory: CreepMemory::new(specialisation.clone()),
+                specialisation,
+                priority: SpawnPriority::High,
+                body,
+                deadline: world.time() + ROOM_REQUEST_TIMEOUT,
+            },
+        );
     }
 
-    /// Check if we need to spawn any more creeps, and trigger spawn if we can
-    pub fn run_spawns(&mut self) {
-        let mut additional = 0;
-        let creep_count = game::creeps().values().count();
-        // info!("creep count: {}", creep_count);
-        if creep_count < 10 {
-            for spawn in game::spawns().values() {
-                info!("\n\n\n");
-                info!("====> running spawn {}", spawn.name());
-                info!(
-                    "Energy available: {}",
-                    spawn.room().unwrap().energy_available()
-                );
-
-                // Determine specialisation, and get body parts and memory
-                let specialisation = self.get_next_specialty(&spawn.room().unwrap());
-                info!("Next specialisation: {:?}", specialisation);
-                let memory = CreepMemory::new(specialisation.clone());
-                let controller: Box<dyn StateController> = specialisation.clone().into();
-                let body = controller.get_best_worker_body(&spawn.room().unwrap());
-
-                // If we can spawn, spawn a new creep
-                if spawn.room().unwrap().energy_available() >= body.iter().map(|p| p.cost()).sum() {
-                    // create a unique name, spawn.
-                    let name_base = game::time();
-                    let name = format!("{:?}-{}-{}", specialisation, name_base, additional);
-                    let options = SpawnOptions::new().memory(memory.into());
-                    match spawn.spawn_creep_with_options(&body, &name, &options) {
-                        Ok(()) => {
-                            info!("====> spawn successful\n\n\n");
-                            additional += 1
-                        },
-                        Err(e) => warn!("couldn't spawn: {:?}", e),
-                    }
-                }
+    /// Queue successors for creeps that are tied to one place (e.g. a miner on its source), early
+    /// enough that the successor arrives just as the old creep dies
+    fn queue_replacements(&mut self, world: &dyn World) {
+        let creeps = world.creeps();
 
+        // Successors born since last tick take over the job of whoever they were queued for
+        for (name, request) in self.spawn_queue.born() {
+            let Some(role_data) = request.memory.role_data() else {
+                continue;
+            };
+            let predecessor = creeps.iter().find(|creep| {
+                creep.name() != *name && creep.memory().role_data() == Some(role_data)
+            });
+            if let Some(predecessor) = predecessor {
+                info!("{} has been born to take over from {}", name, predecessor.name());
             }
         }
+
+        for creep in creeps.iter() {
+            let name = creep.name();
+            // Nobody this far from dying needs a successor yet, however big or far away
+            let Some(ticks_to_live) = creep
+                .ticks_to_live()
+                .filter(|ticks| *ticks <= MAX_CREEP_SIZE * CREEP_SPAWN_TIME + MAX_TRAVEL_TIME)
+            else {
+                continue;
+            };
+            let memory = creep.memory();
+            let Some(role_data) = memory.role_data() else {
+                continue;
+            };
+            let Some(target) = assigned_position(world, &memory) else {
+                continue;
+            };
+            // Successors are found from the creeps and the queue rather than remembered, so a
+            // global reset doesn't queue them twice. Only younger creeps count, so two creeps
+            // sharing a job (e.g. miners on one source) aren't each other's successor
+            let has_successor = creeps.iter().any(|other| {
+                other.name() != name
+                    && other.memory().role_data() == Some(role_data)
+                    && other.ticks_to_live().is_none_or(|other| other > ticks_to_live)
+            }) || self
+                .spawn_queue
+                .queued(target.room_name())
+                .iter()
+                .any(|request| request.memory.role_data() == Some(role_data));
+            if has_successor {
+                continue;
+            }
+            let Some(room) = world.room(target.room_name()) else {
+                continue;
+            };
+            let Some(travel_time) = world
+                .spawns()
+                .iter()
+                .filter(|spawn| spawn.room_name() == room.name())
+                .filter_map(|spawn| movement::path_cost(world, spawn.pos(), target, 1))
+                .min()
+            else {
+                continue;
+            };
+
+            let specialisation = memory.specialisation().clone();
+            let controller = specialisation.clone().controller(&self.repair_policy);
+            let body = controller.get_best_worker_body(room.as_ref());
+            if ticks_to_live > body.spawn_time + travel_time {
+                continue;
+            }
+
+            info!("Queueing replacement for {} with {} ticks to live", name, ticks_to_live);
+            // The successor takes over the same job
+            let mut successor_memory = CreepMemory::new(specialisation.clone());
+            successor_memory.set_role_data(role_data.clone());
+            self.spawn_queue.push(
+                room.name(),
+                SpawnRequest {
+                    specialisation,
+                    priority: SpawnPriority::High,
+                    body,
+                    memory: successor_memory,
+                    deadline: world.time() + ticks_to_live,
+                },
+            );
+        }
     }
 
     /// Spawn a new state controller for the given creep
     /// This is where we control how many of each controller we need
-    fn spawn_new_controller(&mut self, creep: &Creep) {
+    /// If the creep was already in a state (i.e. after a global reset), that state is restored
+    fn spawn_new_controller(&mut self, creep: &dyn CreepView) {
         info!("Spawning new state controller for creep {}", creep.name());
         let memory = creep.memory();
-        let memory: CreepMemory = memory.into();
+        let mut controller = memory.specialisation().clone().controller(&self.repair_policy);
+        if let Some(state) = restore_state(&memory, &self.repair_policy) {
+            state.log_state(creep);
+            state.reserve(creep);
+            controller.set_current_state(state);
+        }
         // Add the new controller to the map
-        // Can use into here due to impl on Specialisation
-        self.state_controllers.insert(
-            creep.name().to_string(),
-            memory.specialisation().clone().into(),
-        );
+        self.state_controllers.insert(creep.name().to_string(), controller);
     }
 
     /// Get the next specialty for a creep based on the current room state
-    fn get_next_specialty(&mut self, room: &Room) -> Specialisation {
+    fn get_next_specialty(&mut self, world: &dyn World, room: &dyn RoomView) -> Specialisation {
         // check if there is a storage in the room, the hauler and miner combo only work
         // if there is a storage and some containers, but the storage comes last
         // Actually maybe not, the hauler can just fallback to upgrading the controller
-        // let storage_exists = room.find(find::STRUCTURES, None)
+        // let storage_exists = room.structures()
         //     .iter()
-        //     .any(|s| matches!(s, StructureObject::StructureStorage(_)));
+        //     .any(|s| s.structure_type == StructureType::Storage);
         // if (!storage_exists) { return Specialisation::Generalist; }
 
         // Get all existing specializations in room
@@ -109,9 +315,9 @@ impl SCManager {
         let mut builder_count = 0;
         let mut upgrader_count = 0;
 
-        room.find(find::CREEPS, None).iter().for_each(|creep| {
+        world.creeps_in_room(room.name()).iter().for_each(|creep| {
             total += 1;
-            match CreepMemory::from(creep.memory()).specialisation() {
+            match creep.memory().specialisation() {
                 Specialisation::Generalist => generalist_count += 1,
                 Specialisation::Miner => miner_count += 1,
                 Specialisation::Hauler => hauler_count += 1,
@@ -125,11 +331,11 @@ impl SCManager {
             return Specialisation::Generalist;
         }
 
-        let energy_count = room.find(find::SOURCES_ACTIVE, None).len();
+        let energy_count = room.sources().len();
         let container_count = room
-            .find(find::STRUCTURES, None)
+            .structures()
             .iter()
-            .filter(|s| matches!(s, StructureObject::StructureContainer(_)))
+            .filter(|s| s.structure_type == StructureType::Container)
             .count();
         // set to max energy or container count
         let max_miner_count = energy_count.max(container_count);
@@ -157,3 +363,98 @@ impl SCManager {
         Specialisation::Generalist
     }
 }
+
+/// Where a creep needs to be to do its job, if it is tied to one place
+fn assigned_position(world: &dyn World, memory: &CreepMemory) -> Option<Position> {
+    match memory.role_data()? {
+        RoleData::Miner { source } => world.source(*source).map(|source| source.pos),
+        RoleData::MinerIndex { .. } => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+    use screeps::Part;
+
+    /// A room with a miner working the source 20 tiles from the spawn
+    fn world_with_miner(ticks_to_live: u32) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        let source = world.add_source(position(45, 25, test_room()));
+        let mut memory = CreepMemory::new(Specialisation::Miner);
+        memory.set_role_data(RoleData::Miner { source });
+        world.add_creep(
+            "miner",
+            position(44, 25, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            memory,
+        );
+        world.creep_mut("miner").ticks_to_live = ticks_to_live;
+        world
+    }
+
+    fn queued_miners(manager: &SCManager) -> usize {
+        manager
+            .spawn_queue
+            .queued(test_room())
+            .iter()
+            .filter(|request| request.specialisation == Specialisation::Miner)
+            .count()
+    }
+
+    #[test]
+    fn dying_miner_is_replaced_in_time_for_the_walk() {
+        // A 300 energy miner takes 9 ticks to spawn, and walks 19 plain tiles at 2 ticks a tile
+        let mut manager = SCManager::new(RepairPolicy::default());
+        manager.queue_replacements(&world_with_miner(48));
+        assert_eq!(queued_miners(&manager), 0);
+        manager.queue_replacements(&world_with_miner(47));
+        assert_eq!(queued_miners(&manager), 1);
+        let request = manager.spawn_queue.queued(test_room())[0].clone();
+        let world = world_with_miner(47);
+        assert_eq!(
+            request.memory.role_data(),
+            world.creeps()[0].memory().role_data()
+        );
+    }
+
+    #[test]
+    fn replacements_are_not_queued_twice_after_a_reset() {
+        let mut world = world_with_miner(40);
+        let mut manager = SCManager::new(RepairPolicy::default());
+        manager.queue_replacements(&world);
+        manager.queue_replacements(&world);
+        assert_eq!(queued_miners(&manager), 1);
+
+        // After a reset the queue is gone, but the successor may already be alive
+        let memory = world.creeps()[0].memory();
+        world.add_creep(
+            "successor",
+            position(26, 25, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            memory,
+        );
+        let mut manager = SCManager::new(RepairPolicy::default());
+        manager.queue_replacements(&world);
+        assert_eq!(queued_miners(&manager), 0);
+    }
+
+    #[test]
+    fn creeps_sharing_a_job_are_not_each_others_successor() {
+        let mut world = world_with_miner(40);
+        let memory = world.creeps()[0].memory();
+        world.add_creep(
+            "partner",
+            position(45, 26, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            memory,
+        );
+        world.creep_mut("partner").ticks_to_live = 30;
+        let mut manager = SCManager::new(RepairPolicy::default());
+        manager.queue_replacements(&world);
+        assert_eq!(queued_miners(&manager), 1);
+    }
+}
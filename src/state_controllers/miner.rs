@@ -1,106 +1,124 @@
 use super::{Specialisation, StateController};
 use crate::screep_states::*;
 use crate::utils;
-use crate::utils::find_object_at_index;
+use crate::utils::{Body, BodyPlan};
+use crate::world::{CreepView, RoomView, SourceInfo, StructureInfo, World};
 use log::warn;
-use screeps::{constants::ResourceType, enums::StructureObject, find, objects::Creep, prelude::*, Part, Room, StructureContainer};
+use screeps::{constants::ResourceType, prelude::*, ObjectId, Part, Source, StructureType};
 
-/// Miner State Controller for mining energy and dumping it into nearby storage
+/// Miner State Controller for mining energy and dumping it into nearby storage.
+/// Miners park on the container next to their source once it is built
 pub struct SCMiner {
     pub current_state: Box<dyn ScreepState>,
-    // index of resource to mine in room
-    pub source_index: Option<u8>,
+    // source this miner is assigned to
+    pub source: Option<ObjectId<Source>>,
 }
 
 impl SCMiner {
     pub fn new() -> Self {
         SCMiner {
             current_state: Box::new(IdleState {}),
-            source_index: None,
+            source: None,
         }
     }
 
-    // Get the source index either from the cached state, the memory or finding a new one
-    fn get_source_index(&mut self, creep: &Creep) -> Option<u8> {
-        // If source_index is already set, return it
-        if let Some(index) = self.source_index {
-            return Some(index);
+    // Get the source either from the cached state, the memory or finding a new one
+    fn get_source(&mut self, creep: &dyn CreepView, world: &dyn World) -> Option<ObjectId<Source>> {
+        // If source is already set, return it
+        if let Some(source) = self.source {
+            return Some(source);
         }
 
-        // Try to get the index from memory
-        if let Some(index) = self.get_index_from_memory(creep) {
-            return Some(index);
+        // Try to get the source from memory
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
+        if let Some(source) = self.get_source_from_memory(room.as_ref(), creep) {
+            return Some(source);
         }
 
-        // If not found in memory, find the source index
-        let room = creep.room().expect("couldn't resolve creep room");
-        self.find_source_index(&room, creep)
+        // If not found in memory, find a source
+        self.find_source(world, room.as_ref(), creep)
     }
 
-    // Get index if stored in memory
-    fn get_index_from_memory(&mut self, creep: &Creep) -> Option<u8> {
-        // Get the source index from memory if it exists
-        let memory: CreepMemory = creep.memory().into();
-        if let Some(data) = memory.additional_data() {
-            if let Ok(index) = data.parse::<u8>() {
-                self.source_index = Some(index);
-            } else {
-                warn!("Invalid source index in memory for creep {}", creep.name());
-                self.source_index = None;
+    // Get source if stored in memory, swapping a source index from older versions for its id
+    fn get_source_from_memory(
+        &mut self,
+        room: &dyn RoomView,
+        creep: &dyn CreepView,
+    ) -> Option<ObjectId<Source>> {
+        self.source = match creep.memory().role_data() {
+            Some(RoleData::Miner { source }) => Some(*source),
+            Some(RoleData::MinerIndex { source_index }) => {
+                let source = room.sources().get(*source_index as usize)?.id;
+                let mut memory = creep.memory();
+                memory.set_role_data(RoleData::Miner { source });
+                creep.set_memory(memory);
+                Some(source)
             }
-        } else {
-            self.source_index = None;
-        }
-        self.source_index
+            None => None,
+        };
+        self.source
     }
 
-    // Find the resource index by getting the source with the least number of miners
-    fn find_source_index(&mut self, room: &Room, creep: &Creep) -> Option<u8> {
-        // get room sources
-        let sources: Vec<u8> = room
-            .find(find::SOURCES_ACTIVE, None)
-            .into_iter()
-            .enumerate()
-            .map(|(i, _)| i as u8)
-            .collect();
-
+    // Find the source with the least number of miners.
+    // All sources are considered, as an empty source will regenerate
+    fn find_source(&mut self, world: &dyn World, room: &dyn RoomView, creep: &dyn CreepView) -> Option<ObjectId<Source>> {
         // Count miners on each source
-        let mut source_counts: Vec<(u8, usize)> = sources
+        let mut source_counts: Vec<(ObjectId<Source>, usize)> = room
+            .sources()
             .iter()
-            .map(|&source_index| (source_index, 0))
+            .map(|source| (source.id, 0))
             .collect();
 
         // Count existing miners on each source
-        room.find(find::CREEPS, None).iter().for_each(|creep| {
-            let memory = CreepMemory::from(creep.memory());
-            if memory.specialisation() == &Specialisation::Miner {
-                if let Some(data) = memory.additional_data() {
-                    if let Ok(source_index) = data.parse::<u8>() {
-                        if let Some((_, count)) = source_counts
-                            .iter_mut()
-                            .find(|(idx, _)| *idx == source_index)
-                        {
-                            *count += 1;
-                        }
-                    }
+        world.creeps_in_room(room.name()).iter().for_each(|creep| {
+            let memory = creep.memory();
+            if let Some(RoleData::Miner { source }) = memory.role_data() {
+                if let Some((_, count)) = source_counts
+                    .iter_mut()
+                    .find(|(id, _)| id == source)
+                {
+                    *count += 1;
                 }
             }
         });
 
         // Find the source with the least number of miners
-        let new_index = source_counts
+        let new_source = source_counts
             .iter()
             .min_by_key(|(_, count)| *count)
-            .map(|(source_index, _)| *source_index);
-        self.source_index = new_index;
-
-        // Set memory of the miner to the new source index
-        if let Some(new_index) = new_index {
-            let mut memory: CreepMemory = creep.memory().into();
-            memory.set_additional_data(new_index.to_string());
-            creep.set_memory(&memory.into());
+            .map(|(source, _)| *source);
+        self.source = new_source;
+
+        // Set memory of the miner to the new source
+        if let Some(source) = new_source {
+            let mut memory = creep.memory();
+            memory.set_role_data(RoleData::Miner { source });
+            creep.set_memory(memory);
         }
-        new_index
+        new_source
+    }
+
+    // Find the container next to the source to park on, unless another miner is parked there
+    fn find_parking(
+        &self,
+        world: &dyn World,
+        room: &dyn RoomView,
+        creep: &dyn CreepView,
+        source: &SourceInfo,
+    ) -> Option<StructureInfo> {
+        let container = room.structures().into_iter().find(|structure| {
+            structure.structure_type == StructureType::Container
+                && structure.pos.is_near_to(source.pos)
+        })?;
+        let taken = world.creeps_in_room(room.name()).iter().any(|other| {
+            let memory = other.memory();
+            other.name() != creep.name()
+                && *memory.current_state() == StateName::DropMine
+                && memory.state_target() == Some(container.id)
+        });
+        (!taken).then_some(container)
     }
 }
 
@@ -109,42 +127,45 @@ impl StateController for SCMiner {
         Specialisation::Miner.into()
     }
 
-    fn current_state(&self) -> &Box<dyn ScreepState> {
-        &self.current_state
+    fn current_state(&self) -> &dyn ScreepState {
+        self.current_state.as_ref()
     }
 
     fn set_current_state(&mut self, state: Box<dyn ScreepState>) {
         self.current_state = state;
     }
 
-    fn choose_next_state(&mut self, creep: &Creep) -> Box<dyn ScreepState> {
-        let room = creep.room().expect("couldn't resolve creep room");
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState> {
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
         let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
 
-        // Use the source_index if it exists, otherwise find and set it
-        let Some(source_index) = self.get_source_index(creep) else {
+        // Use the source if it exists, otherwise find and set it
+        let Some(source_id) = self.get_source(creep, world) else {
             return Box::new(IdleState {});
         };
 
+        if let Some(source) = world.source(source_id) {
+            // Once the source has a container, sit on it and let the energy drop straight in
+            if let Some(container) = self.find_parking(world, room.as_ref(), creep, &source) {
+                return Box::new(DropMineState::new(source.id, container.id));
+            }
 
-        if let Some(source_id) = find_object_at_index(&room, source_index, find::SOURCES_ACTIVE) {
             if energy == 0 {
-                // Go mine boy!
-                return Box::new(HarvestState::new(source_id));
+                // Go mine boy! If the source is empty, wait by it until it regenerates
+                return Box::new(HarvestState::new_waiting(source.id));
             } else {
                 // find the closest container to the source
-                let mut closest_container: Option<StructureContainer> = None;
+                let mut closest_container: Option<StructureInfo> = None;
                 let mut closest_distance = u32::MAX;
                 
-                // Resolve the source to get its position
-                if let Some(source) = source_id.resolve() {
-                    for structure in room.find(find::STRUCTURES, None).iter() {
-                        if let StructureObject::StructureContainer(container) = structure {
-                            let distance = source.pos().get_range_to(container.pos());
-                            if distance < closest_distance {
-                                closest_distance = distance;
-                                closest_container = Some(container.clone());
-                            }
+                for structure in room.structures().iter() {
+                    if structure.structure_type == StructureType::Container {
+                        let distance = source.pos().get_range_to(structure.pos());
+                        if distance < closest_distance {
+                            closest_distance = distance;
+                            closest_container = Some(structure.clone());
                         }
                     }
                 }
@@ -152,16 +173,15 @@ impl StateController for SCMiner {
                 if let Some(container) = closest_container {
                     if container.store().get_free_capacity(Some(ResourceType::Energy)) > 0
                     {
-                        return Box::new(
-                            FeedStructureState::<StructureContainer>::new(
-                                container.id(),
-                            ),
-                        );
+                        return Box::new(FeedStructureState::new(
+                            container.id,
+                            Some(ResourceType::Energy),
+                        ));
                     }
                 }
             }
         } else {
-            warn!("Creep source not found or empty {}", creep.name());
+            warn!("Creep source not found {}", creep.name());
             return Box::new(IdleState {});
         }
 
@@ -170,36 +190,121 @@ impl StateController for SCMiner {
     }
 
     /// Get the best worker body for this state controller
-    fn get_best_worker_body(&self, room: &Room) -> Vec<Part> {
-        let mut base_body: Vec<Part> = vec![];
-        let blueprint = vec![
-            Part::Move,
-            Part::Carry,
-            Part::Work,
-            Part::Work,
-            Part::Work,
-            Part::Work,
-            Part::Work,
-            Part::Work,
-        ];
-        let energy_available: u32 = utils::get_total_upgrade_energy(room);
-        let mut cost = base_body.iter().map(|p| p.cost()).sum::<u32>();
-        // keep adding parts from target until we reach the energy limit
-        for part in blueprint.iter() {
-            if cost + part.cost() <= energy_available {
-                base_body.push(*part);
-                cost += part.cost();
-            } else {
-                break;
-            }
-        }
+    fn get_best_worker_body(&self, room: &dyn RoomView) -> Body {
+        // Miners barely move once they reach their source, and 5 WORK parts (10 energy a tick)
+        // is enough to empty a source before it regenerates. The CARRY is kept even for drop
+        // mining, to hold energy for repairing the container
+        BodyPlan::new()
+            .prefix(&[Part::Carry])
+            .ratio(Part::Work, 1)
+            .move_ratio(2, 2)
+            .max_parts(9)
+            .build(utils::get_total_upgrade_energy(room))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld, StoreInfo};
 
-        // Fill the rest with move (They are only 50 energy each)
-        while cost + Part::Move.cost() <= energy_available {
-            base_body.push(Part::Move);
-            cost += Part::Move.cost();
+    fn add_miner(world: &mut MockWorld, name: &str, source: Option<ObjectId<Source>>) {
+        let mut memory = CreepMemory::new(Specialisation::Miner);
+        if let Some(source) = source {
+            memory.set_role_data(RoleData::Miner { source });
         }
+        world.add_creep(
+            name,
+            position(25, 25, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            memory,
+        );
+    }
+
+    #[test]
+    fn miner_takes_the_source_with_fewest_miners() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let taken = world.add_source(position(10, 10, test_room()));
+        let free = world.add_source(position(40, 40, test_room()));
+        add_miner(&mut world, "old", Some(taken));
+        add_miner(&mut world, "new", None);
+        let creep = world.creeps()[1].clone();
+        let mut controller = SCMiner::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Harvest);
+        assert_eq!(controller.current_state().get_target(), Some(free.into()));
+        assert_eq!(
+            creep.memory().role_data(),
+            Some(&RoleData::Miner { source: free })
+        );
+    }
+
+    #[test]
+    fn miner_parks_on_the_container_next_to_its_source() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let source = world.add_source(position(10, 10, test_room()));
+        // Further from the source, so not a place to park
+        world.add_structure(
+            StructureType::Container,
+            position(20, 20, test_room()),
+            Some(StoreInfo::new(2000)),
+        );
+        let container = world.add_structure(
+            StructureType::Container,
+            position(11, 11, test_room()),
+            Some(StoreInfo::new(2000)),
+        );
+        add_miner(&mut world, "miner", Some(source));
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCMiner::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::DropMine);
+        assert_eq!(controller.current_state().get_target(), Some(container));
+    }
+
+    #[test]
+    fn miners_keep_a_carry_to_repair_their_container() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_spawn("Spawn1", position(25, 25, test_room()));
+        world.add_source(position(10, 10, test_room()));
+        world.add_structure(
+            StructureType::Container,
+            position(11, 11, test_room()),
+            Some(StoreInfo::new(2000)),
+        );
+        let room = world.room(test_room()).unwrap();
+
+        let body = SCMiner::new().get_best_worker_body(room.as_ref());
+        assert_eq!(body.parts.iter().filter(|part| **part == Part::Carry).count(), 1);
+    }
+
+    #[test]
+    fn miner_swaps_an_old_source_index_for_the_source() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_source(position(10, 10, test_room()));
+        let second = world.add_source(position(40, 40, test_room()));
+        let mut memory = CreepMemory::new(Specialisation::Miner);
+        memory.set_role_data(RoleData::MinerIndex { source_index: 1 });
+        world.add_creep(
+            "miner",
+            position(25, 25, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            memory,
+        );
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCMiner::new();
 
-        base_body
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_target(), Some(second.into()));
+        assert_eq!(
+            creep.memory().role_data(),
+            Some(&RoleData::Miner { source: second })
+        );
     }
 }
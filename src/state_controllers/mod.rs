@@ -1,3 +1,5 @@
+mod bootstrap;
+mod defender;
 mod generalist;
 mod hauler;
 mod manager;
@@ -9,9 +11,12 @@ use log::warn;
 use std::cmp::PartialEq;
 // Contains core State Controller logic for managing Screep states
 use crate::screep_states::*;
-use screeps::{find, objects::Creep, Part, Room};
+use crate::tower_manager::RepairPolicy;
+use crate::world::{CreepView, RoomView, World};
+use crate::utils::Body;
 use serde::{Deserialize, Serialize};
 
+use crate::state_controllers::defender::SCDefender;
 use crate::state_controllers::hauler::SCHauler;
 use crate::state_controllers::miner::SCMiner;
 pub use generalist::SCGeneralist;
@@ -25,6 +30,8 @@ pub enum Specialisation {
     Hauler,
     Builder,
     Upgrader,
+    Defender,
+    RangedDefender,
 }
 
 impl From<Specialisation> for &'static str {
@@ -36,24 +43,30 @@ impl From<Specialisation> for &'static str {
             Specialisation::Hauler => "Hauler",
             Specialisation::Builder => "Builder",
             Specialisation::Upgrader => "Upgrader",
+            Specialisation::Defender => "Defender",
+            Specialisation::RangedDefender => "RangedDefender",
         }
     }
 }
 
-impl From<Specialisation> for Box<dyn StateController> {
-    fn from(specialisation: Specialisation) -> Self {
-        match specialisation {
-            Specialisation::Generalist => Box::new(SCGeneralist::new()),
+impl Specialisation {
+    /// A new state controller for the specialisation. Controllers that repair go by the given
+    /// policy, which is the one the towers are run with so they agree on what needs repairing
+    pub fn controller(self, repair_policy: &RepairPolicy) -> Box<dyn StateController> {
+        match self {
+            Specialisation::Generalist => Box::new(SCGeneralist::new(repair_policy.clone())),
             Specialisation::Miner => Box::new(SCMiner::new()),
             Specialisation::Hauler => Box::new(SCHauler::new()),
-            Specialisation::Builder => Box::new(builder::SCBuilder::new()),
+            Specialisation::Builder => Box::new(builder::SCBuilder::new(repair_policy.clone())),
             Specialisation::Upgrader => Box::new(upgrader::SCUpgrader::new()),
+            Specialisation::Defender => Box::new(SCDefender::new()),
+            Specialisation::RangedDefender => Box::new(SCDefender::new_ranged()),
             _ => {
                 warn!(
                     "!!!! Unknown or unsupported specialisation: {:?} defaulting to Generalist",
-                    specialisation
+                    self
                 );
-                Box::new(SCGeneralist::new())
+                Box::new(SCGeneralist::new(repair_policy.clone()))
             } // Default to Generalist for unknown or unsupported specialisations
         }
     }
@@ -64,15 +77,19 @@ pub trait StateController {
     fn get_name(&self) -> &'static str;
 
     /// Run a tick for the given creep and update its state
-    fn run_tick(&mut self, creep: &Creep) {
-        match self.current_state().tick(creep) {
+    fn run_tick(&mut self, creep: &dyn CreepView, world: &dyn World) {
+        let result = if self.should_interrupt(creep, world) {
+            TickResult::Exit
+        } else {
+            self.current_state().tick(creep, world)
+        };
+        match result {
             TickResult::Continue => {
                 // Continue running the current state
-                return;
             }
             TickResult::ChangeState(new_state) => {
                 // Exit the current state
-                self.current_state().on_exit();
+                self.current_state().on_exit(creep);
                 new_state.on_start(creep);
                 new_state.log_state(creep);
                 // set creep state to the new state
@@ -80,8 +97,8 @@ pub trait StateController {
             }
             TickResult::Exit => {
                 // Exit the current state and remove it from the map
-                self.current_state().on_exit();
-                let new_state: Box<dyn ScreepState> = self.choose_next_state(creep);
+                self.current_state().on_exit(creep);
+                let new_state: Box<dyn ScreepState> = self.choose_next_state(creep, world);
                 new_state.on_start(creep);
                 new_state.log_state(creep);
                 self.set_current_state(new_state);
@@ -89,23 +106,29 @@ pub trait StateController {
         }
     }
 
+    /// Whether to give up the current state before it is finished and choose again, e.g.
+    /// because something more important has come up
+    fn should_interrupt(&self, _creep: &dyn CreepView, _world: &dyn World) -> bool {
+        false
+    }
+
     // What is the current state of the controller
-    fn current_state(&self) -> &Box<dyn ScreepState>;
+    fn current_state(&self) -> &dyn ScreepState;
 
     /// Set the current state of the controller
     fn set_current_state(&mut self, state: Box<dyn ScreepState>);
 
     /// Choose the next state based on the current needs of the room
-    fn choose_next_state(&mut self, creep: &Creep) -> Box<dyn ScreepState>;
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState>;
 
     /// Get the best worker body for the current state controller
-    fn get_best_worker_body(&self, _room: &Room) -> Vec<Part>;
+    fn get_best_worker_body(&self, _room: &dyn RoomView) -> Body;
 
     // Count instances of a certain state in the room
-    fn count_state_instances(&self, room: &Room, state: &StateName) -> u8 {
+    fn count_state_instances(&self, world: &dyn World, room: &dyn RoomView, state: &StateName) -> u8 {
         let mut count = 0;
-        room.find(find::CREEPS, None).iter().for_each(|creep| {
-            if CreepMemory::from(creep.memory()).current_state() == state {
+        world.creeps_in_room(room.name()).iter().for_each(|creep| {
+            if creep.memory().current_state() == state {
                 count += 1;
             }
         });
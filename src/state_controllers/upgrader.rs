@@ -1,8 +1,11 @@
+use crate::link_manager::{links_with_role, LinkRole};
 use crate::screep_states::*;
 use crate::utils;
+use crate::utils::{Body, BodyPlan};
 use crate::utils::prelude::*;
+use crate::world::{CreepView, RoomView, World};
 use log::warn;
-use screeps::{constants::ResourceType, enums::StructureObject, find, objects::Creep, prelude::*, ObjectId, Part, Room};
+use screeps::{constants::ResourceType, prelude::*, Part, RawObjectId, StructureType};
 
 use super::{Specialisation, StateController};
 
@@ -24,44 +27,43 @@ impl StateController for SCUpgrader {
         Specialisation::Generalist.into()
     }
 
-    fn current_state(&self) -> &Box<dyn ScreepState> {
-        &self.current_state
+    fn current_state(&self) -> &dyn ScreepState {
+        self.current_state.as_ref()
     }
 
     fn set_current_state(&mut self, state: Box<dyn ScreepState>) {
         self.current_state = state;
     }
 
-    fn choose_next_state(&mut self, creep: &Creep) -> Box<dyn ScreepState> {
-        let room = creep.room().expect("couldn't resolve creep room");
+    fn choose_next_state(&mut self, creep: &dyn CreepView, world: &dyn World) -> Box<dyn ScreepState> {
+        let room = world
+            .room(creep.pos().room_name())
+            .expect("couldn't resolve creep room");
         let energy = creep.store().get_used_capacity(Some(ResourceType::Energy));
         if energy == 0 {
-            // Find the closest container with energy to drain
-            let mut closest_container: Option<ObjectId<screeps::objects::StructureContainer>> = None;
+            // Find the closest container (or controller link) with energy to drain
+            let mut closest_container: Option<RawObjectId> = None;
             let mut min_distance = u32::MAX;
-            for structure in room.find(find::STRUCTURES, None).iter() {
-                if let StructureObject::StructureContainer(container) = structure {
-                    if container
-                        .store()
-                        .get_used_capacity(Some(ResourceType::Energy))
-                        > 0
-                    {
-                        let distance = creep.pos().get_range_to(container.pos());
-                        if distance < min_distance {
-                            min_distance = distance;
-                            closest_container = Some(container.id());
-                        }
+            let mut stores = room.structures();
+            stores.retain(|structure| structure.structure_type == StructureType::Container);
+            stores.extend(links_with_role(room.as_ref(), LinkRole::Controller));
+            for structure in stores.iter() {
+                if unreserved_energy(structure) > 0 {
+                    let distance = creep.pos().get_range_to(structure.pos());
+                    if distance < min_distance {
+                        min_distance = distance;
+                        closest_container = Some(structure.id);
                     }
                 }
             }
 
             // If we found a container with energy, harvest from it
             if let Some(container_id) = closest_container {
-                return Box::new(WithdrawState::new(container_id));
+                return Box::new(WithdrawState::new(container_id, Some(ResourceType::Energy)));
             } else {
                 // Otherwise, attempt to find some sources to harvest
-                if let Some(source) = find_nearest_object(&creep.pos(), &room, find::SOURCES_ACTIVE) {
-                    return Box::new(HarvestState::new(source));
+                if let Some(source) = find_nearest_object(&creep.pos(), &find_active_sources(world, room.as_ref(), creep)) {
+                    return Box::new(HarvestState::new(source.id));
                 } else {
                     warn!("No sources found for creep {}", creep.name());
                     return Box::new(IdleState {});
@@ -70,38 +72,65 @@ impl StateController for SCUpgrader {
         }
 
         // upgrade controller if nothing to build
-        for structure in room.find(find::STRUCTURES, None).iter() {
-            if let StructureObject::StructureController(controller) = structure {
-                return Box::new(UpgradeState::new(controller.id()));
-            }
+        if let Some(controller) = room.controller() {
+            return Box::new(UpgradeState::new(controller.id));
         }
 
         // return idle state if no other states are compatible
         Box::new(IdleState {})
     }
 
-    fn get_best_worker_body(&self, room: &Room) -> Vec<Part> {
-        let mut base_body = vec![];
-        let blueprint = vec![
-            Part::Move,
-            Part::Carry,
-            Part::Work,
-            Part::Work,
-            Part::Move,
-            Part::Move,
-        ];
-        let blueprint_cost = blueprint.iter().map(|p: &Part| p.cost()).sum::<u32>();
-        let energy_available: u32 = utils::get_total_upgrade_energy(room);
-        let mut cost = base_body.iter().map(|p: &Part| p.cost()).sum::<u32>();
-
-        // keep adding parts from blueprint until we reach the energy limit
-        while cost + blueprint_cost <= energy_available {
-            for part in blueprint.iter() {
-                base_body.push(*part);
-                cost += part.cost();
-            }
-        }
+    fn get_best_worker_body(&self, room: &dyn RoomView) -> Body {
+        BodyPlan::new()
+            .ratio(Part::Work, 1)
+            .ratio(Part::Carry, 1)
+            .roads(utils::has_roads(room))
+            .build(utils::get_total_upgrade_energy(room))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld, StoreInfo};
+
+    fn world_with_upgrader(energy: u32) -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_controller(position(25, 10, test_room()), 5);
+        world.add_creep(
+            "upgrader",
+            position(25, 20, test_room()),
+            vec![Part::Work, Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Upgrader),
+        );
+        world.creep_mut("upgrader").store = StoreInfo::new(50).with(ResourceType::Energy, energy);
+        world
+    }
+
+    #[test]
+    fn empty_upgrader_draws_from_the_controller_link() {
+        let mut world = world_with_upgrader(0);
+        let link = world.add_structure(
+            StructureType::Link,
+            position(26, 12, test_room()),
+            Some(StoreInfo::restricted(800, ResourceType::Energy).with(ResourceType::Energy, 400)),
+        );
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCUpgrader::new();
+
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Withdraw);
+        assert_eq!(controller.current_state().get_target(), Some(link));
+    }
+
+    #[test]
+    fn upgrader_with_energy_upgrades() {
+        let world = world_with_upgrader(50);
+        let creep = world.creeps()[0].clone();
+        let mut controller = SCUpgrader::new();
 
-        base_body
+        controller.run_tick(creep.as_ref(), &world);
+        assert_eq!(controller.current_state().get_state_name(), StateName::Upgrade);
     }
 }
@@ -0,0 +1,98 @@
+// Narrow gaps between walls, i.e. the tiles a few ramparts could close off.
+// A tile is part of a chokepoint if, along some line through it, the walls on either side are at
+// most MAX_GAP tiles apart, while the two tiles either side of it across that line are open, so
+// creeps pass through it rather than it being the end of a dead end (or a room's corner).
+use super::tile_map::{all_tiles, TileMap};
+use screeps::{LocalRoomTerrain, RoomXY, Terrain};
+
+// Widest gap that still counts as a chokepoint
+const MAX_GAP: u32 = 3;
+
+// Each line through a tile, along with the line across it
+const AXES: [((i8, i8), (i8, i8)); 4] = [
+    ((1, 0), (0, 1)),
+    ((0, 1), (1, 0)),
+    ((1, 1), (1, -1)),
+    ((1, -1), (1, 1)),
+];
+
+/// Every tile in a chokepoint, see the module docs
+pub fn chokepoints(terrain: &LocalRoomTerrain) -> Vec<RoomXY> {
+    let walkable = |xy: RoomXY| terrain.get_xy(xy) != Terrain::Wall;
+    let mut gaps = TileMap::new(false);
+    for xy in all_tiles().filter(|xy| !xy.is_room_edge() && walkable(*xy)) {
+        gaps[xy] = AXES.into_iter().any(|(along, across)| {
+            let open = |(dx, dy): (i8, i8)| {
+                [(dx, dy), (dx * 2, dy * 2)]
+                    .into_iter()
+                    .all(|step| xy.checked_add(step).is_some_and(walkable))
+            };
+            gap_width(terrain, xy, along).is_some_and(|width| width <= MAX_GAP)
+                && open(across)
+                && open((-across.0, -across.1))
+        });
+    }
+    gaps.iter()
+        .filter(|(_, gap)| *gap)
+        .map(|(xy, _)| xy)
+        .collect()
+}
+
+/// How many walkable tiles there are in a row through the tile along the given direction, or None
+/// if the row reaches the room's edge rather than a wall on both sides
+fn gap_width(terrain: &LocalRoomTerrain, xy: RoomXY, (dx, dy): (i8, i8)) -> Option<u32> {
+    let mut width = 1;
+    for step in [(dx, dy), (-dx, -dy)] {
+        let mut current = xy;
+        loop {
+            let next = current.checked_add(step)?;
+            if terrain.get_xy(next) == Terrain::Wall {
+                break;
+            }
+            if next.is_room_edge() {
+                return None;
+            }
+            width += 1;
+            if width > MAX_GAP {
+                return Some(width);
+            }
+            current = next;
+        }
+    }
+    Some(width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{plain_terrain, terrain_from_rows};
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::checked_new(x, y).unwrap()
+    }
+
+    #[test]
+    fn open_room_has_no_chokepoints() {
+        assert!(chokepoints(&plain_terrain()).is_empty());
+    }
+
+    #[test]
+    fn finds_the_gap_in_a_wall() {
+        let mut rows = vec![String::new(); 20];
+        rows.push(format!("{}..{}", "#".repeat(25), "#".repeat(23)));
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let terrain = terrain_from_rows(&rows);
+
+        assert_eq!(chokepoints(&terrain), vec![xy(25, 20), xy(26, 20)]);
+    }
+
+    #[test]
+    fn gaps_wider_than_the_limit_are_not_chokepoints() {
+        let mut rows = vec![String::new(); 20];
+        rows.push(format!("{}....{}", "#".repeat(25), "#".repeat(21)));
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let terrain = terrain_from_rows(&rows);
+
+        assert!(chokepoints(&terrain).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use super::tile_map::{all_tiles, TileMap};
+use screeps::{LocalRoomTerrain, RoomXY, Terrain};
+use std::collections::VecDeque;
+
+/// Distance of tiles that can't be reached
+pub const UNREACHABLE: u32 = u32::MAX;
+
+/// How far each tile is from the nearest wall, counting diagonal steps as one. Walls are 0, and
+/// anything outside the room counts as a wall, so the room's edge tiles are at most 1
+pub fn distance_transform(terrain: &LocalRoomTerrain) -> TileMap<u8> {
+    let mut distances = TileMap::new(0u8);
+    let at = |distances: &TileMap<u8>, xy: RoomXY, dx: i8, dy: i8| {
+        xy.checked_add((dx, dy)).map_or(0, |next| distances[next])
+    };
+    // Two passes, each taking the neighbours already visited into account
+    for xy in all_tiles() {
+        if terrain.get_xy(xy) == Terrain::Wall {
+            continue;
+        }
+        let nearest = [(-1, -1), (0, -1), (1, -1), (-1, 0)]
+            .into_iter()
+            .map(|(dx, dy)| at(&distances, xy, dx, dy))
+            .min()
+            .unwrap_or(0);
+        distances[xy] = nearest + 1;
+    }
+    let tiles: Vec<RoomXY> = all_tiles().collect();
+    for xy in tiles.into_iter().rev() {
+        if terrain.get_xy(xy) == Terrain::Wall {
+            continue;
+        }
+        let nearest = [(1, 1), (0, 1), (-1, 1), (1, 0)]
+            .into_iter()
+            .map(|(dx, dy)| at(&distances, xy, dx, dy))
+            .min()
+            .unwrap_or(0);
+        distances[xy] = distances[xy].min(nearest + 1);
+    }
+    distances
+}
+
+/// Walking distance of every tile from the nearest of `from`, only stepping onto tiles that aren't
+/// walls and that `passable` allows. Tiles that can't be reached are UNREACHABLE
+pub fn flood_fill(
+    terrain: &LocalRoomTerrain,
+    from: &[RoomXY],
+    passable: impl Fn(RoomXY) -> bool,
+) -> TileMap<u32> {
+    let mut distances = TileMap::new(UNREACHABLE);
+    let mut frontier = VecDeque::new();
+    for xy in from {
+        distances[*xy] = 0;
+        frontier.push_back(*xy);
+    }
+    while let Some(xy) = frontier.pop_front() {
+        let distance = distances[xy] + 1;
+        for next in xy.neighbors() {
+            if distances[next] == UNREACHABLE
+                && terrain.get_xy(next) != Terrain::Wall
+                && passable(next)
+            {
+                distances[next] = distance;
+                frontier.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+/// Walking distance of every tile from the nearest of `from`, around terrain walls only
+pub fn distances(terrain: &LocalRoomTerrain, from: &[RoomXY]) -> TileMap<u32> {
+    flood_fill(terrain, from, |_| true)
+}
+
+/// Walking distance of every tile from the nearest exit
+pub fn exit_distances(terrain: &LocalRoomTerrain) -> TileMap<u32> {
+    let exits: Vec<RoomXY> = exits(terrain).collect();
+    distances(terrain, &exits)
+}
+
+/// The room's exit tiles
+pub fn exits(terrain: &LocalRoomTerrain) -> impl Iterator<Item = RoomXY> + '_ {
+    all_tiles().filter(|xy| xy.is_room_edge() && terrain.get_xy(*xy) != Terrain::Wall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{plain_terrain, terrain_from_rows};
+
+    fn xy(x: u8, y: u8) -> RoomXY {
+        RoomXY::checked_new(x, y).unwrap()
+    }
+
+    /// Plain everywhere, with exits all the way round
+    fn open_terrain() -> LocalRoomTerrain {
+        terrain_from_rows(&[])
+    }
+
+    /// A wall across the room at x = 20, with walls around the edges
+    fn split_terrain() -> LocalRoomTerrain {
+        let row = format!("#{}#{}#", ".".repeat(19), ".".repeat(28));
+        let mut rows = vec!["#".repeat(50)];
+        rows.extend(std::iter::repeat_n(row, 48));
+        rows.push("#".repeat(50));
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        terrain_from_rows(&rows)
+    }
+
+    #[test]
+    fn distance_transform_counts_steps_to_the_nearest_wall() {
+        let distances = distance_transform(&plain_terrain());
+        assert_eq!(distances[xy(0, 0)], 0);
+        assert_eq!(distances[xy(1, 1)], 1);
+        assert_eq!(distances[xy(3, 10)], 3);
+        assert_eq!(distances[xy(25, 25)], 24);
+
+        // Outside the room counts as a wall too
+        let distances = distance_transform(&open_terrain());
+        assert_eq!(distances[xy(0, 0)], 1);
+        assert_eq!(distances[xy(49, 25)], 1);
+    }
+
+    #[test]
+    fn flood_fill_goes_around_impassable_tiles() {
+        let distances = flood_fill(&plain_terrain(), &[xy(10, 10)], |xy| xy.x.u8() != 30);
+        assert_eq!(distances[xy(10, 10)], 0);
+        assert_eq!(distances[xy(15, 12)], 5);
+        assert_eq!(distances[xy(0, 0)], UNREACHABLE);
+        assert_eq!(distances[xy(35, 10)], UNREACHABLE);
+    }
+
+    #[test]
+    fn distances_are_walking_distances_around_walls() {
+        let terrain = split_terrain();
+        let distances = distances(&terrain, &[xy(10, 10), xy(10, 40)]);
+        assert_eq!(distances[xy(13, 17)], 7);
+        assert_eq!(distances[xy(12, 38)], 2);
+        assert_eq!(distances[xy(20, 10)], UNREACHABLE);
+        assert_eq!(distances[xy(30, 10)], UNREACHABLE);
+    }
+
+    #[test]
+    fn exit_distances_are_measured_from_the_open_edges() {
+        let distances = exit_distances(&open_terrain());
+        assert_eq!(distances[xy(0, 17)], 0);
+        assert_eq!(distances[xy(3, 17)], 3);
+        assert_eq!(distances[xy(25, 25)], 24);
+
+        // A room walled in all the way round has no exits
+        let distances = exit_distances(&plain_terrain());
+        assert_eq!(exits(&plain_terrain()).count(), 0);
+        assert_eq!(distances[xy(25, 25)], UNREACHABLE);
+    }
+}
@@ -0,0 +1,90 @@
+// Room geometry worked out from terrain alone: how open each tile is, how far tiles are from each
+// other and from the exits, and where the narrow gaps are. The functions here are pure, taking
+// the terrain and returning a map of the room. Terrain never changes, so analyse() works out the
+// maps every caller needs once per room and keeps them for the rest of the global.
+mod chokepoints;
+mod distance;
+mod tile_map;
+
+pub use chokepoints::chokepoints;
+pub use distance::{distance_transform, distances, exit_distances, exits, flood_fill, UNREACHABLE};
+pub use tile_map::{all_tiles, TileMap};
+
+use crate::world::RoomView;
+use screeps::{LocalRoomTerrain, RoomName, RoomXY, Terrain};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+thread_local! {
+    static ANALYSES: RefCell<HashMap<RoomName, Rc<TerrainAnalysis>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// The terrain of a room and the maps worked out from it
+#[derive(Debug)]
+pub struct TerrainAnalysis {
+    pub terrain: LocalRoomTerrain,
+    // Distance from the nearest wall, see distance_transform
+    pub openness: TileMap<u8>,
+    // Walking distance from the nearest exit
+    pub exit_distances: TileMap<u32>,
+    pub chokepoints: Vec<RoomXY>,
+}
+
+impl TerrainAnalysis {
+    pub fn new(terrain: LocalRoomTerrain) -> Self {
+        TerrainAnalysis {
+            openness: distance_transform(&terrain),
+            exit_distances: exit_distances(&terrain),
+            chokepoints: chokepoints(&terrain),
+            terrain,
+        }
+    }
+
+    pub fn is_walkable(&self, xy: RoomXY) -> bool {
+        is_walkable(&self.terrain, xy)
+    }
+}
+
+/// The analysis of the room's terrain, worked out the first time it is asked for
+pub fn analyse(room: &dyn RoomView) -> Rc<TerrainAnalysis> {
+    ANALYSES.with(|analyses| {
+        analyses
+            .borrow_mut()
+            .entry(room.name())
+            .or_insert_with(|| Rc::new(TerrainAnalysis::new(room.terrain())))
+            .clone()
+    })
+}
+
+pub fn is_walkable(terrain: &LocalRoomTerrain, xy: RoomXY) -> bool {
+    terrain.get_xy(xy) != Terrain::Wall
+}
+
+/// The tiles next to the given one that aren't walls
+pub fn walkable_neighbours(terrain: &LocalRoomTerrain, xy: RoomXY) -> Vec<RoomXY> {
+    xy.neighbors()
+        .into_iter()
+        .filter(|next| is_walkable(terrain, *next))
+        .collect()
+}
+
+/// How many creeps can stand next to the tile at once, e.g. to harvest a source
+pub fn slots(terrain: &LocalRoomTerrain, xy: RoomXY) -> usize {
+    walkable_neighbours(terrain, xy).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::plain_terrain;
+
+    #[test]
+    fn slots_count_the_walkable_tiles_around() {
+        let terrain = plain_terrain();
+        assert_eq!(slots(&terrain, RoomXY::checked_new(25, 25).unwrap()), 8);
+        assert_eq!(slots(&terrain, RoomXY::checked_new(1, 1).unwrap()), 3);
+        assert_eq!(slots(&terrain, RoomXY::checked_new(1, 25).unwrap()), 5);
+    }
+}
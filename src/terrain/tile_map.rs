@@ -0,0 +1,47 @@
+use screeps::constants::{ROOM_AREA, ROOM_USIZE};
+use screeps::RoomXY;
+use std::ops::{Index, IndexMut};
+
+/// A value for every tile in a room
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMap<T> {
+    tiles: Vec<T>,
+}
+
+impl<T: Copy> TileMap<T> {
+    /// A map with every tile set to `value`
+    pub fn new(value: T) -> Self {
+        TileMap {
+            tiles: vec![value; ROOM_AREA],
+        }
+    }
+
+    /// Every tile in the room along with its value
+    pub fn iter(&self) -> impl Iterator<Item = (RoomXY, T)> + '_ {
+        all_tiles().map(|xy| (xy, self[xy]))
+    }
+}
+
+impl<T> Index<RoomXY> for TileMap<T> {
+    type Output = T;
+
+    fn index(&self, xy: RoomXY) -> &T {
+        &self.tiles[index(xy)]
+    }
+}
+
+impl<T> IndexMut<RoomXY> for TileMap<T> {
+    fn index_mut(&mut self, xy: RoomXY) -> &mut T {
+        &mut self.tiles[index(xy)]
+    }
+}
+
+fn index(xy: RoomXY) -> usize {
+    xy.y.u8() as usize * ROOM_USIZE + xy.x.u8() as usize
+}
+
+/// Every tile in a room, row by row
+pub fn all_tiles() -> impl Iterator<Item = RoomXY> {
+    (0..ROOM_USIZE as u8)
+        .flat_map(|y| (0..ROOM_USIZE as u8).filter_map(move |x| RoomXY::checked_new(x, y).ok()))
+}
@@ -1,106 +1,282 @@
+// Runs every tower in a room as one battery.
+// Each tick the room's towers go through a priority pipeline: attack hostile creeps, otherwise
+// heal our damaged creeps, otherwise repair. All towers in a room shoot the same hostile, chosen
+// as the one we can do the most damage to once its (and its friends') healing is taken off.
+pub use repair_policy::{RepairPolicy, RepairUrgency};
+
+mod repair_policy;
+
+use crate::defense::{HostileThreat, RoomThreat};
+use crate::world::{RoomView, StructureInfo, TowerView, World};
 use log::warn;
-use screeps::{find, game, HasHits, HasId, HasPosition, ResourceType, Room, StructureObject, StructureTower};
+use screeps::constants::{
+    TOWER_FALLOFF, TOWER_FALLOFF_RANGE, TOWER_OPTIMAL_RANGE, TOWER_POWER_ATTACK, TOWER_POWER_HEAL,
+};
+use screeps::{Creep, ObjectId, RawObjectId, ResourceType, RoomName};
+use std::collections::HashMap;
+use std::rc::Rc;
 
-pub struct TowerManager;
+pub struct TowerManager {
+    repair_policy: RepairPolicy,
+}
 
 impl TowerManager {
-    pub fn new() -> Self {
-        TowerManager
+    pub fn new(repair_policy: RepairPolicy) -> Self {
+        TowerManager { repair_policy }
     }
 
-    pub fn run_all_towers(&self) {
-        for room in game::rooms().values() {
-            // Iterate through all towers in the game and run their logic
-            for structure in room.find(find::STRUCTURES, None).iter() {
-                if let StructureObject::StructureTower(tower) = structure {
-                    self.run(&room, &tower);
-                }
-            }
+    pub fn run_all_towers(&self, world: &dyn World, threats: &HashMap<RoomName, RoomThreat>) {
+        let no_threat = RoomThreat::default();
+        for room in world.rooms() {
+            let threat = threats.get(&room.name()).unwrap_or(&no_threat);
+            self.run(world, room.as_ref(), threat);
         }
     }
 
-    /// Run the tower manager logic for the given tower
-    fn run(&self, room: &Room, tower: &StructureTower) {
-        if tower.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
+    /// Run the pipeline for all towers in the room
+    fn run(&self, world: &dyn World, room: &dyn RoomView, threat: &RoomThreat) {
+        let towers = loaded_towers(room);
+        if towers.is_empty() {
             return;
         }
-        if let Some(target) = self.find_repair_target(room, tower) {
-            // Extract the individual structure type that implements Repairable
-            match &target {
-                StructureObject::StructureRoad(road) => {
-                    let _ = tower.repair(road).map_err(|err| {
-                        warn!("Tower {} failed to repair road: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureContainer(container) => {
-                    let _ = tower.repair(container).map_err(|err| {
-                        warn!("Tower {} failed to repair container: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureRampart(rampart) => {
-                    let _ = tower.repair(rampart).map_err(|err| {
-                        warn!("Tower {} failed to repair rampart: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureWall(wall) => {
-                    let _ = tower.repair(wall).map_err(|err| {
-                        warn!("Tower {} failed to repair wall: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureExtension(extension) => {
-                    let _ = tower.repair(extension).map_err(|err| {
-                        warn!("Tower {} failed to repair extension: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureSpawn(spawn) => {
-                    let _ = tower.repair(spawn).map_err(|err| {
-                        warn!("Tower {} failed to repair spawn: {}", tower.id(), err);
-                    });
-                }
-                StructureObject::StructureTower(tower_target) => {
-                    let _ = tower.repair(tower_target).map_err(|err| {
-                        warn!("Tower {} failed to repair tower: {}", tower.id(), err);
-                    });
-                }
-                _ => {}
+
+        if let Some(target) = self.find_attack_target(&towers, threat) {
+            for tower in towers.iter() {
+                let _ = tower.attack(target).map_err(|err| {
+                    warn!("Tower {} failed to attack: {:?}", tower.id(), err);
+                });
             }
+            return;
         }
-    }
 
-    /// Find a repair target and sort based on distance to the tower
-    fn find_repair_target(&self, room: &Room, tower: &StructureTower) -> Option<StructureObject> {
-        // Logic to find a structure that needs repairing
-        let structures = room.find(find::STRUCTURES, None);
-        structures.iter().filter_map(|s| match s {
-            StructureObject::StructureRoad(road) if road.hits() < road.hits_max() => {
-                Some(s.clone())
+        // Still heal when the hostiles are out of reach or out-heal us, but don't spend energy
+        // on repairs while they're about, we need it for when they come into range
+        if self.heal_creeps(world, room, &towers) || !threat.is_empty() {
+            return;
+        }
+
+        let mut taken = Vec::new();
+        for tower in towers.iter() {
+            if !self.can_repair(tower.as_ref()) {
+                continue;
+            }
+            if let Some(target) = self.find_repair_target(room, tower.as_ref(), &taken) {
+                taken.push(target.id);
+                let _ = tower.repair(target.id).map_err(|err| {
+                    warn!(
+                        "Tower {} failed to repair {:?}: {:?}",
+                        tower.id(),
+                        target.structure_type,
+                        err
+                    );
+                });
             }
-            StructureObject::StructureContainer(container)
-                if container.hits() < container.hits_max() =>
-            {
-                Some(s.clone())
+        }
+    }
+
+    /// The hostile all towers should focus on: the one taking the most damage after healing,
+    /// and the weakest of those if it's a tie. None if no hostile would take any damage, since
+    /// shooting a target that out-heals us just wastes energy
+    fn find_attack_target(
+        &self,
+        towers: &[Rc<dyn TowerView + '_>],
+        threat: &RoomThreat,
+    ) -> Option<ObjectId<Creep>> {
+        threat
+            .hostiles
+            .iter()
+            .map(|hostile| (hostile, net_damage(towers, hostile, threat)))
+            .filter(|(_, damage)| *damage > 0)
+            .max_by_key(|(hostile, damage)| (*damage, std::cmp::Reverse(hostile.info.hits)))
+            .map(|(hostile, _)| hostile.info.id)
+    }
+
+    /// Heal our damaged creeps, most damaged first, using only as many towers as each one needs.
+    /// Returns true if any tower healed
+    fn heal_creeps(
+        &self,
+        world: &dyn World,
+        room: &dyn RoomView,
+        towers: &[Rc<dyn TowerView + '_>],
+    ) -> bool {
+        let mut damaged: Vec<_> = world
+            .creeps_in_room(room.name())
+            .into_iter()
+            .filter(|creep| creep.hits() < creep.hits_max())
+            .collect();
+        damaged.sort_by_key(|creep| std::cmp::Reverse(creep.hits_max() - creep.hits()));
+
+        let mut free: Vec<_> = towers.iter().collect();
+        for creep in damaged.iter() {
+            let mut missing = creep.hits_max() - creep.hits();
+            while missing > 0 && !free.is_empty() {
+                // Closest tower heals the most
+                let (index, _) = free
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, tower)| tower.pos().get_range_to(creep.pos()))
+                    .unwrap();
+                let tower = free.swap_remove(index);
+                let heal = tower_power(TOWER_POWER_HEAL, tower.pos().get_range_to(creep.pos()));
+                let _ = tower.heal(creep.id()).map_err(|err| {
+                    warn!(
+                        "Tower {} failed to heal {}: {:?}",
+                        tower.id(),
+                        creep.name(),
+                        err
+                    );
+                });
+                missing = missing.saturating_sub(heal);
             }
-            // StructureObject::StructureRampart(rampart)
-            //     if rampart.hits() < rampart.hits_max() =>
-            // {
-            //     Some(s.clone())
-            // }
-            // StructureObject::StructureWall(wall) if wall.hits() < wall.hits_max() => {
-            //     Some(s.clone())
-            // }
-            // StructureObject::StructureExtension(extension)
-            //     if extension.hits() < extension.hits_max() =>
-            // {
-            //     Some(s.clone())
-            // }
-            // StructureObject::StructureSpawn(spawn) if spawn.hits() < spawn.hits_max() => {
-            //     Some(s.clone())
-            // }
-            // StructureObject::StructureTower(tower) if tower.hits() < tower.hits_max() => {
-            //     Some(s.clone())
-            // }
-            _ => None,
-        })
-        .min_by_key(|s| tower.pos().get_range_to(s.pos()))
+        }
+        free.len() < towers.len()
+    }
+
+    /// Whether the tower has energy to spare for repairs, over what it keeps back for defense
+    fn can_repair(&self, tower: &dyn TowerView) -> bool {
+        let store = tower.store();
+        let energy = store.get_used_capacity(Some(ResourceType::Energy));
+        let capacity = store.get_capacity(Some(ResourceType::Energy));
+        energy as f64 > capacity as f64 * self.repair_policy.energy_reserve
+    }
+
+    /// Find the most urgent repair target. Expiring ramparts and walls are repaired weakest
+    /// first, everything else nearest to the tower first. Targets another tower has already
+    /// taken this tick come last within their urgency, so towers spread out over the room
+    fn find_repair_target(
+        &self,
+        room: &dyn RoomView,
+        tower: &dyn TowerView,
+        taken: &[RawObjectId],
+    ) -> Option<StructureInfo> {
+        let level = room.controller().map_or(0, |controller| controller.level);
+        room.structures()
+            .into_iter()
+            .filter_map(|s| {
+                let urgency = self.repair_policy.urgency(&s, level)?;
+                let order = match urgency {
+                    RepairUrgency::Damaged => tower.pos().get_range_to(s.pos),
+                    RepairUrgency::Expiring | RepairUrgency::Fortify => s.hits,
+                };
+                Some(((urgency, taken.contains(&s.id), order), s))
+            })
+            .min_by_key(|(key, _)| *key)
+            .map(|(_, s)| s)
+    }
+}
+
+/// Towers in the room with energy to act
+pub fn loaded_towers(room: &dyn RoomView) -> Vec<Rc<dyn TowerView + '_>> {
+    room.towers()
+        .into_iter()
+        .filter(|tower| tower.store().get_used_capacity(Some(ResourceType::Energy)) > 0)
+        .collect()
+}
+
+/// Amount a tower action achieves at the given range, after falloff
+pub fn tower_power(power: u32, range: u32) -> u32 {
+    let optimal = TOWER_OPTIMAL_RANGE as u32;
+    let falloff = TOWER_FALLOFF_RANGE as u32;
+    let range = range.clamp(optimal, falloff);
+    let penalty = TOWER_FALLOFF * (range - optimal) as f64 / (falloff - optimal) as f64;
+    (power as f64 * (1.0 - penalty)) as u32
+}
+
+/// Hits all towers together would take off the hostile in a tick, minus what it and the hostiles
+/// around it can heal back
+pub fn net_damage(
+    towers: &[Rc<dyn TowerView + '_>],
+    target: &HostileThreat,
+    threat: &RoomThreat,
+) -> i32 {
+    let damage: u32 = towers
+        .iter()
+        .map(|tower| tower_power(TOWER_POWER_ATTACK, tower.pos().get_range_to(target.pos())))
+        .sum();
+    target.damage_taken(damage) as i32 - threat.healing_at(target.pos()) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defense;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, Intent, MockWorld, StoreInfo};
+    use screeps::{Part, StructureType};
+
+    fn world_with_tower() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_structure(
+            StructureType::Tower,
+            position(25, 25, test_room()),
+            Some(StoreInfo::restricted(1000, ResourceType::Energy).with(ResourceType::Energy, 1000)),
+        );
+        world.add_creep(
+            "defender",
+            position(25, 30, test_room()),
+            vec![Part::Attack, Part::Move],
+            CreepMemory::new(Specialisation::Defender),
+        );
+        world.creep_mut("defender").hits = 100;
+        world
+    }
+
+    fn run(world: &MockWorld) -> Vec<Intent> {
+        let threats = defense::assess(world);
+        TowerManager::new(RepairPolicy::default()).run_all_towers(world, &threats);
+        world.take_intents()
+    }
+
+    #[test]
+    fn towers_attack_hostiles_before_healing() {
+        let mut world = world_with_tower();
+        let hostile =
+            world.add_hostile("Invader", position(25, 20, test_room()), vec![Part::Attack]);
+
+        let intents = run(&world);
+        assert_eq!(intents.len(), 1);
+        assert!(matches!(intents[0], Intent::TowerAttack { target, .. } if target == hostile));
+    }
+
+    #[test]
+    fn towers_heal_when_no_hostile_is_worth_shooting() {
+        let mut world = world_with_tower();
+        // Far enough away, and healing enough, that the tower can't hurt it
+        world.add_hostile("Invader", position(25, 48, test_room()), vec![Part::Heal; 20]);
+
+        let intents = run(&world);
+        assert_eq!(intents.len(), 1);
+        assert!(matches!(intents[0], Intent::TowerHeal { .. }));
+    }
+
+    #[test]
+    fn towers_repair_different_structures() {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        let full = || {
+            Some(StoreInfo::restricted(1000, ResourceType::Energy).with(ResourceType::Energy, 1000))
+        };
+        world.add_structure(StructureType::Tower, position(25, 25, test_room()), full());
+        world.add_structure(StructureType::Tower, position(26, 25, test_room()), full());
+        // Closest to both towers
+        let near = world.add_structure(StructureType::Road, position(20, 25, test_room()), None);
+        let far = world.add_structure(StructureType::Road, position(35, 25, test_room()), None);
+        for road in world.room_mut(test_room()).structures.iter_mut().skip(2) {
+            road.hits = 500;
+        }
+
+        let mut repaired: Vec<_> = run(&world)
+            .into_iter()
+            .filter_map(|intent| match intent {
+                Intent::TowerRepair { target, .. } => Some(target),
+                _ => None,
+            })
+            .collect();
+        repaired.sort();
+        let mut expected = vec![near, far];
+        expected.sort();
+        assert_eq!(repaired, expected);
     }
 }
@@ -0,0 +1,140 @@
+// What is worth repairing and how far.
+// Walls and ramparts have millions of hits, so repairing them to full would drain every tower in
+// the room. Instead they are kept at a target that grows with the controller level, and ramparts
+// close to decaying away are repaired before anything else.
+use crate::world::StructureInfo;
+use screeps::constants::RAMPART_DECAY_AMOUNT;
+use screeps::StructureType;
+
+// A rampart is about to expire when it only has this many decays left in it
+const URGENT_RAMPART_DECAYS: u32 = 5;
+
+/// How urgently a structure needs repairing, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RepairUrgency {
+    // Rampart that will decay away soon
+    Expiring,
+    // Roads, containers and anything else that should be kept at full hits
+    Damaged,
+    // Wall or rampart below the target for the controller level
+    Fortify,
+}
+
+/// See the module docs
+#[derive(Debug, Clone)]
+pub struct RepairPolicy {
+    // Hits to keep walls at, indexed by controller level
+    pub wall_hits: [u32; 9],
+    // Hits to keep ramparts at, indexed by controller level
+    pub rampart_hits: [u32; 9],
+    // Fraction of a tower's energy kept back for defense, towers won't repair below it
+    pub energy_reserve: f64,
+}
+
+impl Default for RepairPolicy {
+    fn default() -> Self {
+        let fortify = [
+            0, 0, 10_000, 30_000, 100_000, 300_000, 1_000_000, 3_000_000, 10_000_000,
+        ];
+        RepairPolicy {
+            wall_hits: fortify,
+            rampart_hits: fortify,
+            energy_reserve: 0.5,
+        }
+    }
+}
+
+impl RepairPolicy {
+    /// Hits the structure should be repaired up to at the controller level
+    pub fn target_hits(&self, structure: &StructureInfo, level: u8) -> u32 {
+        let level = (level as usize).min(8);
+        match structure.structure_type {
+            StructureType::Wall => self.wall_hits[level].min(structure.hits_max),
+            StructureType::Rampart => self.rampart_hits[level].min(structure.hits_max),
+            _ => structure.hits_max,
+        }
+    }
+
+    /// How urgently the structure needs repairing, None if it doesn't.
+    /// Only roads, containers, walls and ramparts are repaired, anything else being damaged
+    /// means we are under attack and the energy is better spent on defense
+    pub fn urgency(&self, structure: &StructureInfo, level: u8) -> Option<RepairUrgency> {
+        let target = self.target_hits(structure, level);
+        if structure.hits >= target {
+            return None;
+        }
+        match structure.structure_type {
+            StructureType::Rampart
+                if structure.hits <= RAMPART_DECAY_AMOUNT * URGENT_RAMPART_DECAYS =>
+            {
+                Some(RepairUrgency::Expiring)
+            }
+            StructureType::Road | StructureType::Container => Some(RepairUrgency::Damaged),
+            StructureType::Wall | StructureType::Rampart => Some(RepairUrgency::Fortify),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{position, test_room, MockWorld};
+
+    fn structure(structure_type: StructureType, hits: u32, hits_max: u32) -> StructureInfo {
+        StructureInfo {
+            id: MockWorld::new().next_id(),
+            structure_type,
+            pos: position(25, 25, test_room()),
+            hits,
+            hits_max,
+            store: None,
+        }
+    }
+
+    #[test]
+    fn walls_and_ramparts_are_fortified_up_to_the_controller_level() {
+        let policy = RepairPolicy::default();
+        let wall = structure(StructureType::Wall, 1, 300_000_000);
+        assert_eq!(policy.target_hits(&wall, 0), 0);
+        assert_eq!(policy.target_hits(&wall, 3), 30_000);
+        assert_eq!(policy.target_hits(&wall, 8), 10_000_000);
+        // Levels past 8 are treated as 8
+        assert_eq!(policy.target_hits(&wall, 12), 10_000_000);
+
+        // Ramparts can't be repaired past their maximum, which is low at low levels
+        let rampart = structure(StructureType::Rampart, 1, 300_000);
+        assert_eq!(policy.target_hits(&rampart, 4), 100_000);
+        assert_eq!(policy.target_hits(&rampart, 6), 300_000);
+
+        let road = structure(StructureType::Road, 1, 5_000);
+        assert_eq!(policy.target_hits(&road, 0), 5_000);
+        assert_eq!(policy.target_hits(&road, 8), 5_000);
+    }
+
+    #[test]
+    fn expiring_ramparts_are_the_most_urgent() {
+        let policy = RepairPolicy::default();
+        let urgency = |structure_type, hits, hits_max| {
+            policy.urgency(&structure(structure_type, hits, hits_max), 4)
+        };
+        assert_eq!(
+            urgency(StructureType::Rampart, RAMPART_DECAY_AMOUNT, 3_000_000),
+            Some(RepairUrgency::Expiring)
+        );
+        assert_eq!(urgency(StructureType::Road, 4_000, 5_000), Some(RepairUrgency::Damaged));
+        assert_eq!(urgency(StructureType::Container, 1, 250_000), Some(RepairUrgency::Damaged));
+        assert_eq!(
+            urgency(StructureType::Rampart, 50_000, 3_000_000),
+            Some(RepairUrgency::Fortify)
+        );
+        assert_eq!(urgency(StructureType::Wall, 50_000, 300_000_000), Some(RepairUrgency::Fortify));
+        assert!(RepairUrgency::Expiring < RepairUrgency::Damaged);
+        assert!(RepairUrgency::Damaged < RepairUrgency::Fortify);
+
+        // At or over target, or not something we repair
+        assert_eq!(urgency(StructureType::Wall, 100_000, 300_000_000), None);
+        assert_eq!(urgency(StructureType::Road, 5_000, 5_000), None);
+        assert_eq!(urgency(StructureType::Spawn, 1, 5_000), None);
+    }
+}
@@ -0,0 +1,226 @@
+// Builds creep bodies from a plan, instead of every state controller growing its own
+use screeps::constants::{CREEP_SPAWN_TIME, MAX_CREEP_SIZE};
+use screeps::Part;
+
+/// A body that is ready to spawn
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Body {
+    pub parts: Vec<Part>,
+    pub cost: u32,
+    // Ticks it takes a spawn to build this body
+    pub spawn_time: u32,
+}
+
+impl Body {
+    fn new(mut parts: Vec<Part>) -> Self {
+        parts.sort_by_key(|part| part_order(*part));
+        Body {
+            cost: parts.iter().map(|part| part.cost()).sum(),
+            spawn_time: parts.len() as u32 * CREEP_SPAWN_TIME,
+            parts,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+/// Describes the shape of a body, which is then scaled up to fit an energy budget.
+/// The body is made of a fixed prefix, followed by as many sets of the ratio parts as fit, with
+/// enough MOVE parts added to keep the creep at full speed
+#[derive(Debug, Clone)]
+pub struct BodyPlan {
+    prefix: Vec<Part>,
+    ratios: Vec<(Part, u32)>,
+    max_parts: usize,
+    // Number of other parts each MOVE part can carry, off roads and on roads
+    move_ratio: (u32, u32),
+    roads: bool,
+}
+
+impl BodyPlan {
+    pub fn new() -> Self {
+        BodyPlan {
+            prefix: vec![],
+            ratios: vec![],
+            max_parts: MAX_CREEP_SIZE as usize,
+            move_ratio: (1, 2),
+            roads: false,
+        }
+    }
+
+    /// Parts every body starts with, regardless of size
+    pub fn prefix(mut self, parts: &[Part]) -> Self {
+        self.prefix.extend_from_slice(parts);
+        self
+    }
+
+    /// Add `count` of the part to each set that is repeated to fill the budget
+    pub fn ratio(mut self, part: Part, count: u32) -> Self {
+        self.ratios.push((part, count));
+        self
+    }
+
+    /// Limit the number of parts in the body. Bodies can never be bigger than 50 parts
+    pub fn max_parts(mut self, max_parts: usize) -> Self {
+        self.max_parts = max_parts.min(MAX_CREEP_SIZE as usize);
+        self
+    }
+
+    /// How many other parts each MOVE part can carry at full speed, off roads and on roads
+    pub fn move_ratio(mut self, off_road: u32, on_road: u32) -> Self {
+        self.move_ratio = (off_road.max(1), on_road.max(1));
+        self
+    }
+
+    /// Whether the creep will mostly travel on roads, so needs fewer MOVE parts
+    pub fn roads(mut self, roads: bool) -> Self {
+        self.roads = roads;
+        self
+    }
+
+    /// Build the biggest body that fits within the energy budget.
+    /// Empty if even the prefix can't be afforded
+    pub fn build(&self, energy: u32) -> Body {
+        let mut best = vec![];
+        let mut sets = 0;
+        loop {
+            let parts = self.parts_for(sets);
+            let cost: u32 = parts.iter().map(|part| part.cost()).sum();
+            if parts.len() > self.max_parts || cost > energy {
+                break;
+            }
+            best = parts;
+            // Without any ratio parts the body never grows
+            if self.ratios.is_empty() {
+                break;
+            }
+            sets += 1;
+        }
+        Body::new(best)
+    }
+
+    /// All parts, including MOVE parts, for the given number of sets
+    fn parts_for(&self, sets: u32) -> Vec<Part> {
+        let mut parts = self.prefix.clone();
+        for (part, count) in self.ratios.iter() {
+            parts.extend(std::iter::repeat_n(*part, (count * sets) as usize));
+        }
+        let carried = parts.iter().filter(|part| **part != Part::Move).count() as u32;
+        let moves = parts.iter().filter(|part| **part == Part::Move).count() as u32;
+        let per_move = if self.roads {
+            self.move_ratio.1
+        } else {
+            self.move_ratio.0
+        };
+        let needed = carried.div_ceil(per_move).saturating_sub(moves);
+        parts.extend(std::iter::repeat_n(Part::Move, needed as usize));
+        parts
+    }
+}
+
+impl Default for BodyPlan {
+    fn default() -> Self {
+        BodyPlan::new()
+    }
+}
+
+/// Order parts are laid out in. TOUGH goes first so it takes damage first, MOVE and HEAL last so
+/// the creep keeps moving and healing for as long as possible
+fn part_order(part: Part) -> u8 {
+    match part {
+        Part::Tough => 0,
+        Part::Work => 1,
+        Part::Carry => 2,
+        Part::Attack => 3,
+        Part::RangedAttack => 4,
+        Part::Claim => 5,
+        Part::Move => 6,
+        Part::Heal => 7,
+        _ => 8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(body: &Body, part: Part) -> usize {
+        body.parts.iter().filter(|p| **p == part).count()
+    }
+
+    fn worker() -> BodyPlan {
+        BodyPlan::new().ratio(Part::Work, 1).ratio(Part::Carry, 1)
+    }
+
+    #[test]
+    fn bodies_never_go_over_fifty_parts() {
+        let body = BodyPlan::new().ratio(Part::Carry, 1).build(100_000);
+        assert_eq!(body.parts.len(), MAX_CREEP_SIZE as usize);
+        assert_eq!(count(&body, Part::Carry), 25);
+
+        let body = worker().max_parts(100).build(100_000);
+        assert!(body.parts.len() <= MAX_CREEP_SIZE as usize);
+        let body = worker().max_parts(10).build(100_000);
+        assert!(body.parts.len() <= 10);
+    }
+
+    #[test]
+    fn cost_never_goes_over_the_budget() {
+        for energy in (0..=3000).step_by(25) {
+            for plan in [worker(), worker().roads(true), worker().prefix(&[Part::Carry])] {
+                let body = plan.build(energy);
+                assert!(body.cost <= energy, "{} over budget {}", body.cost, energy);
+                let cost: u32 = body.parts.iter().map(|part| part.cost()).sum();
+                assert_eq!(body.cost, cost);
+            }
+        }
+    }
+
+    #[test]
+    fn roads_need_half_the_move_parts() {
+        let off_road = worker().build(550);
+        assert_eq!(
+            off_road.parts,
+            vec![
+                Part::Work,
+                Part::Work,
+                Part::Carry,
+                Part::Carry,
+                Part::Move,
+                Part::Move,
+                Part::Move,
+                Part::Move
+            ]
+        );
+
+        let on_road = worker().roads(true).build(550);
+        assert_eq!(count(&on_road, Part::Work), 2);
+        assert_eq!(count(&on_road, Part::Move), 2);
+
+        // Odd numbers of parts round the MOVE parts up
+        let body = BodyPlan::new()
+            .ratio(Part::Work, 1)
+            .move_ratio(2, 2)
+            .max_parts(9)
+            .build(10_000);
+        assert_eq!(count(&body, Part::Work), 6);
+        assert_eq!(count(&body, Part::Move), 3);
+    }
+
+    #[test]
+    fn spawn_time_is_three_ticks_a_part() {
+        let body = worker().build(300);
+        assert_eq!(body.spawn_time, body.parts.len() as u32 * CREEP_SPAWN_TIME);
+        assert_eq!(body.spawn_time, 12);
+    }
+
+    #[test]
+    fn unaffordable_prefix_gives_an_empty_body() {
+        let body = worker().prefix(&[Part::Claim]).build(300);
+        assert!(body.is_empty());
+        assert_eq!(body.cost, 0);
+        assert_eq!(body.spawn_time, 0);
+    }
+}
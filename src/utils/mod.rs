@@ -0,0 +1,283 @@
+// Helpful utility functions for the project.
+pub use body_plan::{Body, BodyPlan};
+use crate::reservations::{reserved, ReservationKind};
+use crate::screep_states::StateName;
+use crate::terrain;
+use crate::tower_manager::{loaded_towers, RepairPolicy};
+use crate::world::{
+    CreepView, DroppedInfo, RemainsInfo, RoomView, SiteInfo, SourceInfo, StoreInfo, StructureInfo,
+    World,
+};
+use screeps::constants::TOWER_FALLOFF_RANGE;
+use screeps::{HasPosition, Position, RawObjectId, ResourceType, StructureType};
+
+mod body_plan;
+
+// Creeps repair decaying structures once they fall below this fraction of the hits the repair
+// policy wants for them
+const REPAIR_THRESHOLD: f64 = 0.5;
+// Loose resources smaller than this aren't worth going out of the way for
+const MIN_LOOT_AMOUNT: u32 = 50;
+
+/// Resources lying around outside of our structures
+pub enum Loot {
+    // A pile on the ground, which decays quickly
+    Pile(DroppedInfo),
+    // A tombstone or ruin, which keeps its resources until it crumbles
+    Remains(RemainsInfo),
+}
+
+pub mod prelude {
+    pub use {
+        super::find_active_sources, super::find_nearest_construction_site,
+        super::find_best_loot, super::find_nearest_object, super::find_repair_target,
+        super::get_total_upgrade_energy, super::unreserved_energy, super::unreserved_free_energy,
+        super::Loot,
+    };
+}
+
+// Gets the nearest object based on distance from the creep
+pub fn find_nearest_object<R>(
+    // reference position
+    position: &Position,
+    objects: &[R],
+) -> Option<R>
+where
+    R: HasPosition + Clone,
+{
+    if objects.is_empty() {
+        return None;
+    }
+    // Find the nearest object
+    let nearest = objects
+        .iter()
+        .min_by_key(|obj| position.get_range_to(obj.pos()))?;
+
+    Some(nearest.clone())
+}
+
+/// Get all sources in the room that still have energy left and a free tile next to them for the
+/// creep to harvest from. A tile counts as taken by any other creep standing next to the source,
+/// or on its way there to harvest it
+pub fn find_active_sources(
+    world: &dyn World,
+    room: &dyn RoomView,
+    creep: &dyn CreepView,
+) -> Vec<SourceInfo> {
+    let analysis = terrain::analyse(room);
+    let others = world.creeps_in_room(room.name());
+    room.sources()
+        .into_iter()
+        .filter(|source| source.energy > 0)
+        .filter(|source| {
+            let harvesters = others
+                .iter()
+                .filter(|other| {
+                    let memory = other.memory();
+                    other.name() != creep.name()
+                        && (other.pos().is_near_to(source.pos)
+                            || (*memory.current_state() == StateName::Harvest
+                                && memory.state_target() == Some(source.id.into())))
+                })
+                .count();
+            harvesters < terrain::slots(&analysis.terrain, source.pos.xy())
+        })
+        .collect()
+}
+
+/// Get the nearest construction site based on distance from the creep.
+/// Sites which other creeps already have enough energy reserved to finish are skipped
+pub fn find_nearest_construction_site(creep: &dyn CreepView, room: &dyn RoomView) -> Option<SiteInfo> {
+    let sites: Vec<SiteInfo> = room
+        .construction_sites()
+        .into_iter()
+        .filter(|site| {
+            let reserved = reserved(site.id.into(), ReservationKind::Deliver, ResourceType::Energy);
+            site.progress + reserved < site.progress_total
+        })
+        .collect();
+    find_nearest_object(&creep.pos(), &sites)
+}
+
+/// Get the nearest decaying structure (road, container or rampart) that has fallen below
+/// REPAIR_THRESHOLD of its target hits and that no other creep is repairing.
+/// Anything close enough to a tower for it to repair well is left to the towers
+pub fn find_repair_target(
+    creep: &dyn CreepView,
+    room: &dyn RoomView,
+    policy: &RepairPolicy,
+) -> Option<StructureInfo> {
+    let level = room.controller().map_or(0, |controller| controller.level);
+    let towers: Vec<Position> = loaded_towers(room).iter().map(|tower| tower.pos()).collect();
+    let structures: Vec<StructureInfo> = room
+        .structures()
+        .into_iter()
+        .filter(|structure| {
+            matches!(
+                structure.structure_type,
+                StructureType::Road | StructureType::Container | StructureType::Rampart
+            ) && (structure.hits as f64)
+                < policy.target_hits(structure, level) as f64 * REPAIR_THRESHOLD
+                && reserved(structure.id, ReservationKind::Deliver, ResourceType::Energy) == 0
+                && !towers
+                    .iter()
+                    .any(|tower| tower.get_range_to(structure.pos) <= TOWER_FALLOFF_RANGE as u32)
+        })
+        .collect();
+    find_nearest_object(&creep.pos(), &structures)
+}
+
+/// Get the loose resources most worth collecting: the pile, tombstone or ruin with the most of
+/// the resource (or of any resource if it is None) left after what other creeps are already
+/// taking, for how far away it is
+pub fn find_best_loot(
+    creep: &dyn CreepView,
+    room: &dyn RoomView,
+    resource: Option<ResourceType>,
+) -> Option<Loot> {
+    let piles = room
+        .dropped_resources()
+        .into_iter()
+        .filter(|pile| resource.is_none_or(|resource| pile.resource_type == resource))
+        .map(|pile| {
+            let outgoing = reserved(pile.id.into(), ReservationKind::Withdraw, pile.resource_type);
+            (pile.amount.saturating_sub(outgoing), pile.pos, Loot::Pile(pile))
+        });
+    let remains = room.remains().into_iter().map(|remains| {
+        let amount = unreserved_amount(remains.id, &remains.store, resource);
+        (amount, remains.pos, Loot::Remains(remains))
+    });
+    piles
+        .chain(remains)
+        .filter(|(amount, _, _)| *amount >= MIN_LOOT_AMOUNT)
+        .max_by(|(a, a_pos, _), (b, b_pos, _)| {
+            let worth = |amount: u32, pos: Position| {
+                amount as f64 / (creep.pos().get_range_to(pos) + 1) as f64
+            };
+            worth(*a, *a_pos).total_cmp(&worth(*b, *b_pos))
+        })
+        .map(|(_, _, loot)| loot)
+}
+
+/// Free energy capacity in the structure, minus what other creeps are already bringing to it
+pub fn unreserved_free_energy(structure: &StructureInfo) -> i32 {
+    let incoming = reserved(structure.id, ReservationKind::Deliver, ResourceType::Energy);
+    structure.store().get_free_capacity(Some(ResourceType::Energy)) - incoming as i32
+}
+
+/// Amount of the resource in a store (or of every resource if it is None), minus what other
+/// creeps are already taking from it
+pub fn unreserved_amount(id: RawObjectId, store: &StoreInfo, resource: Option<ResourceType>) -> u32 {
+    store
+        .contents
+        .iter()
+        .filter(|(ty, _)| resource.is_none_or(|resource| resource == *ty))
+        .map(|(ty, amount)| amount.saturating_sub(reserved(id, ReservationKind::Withdraw, *ty)))
+        .sum()
+}
+
+/// Energy in the structure, minus what other creeps are already taking from it
+pub fn unreserved_energy(structure: &StructureInfo) -> u32 {
+    let outgoing = reserved(structure.id, ReservationKind::Withdraw, ResourceType::Energy);
+    structure
+        .store()
+        .get_used_capacity(Some(ResourceType::Energy))
+        .saturating_sub(outgoing)
+}
+
+/// Whether the room has any roads yet, so creeps can be built with fewer MOVE parts
+pub fn has_roads(room: &dyn RoomView) -> bool {
+    room.structures()
+        .iter()
+        .any(|structure| structure.structure_type == StructureType::Road)
+}
+
+/// The max capacity of energy available for upgrades in a room.
+/// This is the sum of the spawns and any extensions in the room.
+pub fn get_total_upgrade_energy(room: &dyn RoomView) -> u32 {
+    let mut energy_available: u32 = 0;
+    for structure in room.structures().iter() {
+        if matches!(
+            structure.structure_type,
+            StructureType::Spawn | StructureType::Extension
+        ) {
+            energy_available += structure.store().get_capacity(Some(ResourceType::Energy));
+        }
+    }
+    energy_available
+}
+//
+// // Gets count of creeps in a room with a specific state
+// pub fn get_count_of_creeps_with_state(room: &Room, state_name: &str) -> usize {
+//     room.find(find::CREEPS, None)
+//         .iter()
+//         .filter(|creep| {
+//             creep
+//                 .memory()
+//                 .get("state")
+//                 .map_or(false, |s| s == state_name)
+//         })
+//         .count()
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reservations;
+    use crate::screep_states::CreepMemory;
+    use crate::state_controllers::Specialisation;
+    use crate::world::{position, test_room, MockWorld, RemainsKind, StoreInfo, World};
+    use screeps::Part;
+
+    fn world_with_hauler() -> MockWorld {
+        let mut world = MockWorld::new();
+        world.add_room(test_room());
+        world.add_creep(
+            "hauler",
+            position(10, 10, test_room()),
+            vec![Part::Carry, Part::Move],
+            CreepMemory::new(Specialisation::Hauler),
+        );
+        world
+    }
+
+    fn best_loot(world: &MockWorld, resource: Option<ResourceType>) -> Option<RawObjectId> {
+        let creep = world.creeps()[0].clone();
+        let room = world.room(test_room()).unwrap();
+        find_best_loot(creep.as_ref(), room.as_ref(), resource).map(|loot| match loot {
+            Loot::Pile(pile) => pile.id.into(),
+            Loot::Remains(remains) => remains.id,
+        })
+    }
+
+    #[test]
+    fn loot_is_ranked_by_amount_for_the_distance() {
+        let mut world = world_with_hauler();
+        let near = world.add_dropped(position(11, 10, test_room()), ResourceType::Energy, 100);
+        // Ten times as much, but thirty times as far
+        world.add_dropped(position(40, 10, test_room()), ResourceType::Energy, 1000);
+        // Too little to be worth the trip, however close
+        world.add_dropped(position(10, 11, test_room()), ResourceType::Energy, 40);
+        assert_eq!(best_loot(&world, Some(ResourceType::Energy)), Some(near.into()));
+
+        let tombstone = world.add_remains(
+            RemainsKind::Tombstone,
+            position(12, 10, test_room()),
+            StoreInfo::new(1000).with(ResourceType::Hydrogen, 500),
+            100,
+        );
+        assert_eq!(best_loot(&world, None), Some(tombstone));
+        assert_eq!(best_loot(&world, Some(ResourceType::Energy)), Some(near.into()));
+    }
+
+    #[test]
+    fn loot_other_creeps_are_taking_is_left_to_them() {
+        let mut world = world_with_hauler();
+        let near = world.add_dropped(position(11, 10, test_room()), ResourceType::Energy, 100);
+        let far = world.add_dropped(position(40, 10, test_room()), ResourceType::Energy, 1000);
+
+        reservations::reserve("other", near.into(), ReservationKind::Withdraw, None, 80);
+        assert_eq!(best_loot(&world, Some(ResourceType::Energy)), Some(far.into()));
+        reservations::release("other");
+    }
+}
@@ -0,0 +1,222 @@
+// Plain snapshots of game objects, so they can be built by both the live game and the mock
+use screeps::{
+    ConstructionSite, Creep, HasPosition, ObjectId, Part, Position, RawObjectId, Resource,
+    ResourceType, Source, StructureController, StructureType,
+};
+
+/// Snapshot of a store, following the same rules as the game's `Store`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreInfo {
+    // Amount of each resource in the store
+    pub contents: Vec<(ResourceType, u32)>,
+    // Total capacity of the store
+    pub capacity: u32,
+    // Some stores (spawns, extensions, towers) can only hold a single resource
+    pub restricted_to: Option<ResourceType>,
+}
+
+impl StoreInfo {
+    /// An empty store that can hold any resource
+    pub fn new(capacity: u32) -> Self {
+        StoreInfo {
+            contents: vec![],
+            capacity,
+            restricted_to: None,
+        }
+    }
+
+    /// An empty store that can only hold the given resource
+    pub fn restricted(capacity: u32, resource: ResourceType) -> Self {
+        StoreInfo {
+            contents: vec![],
+            capacity,
+            restricted_to: Some(resource),
+        }
+    }
+
+    /// Builder style helper to fill the store with a resource
+    pub fn with(mut self, resource: ResourceType, amount: u32) -> Self {
+        self.set(resource, amount);
+        self
+    }
+
+    /// Set the amount of a resource in the store
+    pub fn set(&mut self, resource: ResourceType, amount: u32) {
+        self.contents.retain(|(ty, _)| *ty != resource);
+        if amount > 0 {
+            self.contents.push((resource, amount));
+        }
+    }
+
+    /// All resource types currently in the store
+    pub fn store_types(&self) -> Vec<ResourceType> {
+        self.contents.iter().map(|(ty, _)| *ty).collect()
+    }
+
+    pub fn get_capacity(&self, ty: Option<ResourceType>) -> u32 {
+        match self.restricted_to {
+            Some(resource) if ty != Some(resource) => 0,
+            _ => self.capacity,
+        }
+    }
+
+    pub fn get_used_capacity(&self, ty: Option<ResourceType>) -> u32 {
+        self.contents
+            .iter()
+            .filter(|(resource, _)| ty.is_none() || ty == Some(*resource))
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    pub fn get_free_capacity(&self, ty: Option<ResourceType>) -> i32 {
+        let used = match self.restricted_to {
+            Some(_) => self.get_used_capacity(ty),
+            None => self.get_used_capacity(None),
+        };
+        self.get_capacity(ty) as i32 - used as i32
+    }
+}
+
+/// Snapshot of a structure
+#[derive(Debug, Clone)]
+pub struct StructureInfo {
+    pub id: RawObjectId,
+    pub structure_type: StructureType,
+    pub pos: Position,
+    pub hits: u32,
+    pub hits_max: u32,
+    // None for structures without a store
+    pub store: Option<StoreInfo>,
+}
+
+impl StructureInfo {
+    /// The structure's store, or an empty one if it doesn't have a store
+    pub fn store(&self) -> StoreInfo {
+        self.store.clone().unwrap_or_default()
+    }
+}
+
+impl HasPosition for StructureInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Snapshot of an energy source
+#[derive(Debug, Clone)]
+pub struct SourceInfo {
+    pub id: ObjectId<Source>,
+    pub pos: Position,
+    pub energy: u32,
+    pub energy_capacity: u32,
+    // None until the source has been harvested from
+    pub ticks_to_regeneration: Option<u32>,
+}
+
+impl HasPosition for SourceInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Snapshot of a pile of resources on the ground
+#[derive(Debug, Clone)]
+pub struct DroppedInfo {
+    pub id: ObjectId<Resource>,
+    pub pos: Position,
+    pub resource_type: ResourceType,
+    pub amount: u32,
+}
+
+impl HasPosition for DroppedInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// What is left behind when a creep dies or a structure is destroyed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemainsKind {
+    Tombstone,
+    Ruin,
+}
+
+/// Snapshot of a tombstone or ruin, which can be withdrawn from like a structure
+#[derive(Debug, Clone)]
+pub struct RemainsInfo {
+    pub id: RawObjectId,
+    pub kind: RemainsKind,
+    pub pos: Position,
+    pub store: StoreInfo,
+    pub ticks_to_decay: u32,
+}
+
+impl HasPosition for RemainsInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Snapshot of a construction site
+#[derive(Debug, Clone)]
+pub struct SiteInfo {
+    pub id: ObjectId<ConstructionSite>,
+    pub structure_type: StructureType,
+    pub pos: Position,
+    pub progress: u32,
+    pub progress_total: u32,
+}
+
+impl HasPosition for SiteInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// Snapshot of a room controller
+#[derive(Debug, Clone)]
+pub struct ControllerInfo {
+    pub id: ObjectId<StructureController>,
+    pub pos: Position,
+    pub my: bool,
+    pub level: u8,
+    pub progress: u32,
+    pub progress_total: u32,
+    // Ticks left of safe mode, if it's active
+    pub safe_mode: Option<u32>,
+    pub safe_mode_available: u32,
+    pub safe_mode_cooldown: Option<u32>,
+}
+
+impl HasPosition for ControllerInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
+
+/// A single body part of a creep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyPartInfo {
+    pub part: Part,
+    // Parts with no hits left don't do anything
+    pub hits: u32,
+    // Compound the part was boosted with, if any
+    pub boost: Option<ResourceType>,
+}
+
+/// Snapshot of a creep that isn't ours
+#[derive(Debug, Clone)]
+pub struct HostileInfo {
+    pub id: ObjectId<Creep>,
+    pub pos: Position,
+    pub owner: String,
+    pub hits: u32,
+    pub hits_max: u32,
+    pub body: Vec<BodyPartInfo>,
+}
+
+impl HasPosition for HostileInfo {
+    fn pos(&self) -> Position {
+        self.pos
+    }
+}
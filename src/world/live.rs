@@ -0,0 +1,585 @@
+// Live implementation of the world, backed by the screeps game api
+use super::{
+    ActionResult, BodyPartInfo, ControllerInfo, CreepView, DroppedInfo, HostileInfo, LinkView,
+    RemainsInfo, RemainsKind, RoomView, SiteInfo, SourceInfo, SpawnView, StoreInfo, StructureInfo,
+    TowerView, World,
+};
+use crate::movement::{PLAIN_COST, SWAMP_COST};
+use crate::planner::RoomMemory;
+use crate::screep_states::CreepMemory;
+use screeps::pathfinder::{self, MultiRoomCostResult, SearchOptions};
+use screeps::{
+    find, game, ConstructionSite, Creep, Direction, ErrorCode, HasId, HasPosition, LocalCostMatrix,
+    LocalRoomTerrain, MaybeHasId, ObjectId, OwnedStructureProperties, Part, Position, RawObjectId,
+    Resource, ResourceType, Room, RoomName, RoomObject, RoomXY, Ruin, SharedCreepProperties,
+    Source, SpawnOptions, Store, Structure, StructureController, StructureLink, StructureObject,
+    StructureProperties, StructureSpawn, StructureTower, StructureType, Tombstone,
+};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+/// The world as seen through the game api for the current tick
+pub struct LiveWorld;
+
+impl World for LiveWorld {
+    fn time(&self) -> u32 {
+        game::time()
+    }
+
+    fn creeps(&self) -> Vec<Rc<dyn CreepView>> {
+        game::creeps()
+            .values()
+            .map(|creep| Rc::new(LiveCreep(creep)) as Rc<dyn CreepView>)
+            .collect()
+    }
+
+    fn rooms(&self) -> Vec<Rc<dyn RoomView>> {
+        game::rooms()
+            .values()
+            .map(|room| Rc::new(LiveRoom(room)) as Rc<dyn RoomView>)
+            .collect()
+    }
+
+    fn room(&self, name: RoomName) -> Option<Rc<dyn RoomView>> {
+        game::rooms()
+            .get(name)
+            .map(|room| Rc::new(LiveRoom(room)) as Rc<dyn RoomView>)
+    }
+
+    fn spawns(&self) -> Vec<Rc<dyn SpawnView>> {
+        game::spawns()
+            .values()
+            .map(|spawn| Rc::new(LiveSpawn(spawn)) as Rc<dyn SpawnView>)
+            .collect()
+    }
+
+    fn source(&self, id: ObjectId<Source>) -> Option<SourceInfo> {
+        id.resolve().map(|source| source_info(&source))
+    }
+
+    fn structure(&self, id: RawObjectId) -> Option<StructureInfo> {
+        resolve_structure(id).map(|structure| structure_info(&structure))
+    }
+
+    fn construction_site(&self, id: ObjectId<ConstructionSite>) -> Option<SiteInfo> {
+        id.resolve().map(|site| site_info(&site))
+    }
+
+    fn dropped_resource(&self, id: ObjectId<Resource>) -> Option<DroppedInfo> {
+        id.resolve().map(|resource| dropped_info(&resource))
+    }
+
+    fn remains(&self, id: RawObjectId) -> Option<RemainsInfo> {
+        game::get_object_by_id_erased(&id).and_then(|object| remains_info(&object))
+    }
+
+    fn hostile(&self, id: ObjectId<Creep>) -> Option<HostileInfo> {
+        id.resolve()
+            .filter(|creep| !creep.my())
+            .map(|creep| hostile_info(&creep))
+    }
+
+    fn notify(&self, message: &str) {
+        game::notify(message, None);
+    }
+
+    fn find_path(
+        &self,
+        from: Position,
+        to: Position,
+        range: u32,
+        matrix: &mut dyn FnMut(RoomName) -> Option<LocalCostMatrix>,
+    ) -> Option<Vec<Position>> {
+        let options = SearchOptions::new(|room| match matrix(room) {
+            Some(matrix) => MultiRoomCostResult::CostMatrix(matrix.into()),
+            None => MultiRoomCostResult::Default,
+        })
+        .plain_cost(PLAIN_COST)
+        .swamp_cost(SWAMP_COST);
+        let result = pathfinder::search(from, to, range, Some(options));
+        if result.incomplete() {
+            return None;
+        }
+        Some(result.path())
+    }
+}
+
+pub struct LiveRoom(Room);
+
+impl RoomView for LiveRoom {
+    fn name(&self) -> RoomName {
+        self.0.name()
+    }
+
+    fn energy_available(&self) -> u32 {
+        self.0.energy_available()
+    }
+
+    fn energy_capacity_available(&self) -> u32 {
+        self.0.energy_capacity_available()
+    }
+
+    fn structures(&self) -> Vec<StructureInfo> {
+        self.0
+            .find(find::STRUCTURES, None)
+            .iter()
+            .map(structure_info)
+            .collect()
+    }
+
+    fn sources(&self) -> Vec<SourceInfo> {
+        self.0
+            .find(find::SOURCES, None)
+            .iter()
+            .map(source_info)
+            .collect()
+    }
+
+    fn construction_sites(&self) -> Vec<SiteInfo> {
+        self.0
+            .find(find::MY_CONSTRUCTION_SITES, None)
+            .iter()
+            .map(site_info)
+            .collect()
+    }
+
+    fn dropped_resources(&self) -> Vec<DroppedInfo> {
+        self.0
+            .find(find::DROPPED_RESOURCES, None)
+            .iter()
+            .map(dropped_info)
+            .collect()
+    }
+
+    fn remains(&self) -> Vec<RemainsInfo> {
+        let tombstones = self
+            .0
+            .find(find::TOMBSTONES, None)
+            .into_iter()
+            .map(RoomObject::from);
+        let ruins = self
+            .0
+            .find(find::RUINS, None)
+            .into_iter()
+            .map(RoomObject::from);
+        tombstones
+            .chain(ruins)
+            .filter_map(|object| remains_info(&object))
+            .collect()
+    }
+
+    fn controller(&self) -> Option<ControllerInfo> {
+        self.0.controller().map(|controller| ControllerInfo {
+            id: controller.id(),
+            pos: controller.pos(),
+            my: controller.my(),
+            level: controller.level(),
+            progress: controller.progress().unwrap_or(0),
+            progress_total: controller.progress_total().unwrap_or(0),
+            safe_mode: controller.safe_mode(),
+            safe_mode_available: controller.safe_mode_available(),
+            safe_mode_cooldown: controller.safe_mode_cooldown(),
+        })
+    }
+
+    fn terrain(&self) -> LocalRoomTerrain {
+        LocalRoomTerrain::from(self.0.get_terrain())
+    }
+
+    fn hostile_creeps(&self) -> Vec<HostileInfo> {
+        self.0
+            .find(find::HOSTILE_CREEPS, None)
+            .iter()
+            .map(hostile_info)
+            .collect()
+    }
+
+    fn towers(&self) -> Vec<Rc<dyn TowerView>> {
+        self.0
+            .find(find::MY_STRUCTURES, None)
+            .into_iter()
+            .filter_map(|structure| match structure {
+                StructureObject::StructureTower(tower) => {
+                    Some(Rc::new(LiveTower(tower)) as Rc<dyn TowerView>)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn links(&self) -> Vec<Rc<dyn LinkView>> {
+        self.0
+            .find(find::MY_STRUCTURES, None)
+            .into_iter()
+            .filter_map(|structure| match structure {
+                StructureObject::StructureLink(link) => {
+                    Some(Rc::new(LiveLink(link)) as Rc<dyn LinkView>)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn activate_safe_mode(&self) -> ActionResult {
+        let controller = self.0.controller().ok_or(ErrorCode::InvalidTarget)?;
+        controller.activate_safe_mode().map_err(ErrorCode::from)
+    }
+
+    fn memory(&self) -> RoomMemory {
+        self.0.memory().into()
+    }
+
+    fn set_memory(&self, memory: RoomMemory) {
+        self.0.set_memory(&memory.into());
+    }
+
+    fn create_construction_site(&self, xy: RoomXY, structure_type: StructureType) -> ActionResult {
+        self.0
+            .create_construction_site(xy.x.u8(), xy.y.u8(), structure_type, None)
+            .map_err(ErrorCode::from)
+    }
+}
+
+pub struct LiveCreep(Creep);
+
+impl CreepView for LiveCreep {
+    fn id(&self) -> ObjectId<Creep> {
+        // Our own creeps always have an id, even while spawning
+        self.0.try_id().expect("creep has no id")
+    }
+
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn pos(&self) -> Position {
+        self.0.pos()
+    }
+
+    fn body(&self) -> Vec<Part> {
+        self.0.body().iter().map(|part| part.part()).collect()
+    }
+
+    fn hits(&self) -> u32 {
+        self.0.hits()
+    }
+
+    fn hits_max(&self) -> u32 {
+        self.0.hits_max()
+    }
+
+    fn fatigue(&self) -> u32 {
+        self.0.fatigue()
+    }
+
+    fn ticks_to_live(&self) -> Option<u32> {
+        self.0.ticks_to_live()
+    }
+
+    fn store(&self) -> StoreInfo {
+        store_info(&self.0.store(), None)
+    }
+
+    fn memory(&self) -> CreepMemory {
+        self.0.memory().into()
+    }
+
+    fn set_memory(&self, memory: CreepMemory) {
+        self.0.set_memory(&memory.into());
+    }
+
+    fn say(&self, message: &str) {
+        let _ = self.0.say(message, false);
+    }
+
+    fn move_direction(&self, direction: Direction) -> ActionResult {
+        self.0.move_direction(direction).map_err(ErrorCode::from)
+    }
+
+    fn harvest(&self, source: ObjectId<Source>) -> ActionResult {
+        let source = source.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.harvest(&source).map_err(ErrorCode::from)
+    }
+
+    fn transfer(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let structure = resolve_structure(target).ok_or(ErrorCode::InvalidTarget)?;
+        let target = structure
+            .as_transferable()
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.0
+            .transfer(target, resource, amount)
+            .map_err(ErrorCode::from)
+    }
+
+    fn withdraw(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let object = game::get_object_by_id_erased(&target).ok_or(ErrorCode::InvalidTarget)?;
+        let result = if let Some(tombstone) = object.dyn_ref::<Tombstone>() {
+            self.0.withdraw(tombstone, resource, amount)
+        } else if let Some(ruin) = object.dyn_ref::<Ruin>() {
+            self.0.withdraw(ruin, resource, amount)
+        } else {
+            let structure = resolve_structure(target).ok_or(ErrorCode::InvalidTarget)?;
+            let target = structure
+                .as_withdrawable()
+                .ok_or(ErrorCode::InvalidTarget)?;
+            self.0.withdraw(target, resource, amount)
+        };
+        result.map_err(ErrorCode::from)
+    }
+
+    fn pickup(&self, resource: ObjectId<Resource>) -> ActionResult {
+        let resource = resource.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.pickup(&resource).map_err(ErrorCode::from)
+    }
+
+    fn build(&self, site: ObjectId<ConstructionSite>) -> ActionResult {
+        let site = site.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.build(&site).map_err(ErrorCode::from)
+    }
+
+    fn repair(&self, structure: RawObjectId) -> ActionResult {
+        let structure = resolve_structure(structure).ok_or(ErrorCode::InvalidTarget)?;
+        let target = structure.as_repairable().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.repair(target).map_err(ErrorCode::from)
+    }
+
+    fn upgrade_controller(&self, controller: ObjectId<StructureController>) -> ActionResult {
+        let controller = controller.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0
+            .upgrade_controller(&controller)
+            .map_err(ErrorCode::from)
+    }
+
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        let target = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.attack(&target).map_err(ErrorCode::from)
+    }
+
+    fn ranged_attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        let target = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.ranged_attack(&target).map_err(ErrorCode::from)
+    }
+
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult {
+        let target = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.heal(&target).map_err(ErrorCode::from)
+    }
+}
+
+pub struct LiveSpawn(StructureSpawn);
+
+impl SpawnView for LiveSpawn {
+    fn name(&self) -> String {
+        self.0.name()
+    }
+
+    fn room_name(&self) -> RoomName {
+        self.0.pos().room_name()
+    }
+
+    fn pos(&self) -> Position {
+        self.0.pos()
+    }
+
+    fn is_spawning(&self) -> bool {
+        self.0.spawning().is_some()
+    }
+
+    fn spawn_creep(&self, body: &[Part], name: &str, memory: CreepMemory) -> ActionResult {
+        let options = SpawnOptions::new().memory(memory.into());
+        self.0
+            .spawn_creep_with_options(body, name, &options)
+            .map_err(ErrorCode::from)
+    }
+
+    fn recycle_creep(&self, creep: ObjectId<Creep>) -> ActionResult {
+        let creep = creep.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.recycle_creep(&creep).map_err(ErrorCode::from)
+    }
+}
+
+pub struct LiveTower(StructureTower);
+
+impl TowerView for LiveTower {
+    fn id(&self) -> ObjectId<StructureTower> {
+        self.0.id()
+    }
+
+    fn pos(&self) -> Position {
+        self.0.pos()
+    }
+
+    fn store(&self) -> StoreInfo {
+        store_info(&self.0.store(), Some(ResourceType::Energy))
+    }
+
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        let creep = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.attack(&creep).map_err(ErrorCode::from)
+    }
+
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult {
+        let creep = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.heal(&creep).map_err(ErrorCode::from)
+    }
+
+    fn repair(&self, target: RawObjectId) -> ActionResult {
+        let structure = resolve_structure(target).ok_or(ErrorCode::InvalidTarget)?;
+        let target = structure.as_repairable().ok_or(ErrorCode::InvalidTarget)?;
+        self.0.repair(target).map_err(ErrorCode::from)
+    }
+}
+
+pub struct LiveLink(StructureLink);
+
+impl LinkView for LiveLink {
+    fn id(&self) -> ObjectId<StructureLink> {
+        self.0.id()
+    }
+
+    fn pos(&self) -> Position {
+        self.0.pos()
+    }
+
+    fn store(&self) -> StoreInfo {
+        store_info(&self.0.store(), Some(ResourceType::Energy))
+    }
+
+    fn cooldown(&self) -> u32 {
+        self.0.cooldown()
+    }
+
+    fn transfer_energy(
+        &self,
+        target: ObjectId<StructureLink>,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let link = target.resolve().ok_or(ErrorCode::InvalidTarget)?;
+        self.0
+            .transfer_energy(&link, amount)
+            .map_err(ErrorCode::from)
+    }
+}
+
+/// Resolve a raw id into any kind of structure, or None if it is something else
+fn resolve_structure(id: RawObjectId) -> Option<StructureObject> {
+    game::get_object_by_id_erased(&id)?
+        .dyn_into::<Structure>()
+        .ok()
+        .map(StructureObject::from)
+}
+
+fn store_info(store: &Store, restricted_to: Option<ResourceType>) -> StoreInfo {
+    StoreInfo {
+        contents: store
+            .store_types()
+            .into_iter()
+            .map(|ty| (ty, store.get_used_capacity(Some(ty))))
+            .collect(),
+        capacity: store.get_capacity(restricted_to),
+        restricted_to,
+    }
+}
+
+fn structure_info(structure: &StructureObject) -> StructureInfo {
+    let structure_type = structure.structure_type();
+    // These structures can only ever hold energy
+    let restricted_to = match structure_type {
+        StructureType::Spawn
+        | StructureType::Extension
+        | StructureType::Tower
+        | StructureType::Link => Some(ResourceType::Energy),
+        _ => None,
+    };
+    StructureInfo {
+        id: structure.as_structure().raw_id(),
+        structure_type,
+        pos: structure.pos(),
+        hits: structure.as_structure().hits(),
+        hits_max: structure.as_structure().hits_max(),
+        store: structure
+            .as_has_store()
+            .map(|has_store| store_info(&has_store.store(), restricted_to)),
+    }
+}
+
+fn dropped_info(resource: &Resource) -> DroppedInfo {
+    DroppedInfo {
+        id: resource.id(),
+        pos: resource.pos(),
+        resource_type: resource.resource_type(),
+        amount: resource.amount(),
+    }
+}
+
+/// Snapshot of a tombstone or ruin, or None if the object is something else
+fn remains_info(object: &RoomObject) -> Option<RemainsInfo> {
+    if let Some(tombstone) = object.dyn_ref::<Tombstone>() {
+        return Some(RemainsInfo {
+            id: tombstone.id().into(),
+            kind: RemainsKind::Tombstone,
+            pos: tombstone.pos(),
+            store: store_info(&tombstone.store(), None),
+            ticks_to_decay: tombstone.ticks_to_decay(),
+        });
+    }
+    let ruin = object.dyn_ref::<Ruin>()?;
+    Some(RemainsInfo {
+        id: ruin.id().into(),
+        kind: RemainsKind::Ruin,
+        pos: ruin.pos(),
+        store: store_info(&ruin.store(), None),
+        ticks_to_decay: ruin.ticks_to_decay(),
+    })
+}
+
+fn source_info(source: &Source) -> SourceInfo {
+    SourceInfo {
+        id: source.id(),
+        pos: source.pos(),
+        energy: source.energy(),
+        energy_capacity: source.energy_capacity(),
+        ticks_to_regeneration: source.ticks_to_regeneration(),
+    }
+}
+
+fn hostile_info(creep: &Creep) -> HostileInfo {
+    HostileInfo {
+        // Creeps we can see always have an id
+        id: creep.try_id().expect("hostile creep has no id"),
+        pos: creep.pos(),
+        owner: creep.owner().username(),
+        hits: creep.hits(),
+        hits_max: creep.hits_max(),
+        body: creep
+            .body()
+            .iter()
+            .map(|part| BodyPartInfo {
+                part: part.part(),
+                hits: part.hits(),
+                boost: part.boost(),
+            })
+            .collect(),
+    }
+}
+
+fn site_info(site: &ConstructionSite) -> SiteInfo {
+    SiteInfo {
+        // Our own construction sites are always visible, so always have an id
+        id: site.try_id().expect("construction site has no id"),
+        structure_type: site.structure_type(),
+        pos: site.pos(),
+        progress: site.progress(),
+        progress_total: site.progress_total(),
+    }
+}
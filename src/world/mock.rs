@@ -0,0 +1,1193 @@
+// In-memory implementation of the world, for running the bot's decision logic natively.
+// Actions are checked the same way the game checks them (range, resources, capacity) and
+// recorded as intents, but never change the world itself.
+use super::search::search_room;
+use super::{
+    ActionResult, BodyPartInfo, ControllerInfo, CreepView, DroppedInfo, HostileInfo, LinkView,
+    RemainsInfo, RemainsKind, RoomView, SiteInfo, SourceInfo, SpawnView, StoreInfo, StructureInfo,
+    TowerView, World,
+};
+use crate::planner::RoomMemory;
+use crate::screep_states::CreepMemory;
+use screeps::constants::{controller_levels, CREEP_LIFE_TIME, MAX_CONSTRUCTION_SITES, ROOM_AREA};
+use screeps::{
+    ConstructionSite, Creep, Direction, ErrorCode, LocalCostMatrix, LocalRoomTerrain, ObjectId,
+    Part, Position, RawObjectId, Resource, ResourceType, RoomName, RoomXY, Source,
+    StructureController, StructureLink, StructureTower, StructureType, Terrain,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Position in a room from plain coordinates, panics if they are out of bounds
+pub fn position(x: u8, y: u8, room: RoomName) -> Position {
+    let xy = RoomXY::checked_new(x, y).expect("position in room bounds");
+    Position::new(xy.x, xy.y, room)
+}
+
+/// The room tests are set in, unless they need more than one
+pub fn test_room() -> RoomName {
+    RoomName::new("W1N1").expect("valid room name")
+}
+
+/// Terrain with walls around the edges and plain everywhere else, so creeps can't leave the room
+pub fn plain_terrain() -> LocalRoomTerrain {
+    let mut bits = Box::new([0; ROOM_AREA]);
+    for (index, bit) in bits.iter_mut().enumerate() {
+        let (x, y) = (index % 50, index / 50);
+        if x == 0 || y == 0 || x == 49 || y == 49 {
+            *bit = 1;
+        }
+    }
+    LocalRoomTerrain::new_from_bits(bits)
+}
+
+/// Build terrain from 50 rows of 50 characters, where `#` is a wall, `~` is a swamp and anything
+/// else is plain
+pub fn terrain_from_rows(rows: &[&str]) -> LocalRoomTerrain {
+    let mut bits = Box::new([0; ROOM_AREA]);
+    for (y, row) in rows.iter().take(50).enumerate() {
+        for (x, tile) in row.chars().take(50).enumerate() {
+            bits[y * 50 + x] = match tile {
+                '#' => 1,
+                '~' => 2,
+                _ => 0,
+            };
+        }
+    }
+    LocalRoomTerrain::new_from_bits(bits)
+}
+
+/// An action issued through one of the mock views
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intent {
+    Say {
+        creep: String,
+        message: String,
+    },
+    MoveDirection {
+        creep: String,
+        direction: Direction,
+    },
+    Harvest {
+        creep: String,
+        source: ObjectId<Source>,
+    },
+    Transfer {
+        creep: String,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    },
+    Withdraw {
+        creep: String,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    },
+    Pickup {
+        creep: String,
+        resource: ObjectId<Resource>,
+    },
+    Build {
+        creep: String,
+        site: ObjectId<ConstructionSite>,
+    },
+    Repair {
+        creep: String,
+        structure: RawObjectId,
+    },
+    UpgradeController {
+        creep: String,
+        controller: ObjectId<StructureController>,
+    },
+    SpawnCreep {
+        spawn: String,
+        body: Vec<Part>,
+        name: String,
+        memory: CreepMemory,
+    },
+    Attack {
+        creep: String,
+        target: ObjectId<Creep>,
+    },
+    RangedAttack {
+        creep: String,
+        target: ObjectId<Creep>,
+    },
+    Heal {
+        creep: String,
+        target: ObjectId<Creep>,
+    },
+    RecycleCreep {
+        spawn: String,
+        creep: ObjectId<Creep>,
+    },
+    TowerAttack {
+        tower: ObjectId<StructureTower>,
+        target: ObjectId<Creep>,
+    },
+    TowerHeal {
+        tower: ObjectId<StructureTower>,
+        target: ObjectId<Creep>,
+    },
+    TowerRepair {
+        tower: ObjectId<StructureTower>,
+        target: RawObjectId,
+    },
+    LinkTransfer {
+        link: ObjectId<StructureLink>,
+        target: ObjectId<StructureLink>,
+        amount: Option<u32>,
+    },
+    ActivateSafeMode {
+        controller: ObjectId<StructureController>,
+    },
+    CreateConstructionSite {
+        pos: Position,
+        structure_type: StructureType,
+    },
+    Notify {
+        message: String,
+    },
+}
+
+/// Contents of a mock room
+#[derive(Debug, Clone)]
+pub struct MockRoom {
+    pub name: RoomName,
+    pub energy_available: u32,
+    pub energy_capacity_available: u32,
+    pub structures: Vec<StructureInfo>,
+    pub sources: Vec<SourceInfo>,
+    pub construction_sites: Vec<SiteInfo>,
+    pub dropped: Vec<DroppedInfo>,
+    pub remains: Vec<RemainsInfo>,
+    pub controller: Option<ControllerInfo>,
+    pub hostiles: Vec<HostileInfo>,
+    // All plain unless set
+    pub terrain: LocalRoomTerrain,
+    pub memory: RefCell<RoomMemory>,
+    // Ticks until structures with a cooldown (e.g. links) can act again, missing if they can now
+    pub cooldowns: HashMap<RawObjectId, u32>,
+}
+
+impl MockRoom {
+    pub fn new(name: RoomName) -> Self {
+        MockRoom {
+            name,
+            energy_available: 0,
+            energy_capacity_available: 0,
+            structures: vec![],
+            sources: vec![],
+            construction_sites: vec![],
+            dropped: vec![],
+            remains: vec![],
+            controller: None,
+            hostiles: vec![],
+            terrain: LocalRoomTerrain::new_from_bits(Box::new([0; ROOM_AREA])),
+            memory: RefCell::new(RoomMemory::default()),
+            cooldowns: HashMap::new(),
+        }
+    }
+}
+
+/// A mock creep
+#[derive(Debug, Clone)]
+pub struct MockCreep {
+    pub id: ObjectId<Creep>,
+    pub name: String,
+    pub pos: Position,
+    pub body: Vec<Part>,
+    pub store: StoreInfo,
+    pub memory: RefCell<CreepMemory>,
+    pub hits: u32,
+    pub hits_max: u32,
+    pub fatigue: u32,
+    pub ticks_to_live: u32,
+}
+
+/// A mock spawn. The spawn structure itself lives in the room's structures
+#[derive(Debug, Clone)]
+pub struct MockSpawn {
+    pub name: String,
+    pub pos: Position,
+    pub spawning: bool,
+}
+
+/// In-memory world, see the module docs
+#[derive(Debug, Default)]
+pub struct MockWorld {
+    pub time: u32,
+    pub rooms: Vec<MockRoom>,
+    pub creeps: Vec<MockCreep>,
+    pub spawns: Vec<MockSpawn>,
+    intents: RefCell<Vec<Intent>>,
+    last_id: Cell<u128>,
+}
+
+impl MockWorld {
+    pub fn new() -> Self {
+        MockWorld::default()
+    }
+
+    /// Generate a new unique object id
+    pub fn next_id(&self) -> RawObjectId {
+        self.last_id.set(self.last_id.get() + 1);
+        format!("{:024x}", self.last_id.get())
+            .parse()
+            .expect("generated object id is valid")
+    }
+
+    /// Add an empty room, returning it so it can be filled in
+    pub fn add_room(&mut self, name: RoomName) -> &mut MockRoom {
+        self.rooms.push(MockRoom::new(name));
+        self.rooms.last_mut().expect("room was just added")
+    }
+
+    /// Add a structure to the room at the given position, returning its id
+    pub fn add_structure(
+        &mut self,
+        structure_type: StructureType,
+        pos: Position,
+        store: Option<StoreInfo>,
+    ) -> RawObjectId {
+        let id = self.next_id();
+        let room = self.room_mut(pos.room_name());
+        room.structures.push(StructureInfo {
+            id,
+            structure_type,
+            pos,
+            hits: 1000,
+            hits_max: 1000,
+            store,
+        });
+        id
+    }
+
+    /// Add a full source to the room at the given position, returning its id
+    pub fn add_source(&mut self, pos: Position) -> ObjectId<Source> {
+        let id = self.next_id().into();
+        let room = self.room_mut(pos.room_name());
+        room.sources.push(SourceInfo {
+            id,
+            pos,
+            energy: 3000,
+            energy_capacity: 3000,
+            ticks_to_regeneration: None,
+        });
+        id
+    }
+
+    /// Drop a pile of resources at the given position, returning its id
+    pub fn add_dropped(
+        &mut self,
+        pos: Position,
+        resource_type: ResourceType,
+        amount: u32,
+    ) -> ObjectId<Resource> {
+        let id = self.next_id().into();
+        let room = self.room_mut(pos.room_name());
+        room.dropped.push(DroppedInfo {
+            id,
+            pos,
+            resource_type,
+            amount,
+        });
+        id
+    }
+
+    /// Add a tombstone or ruin holding the given store, returning its id
+    pub fn add_remains(
+        &mut self,
+        kind: RemainsKind,
+        pos: Position,
+        store: StoreInfo,
+        ticks_to_decay: u32,
+    ) -> RawObjectId {
+        let id = self.next_id();
+        let room = self.room_mut(pos.room_name());
+        room.remains.push(RemainsInfo {
+            id,
+            kind,
+            pos,
+            store,
+            ticks_to_decay,
+        });
+        id
+    }
+
+    /// Add a construction site to the room at the given position, returning its id
+    pub fn add_construction_site(
+        &mut self,
+        structure_type: StructureType,
+        pos: Position,
+    ) -> ObjectId<ConstructionSite> {
+        let id = self.next_id().into();
+        let room = self.room_mut(pos.room_name());
+        room.construction_sites.push(SiteInfo {
+            id,
+            structure_type,
+            pos,
+            progress: 0,
+            progress_total: 1000,
+        });
+        id
+    }
+
+    /// Add an owned controller at the given level, returning its id
+    pub fn add_controller(&mut self, pos: Position, level: u8) -> ObjectId<StructureController> {
+        let id = self.next_id();
+        let room = self.room_mut(pos.room_name());
+        room.controller = Some(ControllerInfo {
+            id: id.into(),
+            pos,
+            my: true,
+            level,
+            progress: 0,
+            progress_total: controller_levels(level as u32).unwrap_or(0),
+            safe_mode: None,
+            safe_mode_available: level as u32,
+            safe_mode_cooldown: None,
+        });
+        room.structures.push(StructureInfo {
+            id,
+            structure_type: StructureType::Controller,
+            pos,
+            hits: 0,
+            hits_max: 0,
+            store: None,
+        });
+        id.into()
+    }
+
+    /// Add a spawn with a full energy store, returning its id
+    pub fn add_spawn(&mut self, name: &str, pos: Position) -> RawObjectId {
+        let store =
+            StoreInfo::restricted(300, ResourceType::Energy).with(ResourceType::Energy, 300);
+        let id = self.add_structure(StructureType::Spawn, pos, Some(store));
+        self.spawns.push(MockSpawn {
+            name: name.to_string(),
+            pos,
+            spawning: false,
+        });
+        id
+    }
+
+    /// Add a creep with the given body and memory, returning its id
+    pub fn add_creep(
+        &mut self,
+        name: &str,
+        pos: Position,
+        body: Vec<Part>,
+        memory: CreepMemory,
+    ) -> ObjectId<Creep> {
+        let id = self.next_id().into();
+        let capacity = body.iter().filter(|part| **part == Part::Carry).count() as u32 * 50;
+        let hits = body.len() as u32 * 100;
+        self.creeps.push(MockCreep {
+            id,
+            name: name.to_string(),
+            pos,
+            body,
+            store: StoreInfo::new(capacity),
+            memory: RefCell::new(memory),
+            hits,
+            hits_max: hits,
+            fatigue: 0,
+            ticks_to_live: CREEP_LIFE_TIME,
+        });
+        id
+    }
+
+    /// Add a creep owned by another player, returning its id
+    pub fn add_hostile(&mut self, owner: &str, pos: Position, body: Vec<Part>) -> ObjectId<Creep> {
+        let id = self.next_id().into();
+        let hits = body.len() as u32 * 100;
+        let room = self.room_mut(pos.room_name());
+        room.hostiles.push(HostileInfo {
+            id,
+            pos,
+            owner: owner.to_string(),
+            hits,
+            hits_max: hits,
+            body: body
+                .into_iter()
+                .map(|part| BodyPartInfo {
+                    part,
+                    hits: 100,
+                    boost: None,
+                })
+                .collect(),
+        });
+        id
+    }
+
+    /// Get a mutable reference to a creep by name
+    pub fn creep_mut(&mut self, name: &str) -> &mut MockCreep {
+        self.creeps
+            .iter_mut()
+            .find(|creep| creep.name == name)
+            .expect("no mock creep with that name")
+    }
+
+    /// Get a mutable reference to a room by name
+    pub fn room_mut(&mut self, name: RoomName) -> &mut MockRoom {
+        self.rooms
+            .iter_mut()
+            .find(|room| room.name == name)
+            .expect("no mock room with that name, add it first")
+    }
+
+    /// Take all intents recorded since the last call
+    pub fn take_intents(&self) -> Vec<Intent> {
+        self.intents.take()
+    }
+
+    fn record(&self, intent: Intent) {
+        self.intents.borrow_mut().push(intent);
+    }
+
+    fn find_controller(&self, id: ObjectId<StructureController>) -> Option<&ControllerInfo> {
+        self.rooms
+            .iter()
+            .filter_map(|room| room.controller.as_ref())
+            .find(|controller| controller.id == id)
+    }
+}
+
+impl World for MockWorld {
+    fn time(&self) -> u32 {
+        self.time
+    }
+
+    fn creeps(&self) -> Vec<Rc<dyn CreepView + '_>> {
+        self.creeps
+            .iter()
+            .map(|creep| Rc::new(MockCreepView { world: self, creep }) as Rc<dyn CreepView + '_>)
+            .collect()
+    }
+
+    fn rooms(&self) -> Vec<Rc<dyn RoomView + '_>> {
+        self.rooms
+            .iter()
+            .map(|room| Rc::new(MockRoomView { world: self, room }) as Rc<dyn RoomView + '_>)
+            .collect()
+    }
+
+    fn room(&self, name: RoomName) -> Option<Rc<dyn RoomView + '_>> {
+        self.rooms
+            .iter()
+            .find(|room| room.name == name)
+            .map(|room| Rc::new(MockRoomView { world: self, room }) as Rc<dyn RoomView + '_>)
+    }
+
+    fn spawns(&self) -> Vec<Rc<dyn SpawnView + '_>> {
+        self.spawns
+            .iter()
+            .map(|spawn| Rc::new(MockSpawnView { world: self, spawn }) as Rc<dyn SpawnView + '_>)
+            .collect()
+    }
+
+    fn source(&self, id: ObjectId<Source>) -> Option<SourceInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.sources.iter())
+            .find(|source| source.id == id)
+            .cloned()
+    }
+
+    fn structure(&self, id: RawObjectId) -> Option<StructureInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.structures.iter())
+            .find(|structure| structure.id == id)
+            .cloned()
+    }
+
+    fn construction_site(&self, id: ObjectId<ConstructionSite>) -> Option<SiteInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.construction_sites.iter())
+            .find(|site| site.id == id)
+            .cloned()
+    }
+
+    fn dropped_resource(&self, id: ObjectId<Resource>) -> Option<DroppedInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.dropped.iter())
+            .find(|dropped| dropped.id == id)
+            .cloned()
+    }
+
+    fn remains(&self, id: RawObjectId) -> Option<RemainsInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.remains.iter())
+            .find(|remains| remains.id == id)
+            .cloned()
+    }
+
+    fn hostile(&self, id: ObjectId<Creep>) -> Option<HostileInfo> {
+        self.rooms
+            .iter()
+            .flat_map(|room| room.hostiles.iter())
+            .find(|hostile| hostile.id == id)
+            .cloned()
+    }
+
+    fn notify(&self, message: &str) {
+        self.record(Intent::Notify {
+            message: message.to_string(),
+        });
+    }
+
+    fn find_path(
+        &self,
+        from: Position,
+        to: Position,
+        range: u32,
+        matrix: &mut dyn FnMut(RoomName) -> Option<LocalCostMatrix>,
+    ) -> Option<Vec<Position>> {
+        let room = self
+            .rooms
+            .iter()
+            .find(|room| room.name == from.room_name())?;
+        search_room(from, to, range, &room.terrain, matrix(room.name).as_ref())
+    }
+}
+
+struct MockRoomView<'a> {
+    world: &'a MockWorld,
+    room: &'a MockRoom,
+}
+
+impl RoomView for MockRoomView<'_> {
+    fn name(&self) -> RoomName {
+        self.room.name
+    }
+
+    fn energy_available(&self) -> u32 {
+        self.room.energy_available
+    }
+
+    fn energy_capacity_available(&self) -> u32 {
+        self.room.energy_capacity_available
+    }
+
+    fn structures(&self) -> Vec<StructureInfo> {
+        self.room.structures.clone()
+    }
+
+    fn sources(&self) -> Vec<SourceInfo> {
+        self.room.sources.clone()
+    }
+
+    fn construction_sites(&self) -> Vec<SiteInfo> {
+        self.room.construction_sites.clone()
+    }
+
+    fn dropped_resources(&self) -> Vec<DroppedInfo> {
+        self.room.dropped.clone()
+    }
+
+    fn remains(&self) -> Vec<RemainsInfo> {
+        self.room.remains.clone()
+    }
+
+    fn controller(&self) -> Option<ControllerInfo> {
+        self.room.controller.clone()
+    }
+
+    fn hostile_creeps(&self) -> Vec<HostileInfo> {
+        self.room.hostiles.clone()
+    }
+
+    fn terrain(&self) -> LocalRoomTerrain {
+        self.room.terrain.clone()
+    }
+
+    fn towers(&self) -> Vec<Rc<dyn TowerView + '_>> {
+        self.room
+            .structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Tower)
+            .map(|tower| {
+                Rc::new(MockTowerView {
+                    world: self.world,
+                    tower,
+                }) as Rc<dyn TowerView + '_>
+            })
+            .collect()
+    }
+
+    fn links(&self) -> Vec<Rc<dyn LinkView + '_>> {
+        self.room
+            .structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Link)
+            .map(|link| {
+                Rc::new(MockLinkView {
+                    world: self.world,
+                    room: self.room,
+                    link,
+                }) as Rc<dyn LinkView + '_>
+            })
+            .collect()
+    }
+
+    fn activate_safe_mode(&self) -> ActionResult {
+        let controller = self
+            .room
+            .controller
+            .as_ref()
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if !controller.my {
+            return Err(ErrorCode::NotOwner);
+        }
+        if controller.safe_mode_available == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        // Only one room can be in safe mode at a time
+        let any_active = self
+            .world
+            .rooms
+            .iter()
+            .filter_map(|room| room.controller.as_ref())
+            .any(|controller| controller.safe_mode.is_some());
+        if any_active {
+            return Err(ErrorCode::Busy);
+        }
+        if controller.safe_mode_cooldown.is_some() {
+            return Err(ErrorCode::Tired);
+        }
+        self.world.record(Intent::ActivateSafeMode {
+            controller: controller.id,
+        });
+        Ok(())
+    }
+
+    fn memory(&self) -> RoomMemory {
+        self.room.memory.borrow().clone()
+    }
+
+    fn set_memory(&self, memory: RoomMemory) {
+        self.room.memory.replace(memory);
+    }
+
+    fn create_construction_site(&self, xy: RoomXY, structure_type: StructureType) -> ActionResult {
+        let controller = self.room.controller.as_ref();
+        if !controller.is_some_and(|controller| controller.my) {
+            return Err(ErrorCode::NotOwner);
+        }
+        let occupied = self.room.structures.iter().any(|s| s.pos.xy() == xy)
+            || self
+                .room
+                .construction_sites
+                .iter()
+                .any(|s| s.pos.xy() == xy);
+        if occupied || self.room.terrain.get_xy(xy) == Terrain::Wall {
+            return Err(ErrorCode::InvalidTarget);
+        }
+        let level = controller.map_or(0, |controller| controller.level);
+        let existing = self
+            .room
+            .structures
+            .iter()
+            .map(|s| s.structure_type)
+            .chain(
+                self.room
+                    .construction_sites
+                    .iter()
+                    .map(|s| s.structure_type),
+            )
+            .filter(|ty| *ty == structure_type)
+            .count() as u32;
+        if existing >= structure_type.controller_structures(level as u32) {
+            return Err(ErrorCode::RclNotEnough);
+        }
+        let sites: usize = self
+            .world
+            .rooms
+            .iter()
+            .map(|room| room.construction_sites.len())
+            .sum();
+        if sites >= MAX_CONSTRUCTION_SITES as usize {
+            return Err(ErrorCode::Full);
+        }
+        self.world.record(Intent::CreateConstructionSite {
+            pos: Position::new(xy.x, xy.y, self.room.name),
+            structure_type,
+        });
+        Ok(())
+    }
+}
+
+struct MockCreepView<'a> {
+    world: &'a MockWorld,
+    creep: &'a MockCreep,
+}
+
+impl MockCreepView<'_> {
+    fn check_range(&self, target: Position, range: u32) -> ActionResult {
+        if self.creep.pos.in_range_to(target, range) {
+            Ok(())
+        } else {
+            Err(ErrorCode::NotInRange)
+        }
+    }
+
+    fn check_part(&self, part: Part) -> ActionResult {
+        if self.creep.body.contains(&part) {
+            Ok(())
+        } else {
+            Err(ErrorCode::NoBodypart)
+        }
+    }
+
+    fn check_energy(&self) -> ActionResult {
+        if self
+            .creep
+            .store
+            .get_used_capacity(Some(ResourceType::Energy))
+            == 0
+        {
+            return Err(ErrorCode::NotEnough);
+        }
+        Ok(())
+    }
+}
+
+impl CreepView for MockCreepView<'_> {
+    fn id(&self) -> ObjectId<Creep> {
+        self.creep.id
+    }
+
+    fn name(&self) -> String {
+        self.creep.name.clone()
+    }
+
+    fn pos(&self) -> Position {
+        self.creep.pos
+    }
+
+    fn body(&self) -> Vec<Part> {
+        self.creep.body.clone()
+    }
+
+    fn hits(&self) -> u32 {
+        self.creep.hits
+    }
+
+    fn hits_max(&self) -> u32 {
+        self.creep.hits_max
+    }
+
+    fn fatigue(&self) -> u32 {
+        self.creep.fatigue
+    }
+
+    fn ticks_to_live(&self) -> Option<u32> {
+        Some(self.creep.ticks_to_live)
+    }
+
+    fn store(&self) -> StoreInfo {
+        self.creep.store.clone()
+    }
+
+    fn memory(&self) -> CreepMemory {
+        self.creep.memory.borrow().clone()
+    }
+
+    fn set_memory(&self, memory: CreepMemory) {
+        self.creep.memory.replace(memory);
+    }
+
+    fn say(&self, message: &str) {
+        self.world.record(Intent::Say {
+            creep: self.name(),
+            message: message.to_string(),
+        });
+    }
+
+    fn move_direction(&self, direction: Direction) -> ActionResult {
+        self.check_part(Part::Move)?;
+        if self.creep.fatigue > 0 {
+            return Err(ErrorCode::Tired);
+        }
+        self.world.record(Intent::MoveDirection {
+            creep: self.name(),
+            direction,
+        });
+        Ok(())
+    }
+
+    fn harvest(&self, source: ObjectId<Source>) -> ActionResult {
+        let info = self.world.source(source).ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(info.pos, 1)?;
+        if info.energy == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        self.world.record(Intent::Harvest {
+            creep: self.name(),
+            source,
+        });
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let structure = self
+            .world
+            .structure(target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        let store = structure.store.ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(structure.pos, 1)?;
+        if self.creep.store.get_used_capacity(Some(resource)) == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if store.get_free_capacity(Some(resource)) <= 0 {
+            return Err(ErrorCode::Full);
+        }
+        self.world.record(Intent::Transfer {
+            creep: self.name(),
+            target,
+            resource,
+            amount,
+        });
+        Ok(())
+    }
+
+    fn withdraw(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let (pos, store) = match self.world.structure(target) {
+            Some(structure) => (
+                structure.pos,
+                structure.store.ok_or(ErrorCode::InvalidTarget)?,
+            ),
+            None => {
+                let remains = self.world.remains(target).ok_or(ErrorCode::InvalidTarget)?;
+                (remains.pos, remains.store)
+            }
+        };
+        self.check_range(pos, 1)?;
+        if store.get_used_capacity(Some(resource)) == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        if self.creep.store.get_free_capacity(Some(resource)) <= 0 {
+            return Err(ErrorCode::Full);
+        }
+        self.world.record(Intent::Withdraw {
+            creep: self.name(),
+            target,
+            resource,
+            amount,
+        });
+        Ok(())
+    }
+
+    fn pickup(&self, resource: ObjectId<Resource>) -> ActionResult {
+        let dropped = self
+            .world
+            .dropped_resource(resource)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(dropped.pos, 1)?;
+        if self
+            .creep
+            .store
+            .get_free_capacity(Some(dropped.resource_type))
+            <= 0
+        {
+            return Err(ErrorCode::Full);
+        }
+        self.world.record(Intent::Pickup {
+            creep: self.name(),
+            resource,
+        });
+        Ok(())
+    }
+
+    fn build(&self, site: ObjectId<ConstructionSite>) -> ActionResult {
+        let info = self
+            .world
+            .construction_site(site)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(info.pos, 3)?;
+        self.check_energy()?;
+        self.world.record(Intent::Build {
+            creep: self.name(),
+            site,
+        });
+        Ok(())
+    }
+
+    fn repair(&self, structure: RawObjectId) -> ActionResult {
+        let info = self
+            .world
+            .structure(structure)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(info.pos, 3)?;
+        self.check_energy()?;
+        self.world.record(Intent::Repair {
+            creep: self.name(),
+            structure,
+        });
+        Ok(())
+    }
+
+    fn upgrade_controller(&self, controller: ObjectId<StructureController>) -> ActionResult {
+        let info = self
+            .world
+            .find_controller(controller)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(info.pos, 3)?;
+        self.check_energy()?;
+        self.world.record(Intent::UpgradeController {
+            creep: self.name(),
+            controller,
+        });
+        Ok(())
+    }
+
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        self.check_part(Part::Attack)?;
+        let hostile = self.world.hostile(target).ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(hostile.pos, 1)?;
+        self.world.record(Intent::Attack {
+            creep: self.name(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn ranged_attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        self.check_part(Part::RangedAttack)?;
+        let hostile = self.world.hostile(target).ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(hostile.pos, 3)?;
+        self.world.record(Intent::RangedAttack {
+            creep: self.name(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult {
+        self.check_part(Part::Heal)?;
+        let creep = self
+            .world
+            .creeps
+            .iter()
+            .find(|creep| creep.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.check_range(creep.pos, 1)?;
+        self.world.record(Intent::Heal {
+            creep: self.name(),
+            target,
+        });
+        Ok(())
+    }
+}
+
+struct MockSpawnView<'a> {
+    world: &'a MockWorld,
+    spawn: &'a MockSpawn,
+}
+
+impl SpawnView for MockSpawnView<'_> {
+    fn name(&self) -> String {
+        self.spawn.name.clone()
+    }
+
+    fn room_name(&self) -> RoomName {
+        self.spawn.pos.room_name()
+    }
+
+    fn pos(&self) -> Position {
+        self.spawn.pos
+    }
+
+    fn is_spawning(&self) -> bool {
+        self.spawn.spawning
+    }
+
+    fn spawn_creep(&self, body: &[Part], name: &str, memory: CreepMemory) -> ActionResult {
+        if self.is_spawning() {
+            return Err(ErrorCode::Busy);
+        }
+        if body.is_empty() || body.len() > 50 {
+            return Err(ErrorCode::InvalidArgs);
+        }
+        if self.world.creeps.iter().any(|creep| creep.name == name) {
+            return Err(ErrorCode::NameExists);
+        }
+        let room = self
+            .world
+            .rooms
+            .iter()
+            .find(|room| room.name == self.room_name())
+            .ok_or(ErrorCode::NotFound)?;
+        if room.energy_available < body.iter().map(|part| part.cost()).sum() {
+            return Err(ErrorCode::NotEnough);
+        }
+        self.world.record(Intent::SpawnCreep {
+            spawn: self.name(),
+            body: body.to_vec(),
+            name: name.to_string(),
+            memory,
+        });
+        Ok(())
+    }
+
+    fn recycle_creep(&self, creep: ObjectId<Creep>) -> ActionResult {
+        let target = self
+            .world
+            .creeps
+            .iter()
+            .find(|target| target.id == creep)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if !self.spawn.pos.is_near_to(target.pos) {
+            return Err(ErrorCode::NotInRange);
+        }
+        self.world.record(Intent::RecycleCreep {
+            spawn: self.name(),
+            creep,
+        });
+        Ok(())
+    }
+}
+
+struct MockTowerView<'a> {
+    world: &'a MockWorld,
+    tower: &'a StructureInfo,
+}
+
+impl MockTowerView<'_> {
+    fn check_energy(&self) -> ActionResult {
+        if self.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
+            return Err(ErrorCode::NotEnough);
+        }
+        Ok(())
+    }
+}
+
+impl TowerView for MockTowerView<'_> {
+    fn id(&self) -> ObjectId<StructureTower> {
+        self.tower.id.into()
+    }
+
+    fn pos(&self) -> Position {
+        self.tower.pos
+    }
+
+    fn store(&self) -> StoreInfo {
+        self.tower.store.clone().unwrap_or_default()
+    }
+
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult {
+        self.check_energy()?;
+        self.world
+            .rooms
+            .iter()
+            .flat_map(|room| room.hostiles.iter())
+            .find(|hostile| hostile.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.world.record(Intent::TowerAttack {
+            tower: self.id(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult {
+        self.check_energy()?;
+        self.world
+            .creeps
+            .iter()
+            .find(|creep| creep.id == target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.world.record(Intent::TowerHeal {
+            tower: self.id(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn repair(&self, target: RawObjectId) -> ActionResult {
+        self.check_energy()?;
+        self.world
+            .structure(target)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        self.world.record(Intent::TowerRepair {
+            tower: self.id(),
+            target,
+        });
+        Ok(())
+    }
+}
+
+struct MockLinkView<'a> {
+    world: &'a MockWorld,
+    room: &'a MockRoom,
+    link: &'a StructureInfo,
+}
+
+impl LinkView for MockLinkView<'_> {
+    fn id(&self) -> ObjectId<StructureLink> {
+        self.link.id.into()
+    }
+
+    fn pos(&self) -> Position {
+        self.link.pos
+    }
+
+    fn store(&self) -> StoreInfo {
+        self.link.store()
+    }
+
+    fn cooldown(&self) -> u32 {
+        self.room.cooldowns.get(&self.link.id).copied().unwrap_or(0)
+    }
+
+    fn transfer_energy(
+        &self,
+        target: ObjectId<StructureLink>,
+        amount: Option<u32>,
+    ) -> ActionResult {
+        let target_link = self
+            .room
+            .structures
+            .iter()
+            .find(|structure| structure.id == RawObjectId::from(target))
+            .filter(|structure| structure.structure_type == StructureType::Link)
+            .ok_or(ErrorCode::InvalidTarget)?;
+        if self.cooldown() > 0 {
+            return Err(ErrorCode::Tired);
+        }
+        let energy = self.store().get_used_capacity(Some(ResourceType::Energy));
+        if energy == 0 || amount.is_some_and(|amount| amount > energy) {
+            return Err(ErrorCode::NotEnough);
+        }
+        if target_link
+            .store()
+            .get_free_capacity(Some(ResourceType::Energy))
+            <= 0
+        {
+            return Err(ErrorCode::Full);
+        }
+        self.world.record(Intent::LinkTransfer {
+            link: self.id(),
+            target,
+            amount,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+// Abstraction over the game world, so the decision logic in the state controllers and states
+// doesn't call into the game API directly and can be run natively against the mock
+pub use info::{
+    BodyPartInfo, ControllerInfo, DroppedInfo, HostileInfo, RemainsInfo, RemainsKind, SiteInfo,
+    SourceInfo, StoreInfo, StructureInfo,
+};
+pub use live::LiveWorld;
+#[cfg(test)]
+pub use mock::{plain_terrain, position, terrain_from_rows, test_room, Intent, MockWorld};
+
+mod info;
+mod live;
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod search;
+
+use crate::planner::RoomMemory;
+use crate::screep_states::CreepMemory;
+use screeps::{
+    ConstructionSite, Creep, Direction, ErrorCode, LocalCostMatrix, LocalRoomTerrain, ObjectId,
+    Part, Position, RawObjectId, Resource, ResourceType, RoomName, RoomXY, Source,
+    StructureController, StructureLink, StructureTower, StructureType,
+};
+use std::rc::Rc;
+
+/// Result of an action (harvest, transfer, etc.) issued by a creep or structure
+pub type ActionResult = Result<(), ErrorCode>;
+
+/// Everything the bot can see and do in the game for a single tick
+pub trait World {
+    /// Current game tick
+    fn time(&self) -> u32;
+
+    /// All of our creeps
+    fn creeps(&self) -> Vec<Rc<dyn CreepView + '_>>;
+
+    /// All visible rooms
+    fn rooms(&self) -> Vec<Rc<dyn RoomView + '_>>;
+
+    /// A visible room by name
+    fn room(&self, name: RoomName) -> Option<Rc<dyn RoomView + '_>>;
+
+    /// All of our spawns
+    fn spawns(&self) -> Vec<Rc<dyn SpawnView + '_>>;
+
+    /// Look up a source by id
+    fn source(&self, id: ObjectId<Source>) -> Option<SourceInfo>;
+
+    /// Look up any structure by id
+    fn structure(&self, id: RawObjectId) -> Option<StructureInfo>;
+
+    /// Look up a construction site by id
+    fn construction_site(&self, id: ObjectId<ConstructionSite>) -> Option<SiteInfo>;
+
+    /// Look up a pile of dropped resources by id
+    fn dropped_resource(&self, id: ObjectId<Resource>) -> Option<DroppedInfo>;
+
+    /// Look up a tombstone or ruin by id
+    fn remains(&self, id: RawObjectId) -> Option<RemainsInfo>;
+
+    /// Look up a creep owned by another player by id
+    fn hostile(&self, id: ObjectId<Creep>) -> Option<HostileInfo>;
+
+    /// Send a notification to the player by email
+    fn notify(&self, message: &str);
+
+    /// Find a path from `from` to within `range` of `to`, not including `from`. Each room on the
+    /// way is searched with the cost matrix given for it, or just its terrain if there is none.
+    /// Returns None if there is no complete path
+    fn find_path(
+        &self,
+        from: Position,
+        to: Position,
+        range: u32,
+        matrix: &mut dyn FnMut(RoomName) -> Option<LocalCostMatrix>,
+    ) -> Option<Vec<Position>>;
+
+    /// All of our creeps in the given room
+    fn creeps_in_room(&self, room: RoomName) -> Vec<Rc<dyn CreepView + '_>> {
+        self.creeps()
+            .into_iter()
+            .filter(|creep| creep.pos().room_name() == room)
+            .collect()
+    }
+}
+
+/// A single visible room
+pub trait RoomView {
+    fn name(&self) -> RoomName;
+
+    fn energy_available(&self) -> u32;
+
+    fn energy_capacity_available(&self) -> u32;
+
+    /// All structures in the room, including the controller
+    fn structures(&self) -> Vec<StructureInfo>;
+
+    /// All sources in the room, including ones that are currently empty
+    fn sources(&self) -> Vec<SourceInfo>;
+
+    fn construction_sites(&self) -> Vec<SiteInfo>;
+
+    /// Resources lying on the ground
+    fn dropped_resources(&self) -> Vec<DroppedInfo>;
+
+    /// Tombstones and ruins in the room
+    fn remains(&self) -> Vec<RemainsInfo>;
+
+    fn controller(&self) -> Option<ControllerInfo>;
+
+    fn terrain(&self) -> LocalRoomTerrain;
+
+    /// Creeps in the room owned by other players
+    fn hostile_creeps(&self) -> Vec<HostileInfo>;
+
+    /// Our towers in the room
+    fn towers(&self) -> Vec<Rc<dyn TowerView + '_>>;
+
+    /// Our links in the room
+    fn links(&self) -> Vec<Rc<dyn LinkView + '_>>;
+
+    /// Activate safe mode on the room's controller
+    fn activate_safe_mode(&self) -> ActionResult;
+
+    fn memory(&self) -> RoomMemory;
+
+    fn set_memory(&self, memory: RoomMemory);
+
+    /// Place a construction site for the given structure type
+    fn create_construction_site(&self, xy: RoomXY, structure_type: StructureType) -> ActionResult;
+}
+
+/// One of our creeps, along with the actions it can take
+pub trait CreepView {
+    fn id(&self) -> ObjectId<Creep>;
+
+    fn name(&self) -> String;
+
+    fn pos(&self) -> Position;
+
+    fn body(&self) -> Vec<Part>;
+
+    fn hits(&self) -> u32;
+
+    fn hits_max(&self) -> u32;
+
+    /// The creep can't move until this is back to 0
+    fn fatigue(&self) -> u32;
+
+    /// None while the creep is still spawning
+    fn ticks_to_live(&self) -> Option<u32>;
+
+    fn store(&self) -> StoreInfo;
+
+    fn memory(&self) -> CreepMemory;
+
+    fn set_memory(&self, memory: CreepMemory);
+
+    fn say(&self, message: &str);
+
+    /// Step onto the neighbouring tile in the given direction
+    fn move_direction(&self, direction: Direction) -> ActionResult;
+
+    fn harvest(&self, source: ObjectId<Source>) -> ActionResult;
+
+    fn transfer(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult;
+
+    /// Withdraw from a structure, tombstone or ruin
+    fn withdraw(
+        &self,
+        target: RawObjectId,
+        resource: ResourceType,
+        amount: Option<u32>,
+    ) -> ActionResult;
+
+    /// Pick up a pile of dropped resources
+    fn pickup(&self, resource: ObjectId<Resource>) -> ActionResult;
+
+    fn build(&self, site: ObjectId<ConstructionSite>) -> ActionResult;
+
+    fn repair(&self, structure: RawObjectId) -> ActionResult;
+
+    fn upgrade_controller(&self, controller: ObjectId<StructureController>) -> ActionResult;
+
+    /// Melee attack a hostile creep
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult;
+
+    fn ranged_attack(&self, target: ObjectId<Creep>) -> ActionResult;
+
+    /// Heal one of our creeps, including this one
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult;
+}
+
+/// One of our spawns
+pub trait SpawnView {
+    fn name(&self) -> String;
+
+    fn room_name(&self) -> RoomName;
+
+    fn pos(&self) -> Position;
+
+    /// Whether the spawn is busy spawning a creep
+    fn is_spawning(&self) -> bool;
+
+    fn spawn_creep(&self, body: &[Part], name: &str, memory: CreepMemory) -> ActionResult;
+
+    /// Kill one of our creeps next to the spawn, getting back some of the energy it cost
+    fn recycle_creep(&self, creep: ObjectId<Creep>) -> ActionResult;
+}
+
+/// One of our towers
+pub trait TowerView {
+    fn id(&self) -> ObjectId<StructureTower>;
+
+    fn pos(&self) -> Position;
+
+    fn store(&self) -> StoreInfo;
+
+    fn attack(&self, target: ObjectId<Creep>) -> ActionResult;
+
+    fn heal(&self, target: ObjectId<Creep>) -> ActionResult;
+
+    fn repair(&self, target: RawObjectId) -> ActionResult;
+}
+
+/// One of our links
+pub trait LinkView {
+    fn id(&self) -> ObjectId<StructureLink>;
+
+    fn pos(&self) -> Position;
+
+    fn store(&self) -> StoreInfo;
+
+    /// The link can't send energy until this is back to 0
+    fn cooldown(&self) -> u32;
+
+    /// Send energy to another link in the same room, as much as fits if the amount is None
+    fn transfer_energy(&self, target: ObjectId<StructureLink>, amount: Option<u32>)
+        -> ActionResult;
+}
@@ -0,0 +1,63 @@
+// Stand-in for the game's PathFinder, so the mock can find paths natively. Costs follow the same
+// rules: a non-zero cost matrix value overrides the terrain, and 255 can't be walked on. Paths
+// never leave the room they start in
+use crate::movement::{BLOCKED, PLAIN_COST, SWAMP_COST};
+use screeps::{LocalCostMatrix, LocalRoomTerrain, Position, RoomXY, Terrain};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Cheapest path from `from` to within `range` of `to`, not including the starting tile
+pub fn search_room(
+    from: Position,
+    to: Position,
+    range: u32,
+    terrain: &LocalRoomTerrain,
+    matrix: Option<&LocalCostMatrix>,
+) -> Option<Vec<Position>> {
+    if from.room_name() != to.room_name() {
+        return None;
+    }
+    let room = from.room_name();
+    let cost = |xy: RoomXY| {
+        let cost = matrix.map_or(0, |matrix| matrix.get(xy));
+        match (cost, terrain.get_xy(xy)) {
+            (BLOCKED, _) | (0, Terrain::Wall) => None,
+            (0, Terrain::Swamp) => Some(SWAMP_COST as u32),
+            (0, _) => Some(PLAIN_COST as u32),
+            (cost, _) => Some(cost as u32),
+        }
+    };
+
+    let mut distances: HashMap<RoomXY, u32> = HashMap::from([(from.xy(), 0)]);
+    let mut came_from: HashMap<RoomXY, RoomXY> = HashMap::new();
+    // RoomXY isn't ordered, so the queue holds plain coordinates
+    let mut open = BinaryHeap::from([Reverse((0, from.x().u8(), from.y().u8()))]);
+    while let Some(Reverse((distance, x, y))) = open.pop() {
+        let current = RoomXY::checked_new(x, y).ok()?;
+        if distances.get(&current).is_some_and(|best| *best < distance) {
+            continue;
+        }
+        if current.get_range_to(to.xy()) as u32 <= range {
+            let mut path = vec![];
+            let mut step = current;
+            while step != from.xy() {
+                path.push(Position::new(step.x, step.y, room));
+                step = came_from[&step];
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for next in current.neighbors() {
+            let Some(cost) = cost(next) else {
+                continue;
+            };
+            let distance = distance + cost;
+            if distances.get(&next).is_none_or(|best| distance < *best) {
+                distances.insert(next, distance);
+                came_from.insert(next, current);
+                open.push(Reverse((distance, next.x.u8(), next.y.u8())));
+            }
+        }
+    }
+    None
+}